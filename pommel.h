@@ -8,6 +8,9 @@
 typedef struct Pom Pom;
 /// An opaque type representing a bank of samples.
 typedef struct PomPCMBank PomPCMBank;
+/// An opaque, reference-counted handle to a synthesiser guarded by a lock.
+/// See the shared synthesisers section for its threading contract.
+typedef struct PomShared PomShared;
 
 /// A duration.
 ///
@@ -16,6 +19,9 @@ typedef struct PomDuration {
     uint64_t seconds;
     uint32_t nanoseconds;
 } PomDuration;
+/// The fields of a `PomDuration` that stands in for an infinite duration.
+#define POM_DURATION_INFINITE_SECONDS UINT64_MAX
+#define POM_DURATION_INFINITE_NANOSECONDS UINT32_MAX
 
 /// A waveform shape for an operator.
 typedef int PomWaveformType;
@@ -26,57 +32,266 @@ typedef int PomWaveformType;
 #define POM_WAVEFORM_TYPE_INVERTED_SAWTOOTH 4
 #define POM_WAVEFORM_TYPE_PCM 5
 #define POM_WAVEFORM_TYPE_CONSTANT 6
+#define POM_WAVEFORM_TYPE_NOISE 7
+/// A single-cycle wavetable, using `sample_id` like PCM waveforms.
+#define POM_WAVEFORM_TYPE_WAVETABLE 8
+/// Squeezes `nested.base` into the first `nested.amount` of the period.
+#define POM_WAVEFORM_TYPE_THIN 9
+/// Silences `nested.base` after the first `nested.amount` of the period.
+#define POM_WAVEFORM_TYPE_CUT 10
+/// The absolute value of `nested.base`.
+#define POM_WAVEFORM_TYPE_ABSOLUTE 11
+/// Soft-clips `nested.base` with `tanh(amount * x) / tanh(amount)`.
+#define POM_WAVEFORM_TYPE_SATURATE 12
+/// Crossfades from `mix.a` to `mix.b` by `mix.blend`.
+#define POM_WAVEFORM_TYPE_MIX 13
+/// Scales and shifts `offset.base` to `base * offset.gain + offset.bias`.
+#define POM_WAVEFORM_TYPE_OFFSET 14
+/// Hard-clips `clamp.base` to between `clamp.min` and `clamp.max`.
+#define POM_WAVEFORM_TYPE_CLAMP 15
 
 /// An identifier for a sample in a sample bank.
 typedef uint64_t PomSampleID;
 
+/// Noise settings for a waveform.
+///
+/// When `pink` is non-zero, the noise has a pink spectrum; otherwise, it is white.
+typedef struct PomWaveformNoise {
+    uint64_t seed;
+    int pink;
+} PomWaveformNoise;
+
+typedef struct PomWaveform PomWaveform;
+
+/// Settings for a waveform that wraps another waveform.
+///
+/// `base` is only read while creating an operator, so it can point to a local
+/// variable. Waveforms must not contain themselves.
+typedef struct PomWaveformNested {
+    const PomWaveform* base;
+    double amount;
+} PomWaveformNested;
+
+/// Settings for a waveform that blends two waveforms, producing
+/// `a * (1 - blend) + b * blend`. Like `PomWaveformNested`, `a` and `b` are
+/// only read while creating an operator.
+typedef struct PomWaveformMix {
+    const PomWaveform* a;
+    const PomWaveform* b;
+    double blend;
+} PomWaveformMix;
+
+/// Settings for a waveform that scales and shifts another waveform, producing
+/// `base * gain + bias`. Like `PomWaveformNested`, `base` is only read while
+/// creating an operator.
+typedef struct PomWaveformOffset {
+    const PomWaveform* base;
+    double gain;
+    double bias;
+} PomWaveformOffset;
+
+/// Settings for a waveform that hard-clips another waveform to between `min`
+/// and `max`, giving flat tops with a hard knee. The bounds are swapped if
+/// `min` is greater than `max`, and NaN bounds are ignored. Like
+/// `PomWaveformNested`, `base` is only read while creating an operator.
+typedef struct PomWaveformClamp {
+    const PomWaveform* base;
+    double min;
+    double max;
+} PomWaveformClamp;
+
 /// Waveform settings for an operator.
-typedef struct PomWaveform {
+struct PomWaveform {
     PomWaveformType type;
     union {
+        /// The fraction of each period a pulse wave is high for.
         double duty_cycle;
         double constant_offset;
         PomSampleID sample_id;
+        PomWaveformNoise noise;
+        PomWaveformNested nested;
+        PomWaveformMix mix;
+        PomWaveformOffset offset;
+        PomWaveformClamp clamp;
     };
-} PomWaveform;
+};
 
 /// An envelope for an operator.
 typedef struct PomEnvelope {
     PomDuration attack_time;
     double halving_rate;
+    double sustain_level;
     PomDuration release_time;
+    /// Non-zero to restart the attack from the sustain level whenever the
+    /// decay becomes inaudible while the note is held, for rhythmic swells.
+    int loop_envelope;
 } PomEnvelope;
 
 /// Modifiers that are applied to an operator.
 typedef struct PomModifiers {
     double frequency_multiplier;
+    /// Detune in cents, composed with `frequency_multiplier`.
+    double detune_cents;
     double volume_multiplier;
     double constant_phase_offset;
+    /// How much of the operator's previous output is fed back into its phase.
+    double feedback;
+    /// How strongly the envelope's speed follows the played frequency, relative
+    /// to `key_track_reference` in hertz. 0 disables key-tracking.
+    double key_track;
+    double key_track_reference;
+    /// A final multiplier on the operator's output, independent of the volume
+    /// it is played at. Usually 1.
+    double output_gain;
+    /// The exponent played volumes are raised to. 1 is linear, and higher
+    /// values make quiet notes quieter. Zero, negative and NaN curves are
+    /// treated as linear. Usually 1.
+    double velocity_curve;
 } PomModifiers;
 
+/// A resonant low-pass filter applied to the output of an operator.
+///
+/// The filter is only applied when `enabled` is non-zero. `resonance` is the Q
+/// factor, where roughly 0.707 gives a flat response.
+typedef struct PomFilter {
+    int enabled;
+    double cutoff_hz;
+    double resonance;
+} PomFilter;
+
 /// Settings for creating an operator.
 typedef struct PomOperatorSettings {
     PomWaveform waveform;
     PomEnvelope envelope;
     PomModifiers modifiers;
+    PomFilter filter;
+    /// The time constant of portamento when the frequency is changed with
+    /// `pom_set_frequency`. Zero jumps to new frequencies instantly.
+    PomDuration glide_time;
+    /// Non-zero to start each note at a phase chosen by `start_phase_seed`
+    /// instead of the beginning of the waveform, so that voices with different
+    /// seeds don't start in phase. The same seed always starts at the same
+    /// phase.
+    int random_start_phase;
+    uint64_t start_phase_seed;
+    /// Non-zero to silence the operator while its frequency is above half the
+    /// sample rate, where it would alias. The sample rate is inferred from the
+    /// time between samples, such as the sample interval of `pom_fill`.
+    int nyquist_mute;
+    /// The time constant of smoothing applied to the phase offset the operator
+    /// is modulated by, which hides zipper noise from sudden jumps in
+    /// modulation. Zero disables smoothing.
+    PomDuration phase_offset_smoothing;
 } PomOperatorSettings;
 
-/// Settings for creating an operator.
+/// How a PCM sample computes values between its samples.
+typedef int PomInterpolation;
+#define POM_INTERPOLATION_NEAREST 0
+#define POM_INTERPOLATION_LINEAR 1
+#define POM_INTERPOLATION_CUBIC 2
+
+/// Settings for adding a PCM sample.
 typedef struct PomPCMSampleSettings {
+    /// The sample rate the PCM data was recorded at.
+    double samples_per_second;
+    /// The number of samples in one period. The sample plays at its original
+    /// speed when played at `samples_per_second / samples_per_period` hertz.
     double samples_per_period;
+    /// Where the loop starts, in periods from the start of the data.
     PomDuration loop_point;
     PomDuration loop_duration;
+    PomInterpolation interpolation;
+    /// Where playback starts, in periods from the start of the data, skipping
+    /// any silence or pre-roll before it.
+    PomDuration start_offset;
+    /// Non-zero to play the data backwards. The start offset and loop region
+    /// are then measured from the end of the data.
+    int reverse;
+    /// How long before the end of the loop to start blending in the audio
+    /// leading up to the loop point, hiding clicks at the seam. Zero disables
+    /// crossfading.
+    PomDuration crossfade;
 } PomPCMSampleSettings;
 
 /// An algorithm for a combinator.
 typedef int PomCombinatorType;
 #define POM_COMBINATOR_TYPE_SUM 0
 #define POM_COMBINATOR_TYPE_MODULATE 1
+#define POM_COMBINATOR_TYPE_MULTIPLY 2
+#define POM_COMBINATOR_TYPE_MIN 3
+#define POM_COMBINATOR_TYPE_MAX 4
+#define POM_COMBINATOR_TYPE_AVERAGE 5
+/// Weights can only be given with `pom_create_weighted_sum`; otherwise, every
+/// weight is 1.
+#define POM_COMBINATOR_TYPE_WEIGHTED_SUM 6
+
+/// The kind of a scheduled event.
+typedef int PomEventType;
+#define POM_EVENT_TYPE_PLAY 0
+#define POM_EVENT_TYPE_RELEASE 1
+#define POM_EVENT_TYPE_CUT 2
+#define POM_EVENT_TYPE_SET_FREQUENCY 3
+
+/// An event for a scheduled synthesiser. `frequency` is used by play and
+/// set frequency events, and `volume` by play events.
+typedef struct PomEvent {
+    PomEventType type;
+    double frequency;
+    double volume;
+} PomEvent;
+
+/// The kind of a stacker instruction.
+typedef int PomStackInstructionType;
+#define POM_STACK_INSTRUCTION_TYPE_CONSTANT 0
+#define POM_STACK_INSTRUCTION_TYPE_INPUT_PHASE_OFFSET 1
+#define POM_STACK_INSTRUCTION_TYPE_SAMPLE 2
+#define POM_STACK_INSTRUCTION_TYPE_ADD 3
+#define POM_STACK_INSTRUCTION_TYPE_SUB 4
+#define POM_STACK_INSTRUCTION_TYPE_MUL 5
+#define POM_STACK_INSTRUCTION_TYPE_NEG 6
+#define POM_STACK_INSTRUCTION_TYPE_DUPE 7
+#define POM_STACK_INSTRUCTION_TYPE_SWAP 8
+#define POM_STACK_INSTRUCTION_TYPE_ROT 9
+#define POM_STACK_INSTRUCTION_TYPE_SELECT_IF 10
+
+/// An instruction of a stacker. `constant` is used by constant instructions and
+/// as the threshold of select-if instructions, and `operator_index` by sample
+/// instructions; both are 0 otherwise.
+typedef struct PomStackInstruction {
+    PomStackInstructionType type;
+    double constant;
+    uint64_t operator_index;
+} PomStackInstruction;
 
 /// A result type.
 typedef int PomResult;
 #define POM_SUCCESS 0
 #define POM_FAIL_INVALID_INPUT 1
+/// A required pointer was null. Functions that don't return a result do
+/// nothing when given null pointers, and return 0 if they return a value.
+#define POM_FAIL_NULL_POINTER 2
+/// A waveform, or a waveform it wraps, has an unknown type.
+#define POM_FAIL_UNKNOWN_WAVEFORM 3
+#define POM_FAIL_UNKNOWN_SAMPLE_FORMAT 4
+/// No sample with the given identifier is in the PCM bank.
+#define POM_FAIL_SAMPLE_NOT_FOUND 5
+/// There wasn't enough memory, such as to copy PCM data.
+#define POM_FAIL_ALLOCATION_FAILED 6
+/// An internal error occurred, which is a bug in Pommel. Functions that don't
+/// return a result do nothing, or return 0 if they return a value.
+#define POM_FAIL_INTERNAL_ERROR 7
+/// A sample identifier is in both PCM banks being merged by
+/// `pom_merge_pcm_banks` with `POM_CONFLICT_POLICY_ERROR`.
+#define POM_FAIL_SAMPLE_CONFLICT 8
+
+/// How `pom_merge_pcm_banks` handles samples already in the destination bank.
+typedef int PomConflictPolicy;
+/// Replaces the sample in the destination bank.
+#define POM_CONFLICT_POLICY_OVERWRITE 0
+/// Keeps the sample in the destination bank.
+#define POM_CONFLICT_POLICY_SKIP 1
+/// Fails with `POM_FAIL_SAMPLE_CONFLICT`, leaving the destination unchanged.
+#define POM_CONFLICT_POLICY_ERROR 2
 
 /// A type that represents a PCM sample format.
 typedef int PomSampleFormat;
@@ -86,11 +301,32 @@ typedef int PomSampleFormat;
 #define POM_SAMPLE_FORMAT_F32 3
 #define POM_SAMPLE_FORMAT_F64 4
 
+/// How `pom_fill_limited` keeps samples within -1 to 1 before converting them.
+typedef int PomLimiter;
+/// Passes samples through, so float formats can exceed -1 to 1.
+#define POM_LIMITER_NONE 0
+/// Hard-clips samples to -1 to 1.
+#define POM_LIMITER_BRICKWALL 1
+/// Smoothly compresses samples towards -1 and 1 with `tanh`, which also bends
+/// samples within range.
+#define POM_LIMITER_SOFT_CLIP 2
+
 // ---------- CREATION ----------
 
 /// Allocates a new operator. An operator is the most basic synthesiser; it
 /// simply produces a waveform.
 extern PomResult pom_create_operator(Pom** out, PomOperatorSettings settings);
+/// Creates a summation combinator of `voices` copies of an operator, such as
+/// for a supersaw. The outermost voices are detuned by `detune_cents`, and
+/// spread across the stereo field by `stereo_spread`, from 0 (none) to 1 (hard
+/// left and right).
+extern PomResult pom_create_unison(
+    Pom** out,
+    PomOperatorSettings settings,
+    uint64_t voices,
+    double detune_cents,
+    double stereo_spread
+);
 /// Creates a modulation combinator, which modulates the phase offset of the
 /// signal from `carrier` with the signal from `modulator`.
 extern PomResult
@@ -102,6 +338,85 @@ extern PomResult pom_create_summation(Pom** out, const Pom* a, const Pom* b);
 extern PomResult pom_create_combinator(
     Pom** out, const Pom* synths[], uint64_t synth_count, PomCombinatorType type
 );
+/// Creates a summation combinator that divides the sum by the number of synths
+/// currently producing output, so that it doesn't clip if none of them do.
+extern PomResult pom_create_normalized_sum(
+    Pom** out, const Pom* synths[], uint64_t synth_count
+);
+/// Creates a combinator that sums every synth multiplied by its weight.
+/// `synths` and `weights` must both hold `length` elements.
+extern PomResult pom_create_weighted_sum(
+    Pom** out, const Pom* synths[], const double weights[], uint64_t length
+);
+/// Creates a scheduled synthesiser, which applies events given to
+/// `pom_schedule_event` to a copy of `synth` when sampling reaches their time.
+extern PomResult pom_create_scheduled(Pom** out, const Pom* synth);
+/// Creates a hard sync synthesiser, which resets the phase of `slave` whenever
+/// `master` completes a period. Only `slave` is heard.
+extern PomResult
+pom_create_sync(Pom** out, const Pom* master, const Pom* slave);
+/// Creates a stereo synthesiser that places a copy of `synth` in the stereo
+/// field using equal-power panning. `pan` ranges from -1 (hard left) to 1
+/// (hard right).
+extern PomResult pom_create_panned(Pom** out, const Pom* synth, double pan);
+/// Creates a stereo synthesiser that places a copy of `synth` in the stereo
+/// field, using the same equal-power panning as `pom_create_panned`. `pan`
+/// ranges from -1 (hard left) to 1 (hard right), and keeps the total power of
+/// mono signals constant.
+/// `width` scales the difference between the sides of stereo synthesisers,
+/// from 0 (mono) through 1 (unchanged); it has no effect on mono ones.
+extern PomResult
+pom_create_stereo(Pom** out, const Pom* synth, double pan, double width);
+/// Creates a chorus, which mixes a copy of `synth` with its own output delayed
+/// by a time swept between `delay - depth` and `delay + depth` by a sine LFO at
+/// `rate` hertz. `mix` ranges from 0 (only the dry signal) to 1 (only the
+/// delayed signal). Delays are measured in samples using the interval between
+/// the last two sampled times, so synthesisers should be sampled at a steady
+/// rate.
+extern PomResult pom_create_chorus(
+    Pom** out,
+    const Pom* synth,
+    PomDuration delay,
+    PomDuration depth,
+    double rate,
+    double mix
+);
+/// Creates a phaser, which sweeps notches through the spectrum of a copy of
+/// `synth` by mixing it with itself passed through `stages` allpass filters
+/// (at most 64). A sine LFO at `rate` hertz sweeps the filters' corner
+/// frequency `depth` octaves either side of `frequency` hertz. Every two
+/// stages add a notch. `feedback` ranges from -1 to 1, and sharpens the
+/// notches. `mix` ranges from 0 (only the dry signal) to 1 (only the filtered
+/// signal), and gives the deepest notches at 0.5. Like a chorus, it should be
+/// sampled at a steady rate.
+extern PomResult pom_create_phaser(
+    Pom** out,
+    const Pom* synth,
+    uint32_t stages,
+    double frequency,
+    double depth,
+    double rate,
+    double feedback,
+    double mix
+);
+/// Creates a synthesiser that reduces the aliasing of a copy of `synth`, such
+/// as from saturated waveforms or feedback, by sampling it `oversample` times
+/// per sample and low-pass filtering the result. 0 and 1 disable oversampling.
+/// When oversampling, the output is delayed by 16 samples. Like a chorus, it
+/// should be sampled at a steady rate.
+extern PomResult
+pom_create_oversampled(Pom** out, const Pom* synth, uint32_t oversample);
+/// Creates a synthesiser that gates a copy of `carrier` on and off in steps of
+/// `step` global time. Bit `i % 8` of byte `i / 8` of `pattern` is set if
+/// step `i` is heard; the `pattern_length` steps repeat. An empty pattern or a
+/// zero step leaves the carrier ungated.
+extern PomResult pom_create_gate(
+    Pom** out,
+    const Pom* carrier,
+    const uint8_t pattern[],
+    uint64_t pattern_length,
+    PomDuration step
+);
 /// Clones an existing synthesiser.
 extern PomResult pom_clone_synth(Pom** out, const Pom* source);
 
@@ -114,13 +429,97 @@ extern PomResult pom_clone_pcm_bank(PomPCMBank** out, const PomPCMBank* source);
 
 /// Marks a synthesiser as playing at its current position.
 extern void pom_play(Pom* synth, double frequency, double volume);
+/// Schedules a synthesiser to start playing at the given global time.
+/// Sampling before that time produces silence.
+extern void pom_set_start(Pom* synth, PomDuration when);
+/// Schedules an event on a synthesiser created by `pom_create_scheduled`, to
+/// be applied once sampling reaches `when`, in global time. Play events start
+/// exactly at `when`.
+extern PomResult
+pom_schedule_event(Pom* synth, PomDuration when, PomEvent event);
+/// Replaces the pattern and step of a synthesiser created by
+/// `pom_create_gate`, in the same format. Fails with `POM_FAIL_INVALID_INPUT`
+/// if the synthesiser is not a gate.
+extern PomResult pom_set_gate_pattern(
+    Pom* synth,
+    const uint8_t pattern[],
+    uint64_t pattern_length,
+    PomDuration step
+);
+/// Adds a copy of `child` to the end of a combinator, such as to add a partial
+/// to an additive synthesiser. Fails with `POM_FAIL_INVALID_INPUT` if
+/// `combinator` was not created as a combinator.
+extern PomResult pom_combinator_push(Pom* combinator, const Pom* child);
+/// Removes and destroys the synthesiser at `index` of a combinator, along with
+/// its weight if it has one. Fails with `POM_FAIL_INVALID_INPUT` if
+/// `combinator` was not created as a combinator, or `index` is out of range.
+extern PomResult pom_combinator_remove(Pom* combinator, uint64_t index);
+/// Mutes or unmutes an operator without removing it, such as for auditioning
+/// the rest of a patch. A muted operator keeps running but outputs silence,
+/// except in a modulation chain or stacker, where it passes the phase offset it
+/// is given through unchanged; muting the carrier of a chain outputs the
+/// modulation itself. `index` selects the operator: 0 for an operator, or the
+/// index of an operator in a stacker or combinator. Fails with
+/// `POM_FAIL_INVALID_INPUT` if there is no operator at `index`.
+extern PomResult pom_set_operator_muted(Pom* synth, uint64_t index, int muted);
+/// Changes the frequency of a synthesiser without restarting its envelope.
+extern void pom_set_frequency(Pom* synth, double frequency);
 /// Marks a synthesiser as releasing at its current position.
 extern void pom_release(Pom* synth);
 /// Hard stops a synthesiser.
 extern void pom_cut(Pom* synth);
+/// Returns 1 if a synthesiser is playing or scheduled to play, or 0 once it has
+/// been cut or has finished releasing, as of the last time it was sampled.
+extern int pom_is_active(const Pom* synth);
+/// Restarts the waveforms of a synthesiser from the beginning of their period,
+/// without affecting envelopes. Combinators and stackers reset every operator
+/// they contain. Useful for deterministic renders.
+extern void pom_reset_phase(Pom* synth);
+/// Writes the phase of a synthesiser's oscillator, within [0, 1), to `out`.
+/// Fails with `POM_FAIL_INVALID_INPUT` if the synthesiser doesn't have a
+/// single oscillator, such as a combinator.
+extern PomResult pom_get_phase(const Pom* synth, double* out);
+/// Returns the volume a synthesiser would be enveloped to at `global_time`,
+/// without sampling it or advancing its oscillators, for metering. Combinators
+/// report their loudest synthesiser. Returns 0 if the synthesiser is off.
+extern double pom_current_volume(const Pom* synth, PomDuration global_time);
+/// Returns how long until a synthesiser goes silent, as of the last time it was
+/// sampled. A zero duration means it is already off. If it is sustaining
+/// indefinitely, `POM_DURATION_INFINITE_SECONDS` and
+/// `POM_DURATION_INFINITE_NANOSECONDS` are returned.
+extern PomDuration pom_remaining(const Pom* synth);
+/// Writes the number of operators in a stacker to `out`. Stackers can be
+/// received from `pom_deserialize_synth`. Fails with `POM_FAIL_INVALID_INPUT`
+/// if the synthesiser is not a stacker.
+extern PomResult pom_stacker_operator_count(const Pom* synth, uint64_t* out);
+/// Writes the number of instructions in a stacker to `out`. Fails with
+/// `POM_FAIL_INVALID_INPUT` if the synthesiser is not a stacker.
+extern PomResult pom_stacker_instruction_count(const Pom* synth, uint64_t* out);
+/// Copies the settings of the operator at `index` in a stacker to `out`.
+/// Waveforms that wrap other waveforms keep their type and settings, but the
+/// pointers to the waveforms they wrap are null. Envelope curves aren't copied.
+/// Fails with `POM_FAIL_INVALID_INPUT` if the synthesiser is not a stacker,
+/// `index` is out of range, or the operator has a breakpoint envelope, and
+/// with `POM_FAIL_UNKNOWN_WAVEFORM` if its waveform has no type in this header.
+extern PomResult pom_stacker_get_operator(
+    const Pom* synth,
+    uint64_t index,
+    PomOperatorSettings* out
+);
+/// Copies the instruction at `index` in a stacker to `out`. Fails with
+/// `POM_FAIL_INVALID_INPUT` if the synthesiser is not a stacker or `index` is
+/// out of range.
+extern PomResult pom_stacker_get_instruction(
+    const Pom* synth,
+    uint64_t index,
+    PomStackInstruction* out
+);
 
-/// Adds a PCM sample to a PCM bank.
-extern void pom_add_pcm(
+/// Adds a PCM sample to a PCM bank. The data is copied, but kept in its
+/// original sample format, and converted as it is played. Fails with
+/// `POM_FAIL_INVALID_INPUT` if the loop region extends past the end of the
+/// data, which would otherwise play silence.
+extern PomResult pom_add_pcm_sample(
     PomPCMBank* bank,
     void* pcm_data,
     uint64_t pcm_length,
@@ -128,6 +527,42 @@ extern void pom_add_pcm(
     PomSampleID identifier,
     PomPCMSampleSettings pcm_sample_settings
 );
+/// Moves the loop region of a sample already in a PCM bank, in periods from
+/// the start of its data, without copying the data again. Fails with
+/// `POM_FAIL_SAMPLE_NOT_FOUND` if there is no such sample, or with
+/// `POM_FAIL_INVALID_INPUT` if the loop region extends past the end of the
+/// data, in which case the sample is unchanged.
+extern PomResult pom_set_pcm_loop(
+    PomPCMBank* bank,
+    PomSampleID identifier,
+    PomDuration loop_point,
+    PomDuration loop_duration
+);
+/// Copies every sample of `source` into `destination`, such as to combine
+/// instrument packs. Samples with identifiers already in `destination` are
+/// handled by `policy`. Fails with `POM_FAIL_INVALID_INPUT` if the policy is
+/// unknown.
+extern PomResult pom_merge_pcm_banks(
+    PomPCMBank* destination,
+    const PomPCMBank* source,
+    PomConflictPolicy policy
+);
+/// Removes the sample with the given identifier from a PCM bank, freeing its
+/// data. Fails with `POM_FAIL_SAMPLE_NOT_FOUND` if there is no such sample.
+extern PomResult pom_remove_pcm_sample(PomPCMBank* bank, PomSampleID identifier);
+/// Removes every sample from a PCM bank, freeing their data.
+extern void pom_clear_pcm_bank(PomPCMBank* bank);
+/// Writes the number of PCM samples in the sample with the given identifier to
+/// `out`. Fails with `POM_FAIL_SAMPLE_NOT_FOUND` if there is no such sample.
+extern PomResult pom_pcm_sample_length(
+    const PomPCMBank* bank, PomSampleID identifier, uint64_t* out
+);
+/// Writes the length of the sample with the given identifier to `out`, in
+/// periods like its start offset and loop region. Fails with
+/// `POM_FAIL_SAMPLE_NOT_FOUND` if there is no such sample.
+extern PomResult pom_pcm_sample_duration(
+    const PomPCMBank* bank, PomSampleID identifier, PomDuration* out
+);
 
 // ---------- SAMPLING ----------
 
@@ -138,8 +573,28 @@ extern double pom_sample(
     PomDuration global_time,
     double input_phase_offset
 );
+/// Samples a synthesiser once like `pom_sample`, but tells silence apart from
+/// the synthesiser being off. Returns 1 and writes the sample to `out` if the
+/// synthesiser produced one, or returns 0 and leaves `out` unchanged if it is
+/// off, such as once a note has finished. `out` can be null.
+extern int pom_sample_opt(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration global_time,
+    double input_phase_offset,
+    double* out
+);
+/// Evaluates a waveform at a phase in cycles, without any synthesiser state,
+/// such as for drawing previews. Returns 0 if the waveform is invalid.
+extern double pom_sample_waveform(
+    PomWaveform waveform, const PomPCMBank* bank, double phase
+);
 /// Samples a synthesiser many times, filling an audio array.
 /// The byte size of the data is `length` times the size of the sample format.
+/// Sample `i` is taken at exactly `pom_advance_time(start_time, sample_interval,
+/// i)`, so filling consecutive blocks, each starting where
+/// `pom_advance_time` says the last one ended, produces bit-identical output to
+/// filling one large buffer.
 extern PomResult pom_fill(
     Pom* synth,
     const PomPCMBank* bank,
@@ -150,6 +605,202 @@ extern PomResult pom_fill(
     PomSampleFormat sample_format,
     double constant_phase_offset
 );
+/// Samples a synthesiser many times like `pom_fill`, but adds triangular dither
+/// of up to one step before rounding to integer sample formats. This turns the
+/// distortion of quantising quiet signals into a constant noise floor. The
+/// dither is generated from `dither_seed`, so renders are reproducible; use a
+/// different seed for each block. Float formats are not dithered.
+extern PomResult pom_fill_dithered(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset,
+    uint64_t dither_seed
+);
+/// Samples a synthesiser many times like `pom_fill`, but passes each sample
+/// through `limiter` before converting it to `sample_format`. This keeps float
+/// formats within -1 to 1 even if the synthesiser produces runaway values, such
+/// as from feedback. Integer formats are clamped to their range regardless. NaN
+/// samples become 0 unless `limiter` is `POM_LIMITER_NONE`. Fails with
+/// `POM_FAIL_INVALID_INPUT` if `limiter` is unknown.
+extern PomResult pom_fill_limited(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset,
+    PomLimiter limiter
+);
+/// Samples a synthesiser created by `pom_create_oversampled` many times like
+/// `pom_fill`, first changing its oversampling factor to `oversample`. The
+/// state of its filter carries over between blocks, unless the factor changes.
+/// Fails with `POM_FAIL_INVALID_INPUT` if the synthesiser wasn't created by
+/// `pom_create_oversampled`.
+extern PomResult pom_fill_oversampled(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset,
+    uint32_t oversample
+);
+/// Samples a synthesiser many times like `pom_fill`, and measures the block as
+/// it is filled, for metering. The peak absolute sample is written to `peak`,
+/// and the root mean square of the samples to `rms`. Both are measured before
+/// conversion to `sample_format`, so they are the same for every format. Either
+/// can be null if it isn't needed.
+extern PomResult pom_fill_metered(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset,
+    double* peak,
+    double* rms
+);
+/// Samples a synthesiser many times, filling an interleaved multichannel audio
+/// array. Each frame's sample is duplicated across all `channels`, except for
+/// stereo synthesisers with more than one channel, which write their left
+/// channel to even channels and their right channel to odd channels.
+/// The byte size of the data is `frame_count * channels` times the size of the
+/// sample format.
+extern PomResult pom_fill_interleaved(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t frame_count,
+    uint32_t channels,
+    PomSampleFormat sample_format,
+    double constant_phase_offset
+);
+/// Samples a synthesiser many times like `pom_fill`, but sets its frequency to
+/// `frequencies[i]` before producing sample `i`, for glides and pitch
+/// envelopes. `frequencies` must hold `length` values, or be null to behave
+/// exactly like `pom_fill`.
+extern PomResult pom_fill_automated(
+    Pom* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    const double* frequencies,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset
+);
+
+// ---------- CONVERSION ----------
+
+/// Returns the duration of one period at the given frequency. Returns a zero
+/// duration if `frequency` is not positive.
+extern PomDuration pom_frequency_to_interval(double frequency);
+/// Returns the start time of the block following a block of `length` samples
+/// starting at `global_time`. This is exact, unlike adding up durations in
+/// floating point, so consecutive blocks line up without drift.
+extern PomDuration
+pom_advance_time(PomDuration global_time, PomDuration sample_interval, uint64_t length);
+/// Converts a MIDI note number to a frequency in hertz, with A4 (note 69) at
+/// 440Hz.
+extern double pom_midi_to_frequency(uint8_t note);
+/// Converts a MIDI note number to a frequency in hertz, with A4 (note 69) at
+/// `a4_frequency`.
+extern double pom_midi_to_frequency_tuned(uint8_t note, double a4_frequency);
+/// Converts a frequency in hertz to a fractional MIDI note number, with A4 at
+/// 440Hz.
+extern double pom_frequency_to_midi(double frequency);
+
+/// Maps `x` from `input_min..input_max` to `output_min..output_max`, rounds,
+/// then clamps to the output range. This is how samples are converted to
+/// integer formats when filling.
+extern double pom_quantise(
+    double x,
+    double input_min,
+    double input_max,
+    double output_min,
+    double output_max
+);
+/// Maps `x` from `min..max` to -1..1, without clamping. This is how integer
+/// PCM samples are converted when played.
+extern double pom_normalise(double x, double min, double max);
+
+// ---------- SERIALISATION ----------
+
+/// Encodes a synthesiser into a newly allocated buffer, whose length is written
+/// to `out_length`. The buffer must be destroyed with `pom_destroy_buffer`.
+///
+/// Operators, stackers, and combinators of those can be serialised; other
+/// synthesisers produce `POM_FAIL_INVALID_INPUT`.
+extern PomResult pom_serialize_synth(
+    const Pom* synth, uint8_t** out_buffer, uint64_t* out_length
+);
+/// Decodes a synthesiser from bytes produced by `pom_serialize_synth`.
+extern PomResult
+pom_deserialize_synth(Pom** out, const uint8_t* bytes, uint64_t length);
+
+// ---------- SHARED SYNTHESISERS ----------
+//
+// The plain `Pom*` functions are not thread-safe; a `Pom*` must only be used
+// by one thread at a time. A `PomShared*` wraps a synthesiser in a lock that
+// every `_locked` function takes internally, so one thread may sample or fill
+// while another plays, releases, or cuts. Each function blocks until the lock
+// is available; `pom_fill_locked` holds it for the entire fill.
+//
+// Handles are reference-counted. `pom_clone_shared_handle` produces another
+// handle to the same synthesiser (e.g. one per thread), and each handle must
+// be destroyed with `pom_destroy_shared` exactly once. Sample banks are not
+// locked, so a bank must not be modified while any thread samples from it.
+
+/// Allocates a new operator behind a shared handle.
+extern PomResult
+pom_create_operator_shared(PomShared** out, PomOperatorSettings settings);
+/// Clones an existing synthesiser into a new shared handle.
+extern PomResult pom_share_synth(PomShared** out, const Pom* source);
+/// Creates another handle to the same shared synthesiser.
+extern PomResult
+pom_clone_shared_handle(PomShared** out, const PomShared* source);
+/// Marks a shared synthesiser as playing at its current position.
+extern void
+pom_play_locked(const PomShared* synth, double frequency, double volume);
+/// Marks a shared synthesiser as releasing at its current position.
+extern void pom_release_locked(const PomShared* synth);
+/// Hard stops a shared synthesiser.
+extern void pom_cut_locked(const PomShared* synth);
+/// Samples a shared synthesiser once, stepping it to the given current time.
+extern double pom_sample_locked(
+    const PomShared* synth,
+    const PomPCMBank* bank,
+    PomDuration global_time,
+    double input_phase_offset
+);
+/// Samples a shared synthesiser many times, filling an audio array.
+extern PomResult pom_fill_locked(
+    const PomShared* synth,
+    const PomPCMBank* bank,
+    PomDuration start_time,
+    PomDuration sample_interval,
+    void* data,
+    uint64_t length,
+    PomSampleFormat sample_format,
+    double constant_phase_offset
+);
+/// Destroys a handle to a shared synthesiser. The synthesiser itself is
+/// destroyed along with its last handle.
+extern void pom_destroy_shared(const PomShared* synth);
 
 // ---------- CLEANUP ----------
 
@@ -157,3 +808,5 @@ extern PomResult pom_fill(
 extern void pom_destroy_synth(Pom* object);
 /// Destroys a PCM bank.
 extern void pom_destroy_pcm_bank(PomPCMBank* bank);
+/// Destroys a buffer allocated by Pommel.
+extern void pom_destroy_buffer(uint8_t* buffer, uint64_t length);
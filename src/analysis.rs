@@ -0,0 +1,103 @@
+//! Frequency-domain analysis, for checking the harmonic content of synthesised audio in tests.
+//! Enabled with the `analysis` feature.
+
+use alloc::{vec, vec::Vec};
+use core::f64::consts::TAU;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::math::Float;
+
+/// Computes the amplitude spectrum of `samples` with a radix-2 FFT.
+///
+/// The samples are zero-padded to the next power of two, `n`, and the output has `n / 2 + 1` bins,
+/// where bin `k` is at `k / n` times the sample rate. Magnitudes are scaled to the amplitude of the component,
+/// so a sine of amplitude 1 whose frequency lands exactly on a bin produces 1 in that bin, and a DC signal produces its value in bin 0.
+pub fn magnitude_spectrum(samples: &[f64]) -> Vec<f64> {
+    if samples.is_empty() {
+        return vec![];
+    }
+    let length = samples.len().next_power_of_two();
+    let mut bins: Vec<(f64, f64)> = samples.iter().map(|&sample| (sample, 0.0)).collect();
+    bins.resize(length, (0.0, 0.0));
+    fft(&mut bins);
+    bins[..=length / 2]
+        .iter()
+        .enumerate()
+        .map(|(k, (re, im))| {
+            let magnitude = re.hypot(*im) / length as f64;
+            // every other bin also has a mirrored negative frequency holding the other half of its amplitude
+            if k == 0 || k == length / 2 {
+                magnitude
+            } else {
+                magnitude * 2.0
+            }
+        })
+        .collect()
+}
+
+/// An in-place, iterative Cooley-Tukey FFT over `(real, imaginary)` pairs. The length must be a power of two.
+fn fft(data: &mut [(f64, f64)]) {
+    let length = data.len();
+    // reorder into bit-reversed order, so each pass can combine adjacent halves
+    let mut j = 0;
+    for i in 1..length {
+        let mut bit = length >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= length {
+        let angle = -TAU / size as f64;
+        for start in (0..length).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (re, im) = data[start + k + size / 2];
+                let odd = (re * cos - im * sin, re * sin + im * cos);
+                let even = data[start + k];
+                data[start + k] = (even.0 + odd.0, even.1 + odd.1);
+                data[start + k + size / 2] = (even.0 - odd.0, even.1 - odd.1);
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_has_energy_in_one_bin() {
+        // 8 cycles over 256 samples, landing on bin 8
+        let sine: Vec<f64> = (0..256)
+            .map(|i| 0.75 * (TAU * 8.0 * i as f64 / 256.0).sin())
+            .collect();
+        let spectrum = magnitude_spectrum(&sine);
+        assert_eq!(spectrum.len(), 129);
+        for (bin, magnitude) in spectrum.iter().enumerate() {
+            let expected = if bin == 8 { 0.75 } else { 0.0 };
+            assert!(
+                (magnitude - expected).abs() < 1e-9,
+                "{magnitude} in bin {bin}"
+            );
+        }
+    }
+
+    #[test]
+    fn dc_has_energy_in_bin_zero() {
+        let spectrum = magnitude_spectrum(&[-0.5; 64]);
+        assert!((spectrum[0] - 0.5).abs() < 1e-12);
+        assert!(
+            spectrum[1..]
+                .iter()
+                .all(|magnitude| magnitude.abs() < 1e-12)
+        );
+        assert!(magnitude_spectrum(&[]).is_empty());
+    }
+}
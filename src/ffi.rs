@@ -1,8 +1,18 @@
-use std::{ffi::c_int, sync::LazyLock, time::Duration};
+use std::{
+    ffi::c_int,
+    panic::{self, AssertUnwindSafe},
+    prelude::rust_2024::*,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+    vec,
+};
 
 use crate::{
-    Combinator, CombinatorType, Envelope, Operator, OperatorModifiers, Pom, Sample, SampleBank,
-    SampleID, Waveform, time::NANOS_PER_SEC,
+    Chorus, Combinator, CombinatorType, ConflictPolicy, Curve, DecayCurve, Envelope, EnvelopeKind,
+    Event, Filter, Gate, HardSync, Interpolation, Operator, OperatorModifiers, Oversampled, Panned,
+    PcmData, Period, Phaser, Pom, Sample, SampleBank, SampleID, Scheduled, SerializedSynth,
+    StackInstruction, Stacker, Stereo, Waveform, normalise, pitch, random,
+    time::{self, NANOS_PER_SEC},
 };
 
 /// The `Pom` type used in FFI. Only one type of data is supported currently, and that is [`SampleBank`].
@@ -17,6 +27,27 @@ type PomOpaqueMut = *mut FFIPomBox;
 /// It is not a mistake that these are pointers to boxes; these are `leak`-ed `Box<FFIPomBox>`es.
 type PomOpaque = *const FFIPomBox;
 
+/// A synthesiser that can be handed to another thread.
+///
+/// [`Pom`] doesn't require [`Send`], so that synthesisers which are only ever used on one thread can hold
+/// thread-bound state. The FFI can only create synthesisers out of this crate's own types, which can be sent.
+pub struct SendPom(FFIPomBox);
+// SAFETY: every synthesiser reachable through FFI is built by this module from this crate's types,
+// whose leaves are checked to be `Send` below, and whose wrappers only hold other such synthesisers.
+unsafe impl Send for SendPom {}
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Operator>();
+    assert_send::<Stacker>();
+};
+
+/// A synthesiser guarded by a mutex, so that it can be used from multiple threads.
+type SharedPom = Mutex<SendPom>;
+/// The pointer type for shared synthesisers sent through FFI (`const PomShared*` in C).
+/// `PomShared` should be an opaque type on the other end.
+/// These are `into_raw`-ed `Arc<SharedPom>`s; every handle holds one strong reference.
+type PomSharedOpaque = *const SharedPom;
+
 /// The pointer type for sample banks sent through FFI (`PomSampleBank*` in C).
 /// `PomSampleBank` should be an opaque type on the other end.
 type PomPCMBankMut = *mut SampleBank;
@@ -32,6 +63,12 @@ pub struct PomDuration {
     nanoseconds: u32,
 }
 impl PomDuration {
+    /// Stands in for an infinite duration, which is not otherwise representable.
+    pub const INFINITE: Self = Self {
+        seconds: u64::MAX,
+        nanoseconds: u32::MAX,
+    };
+
     pub fn to_rust(&self) -> Duration {
         if self
             .seconds
@@ -56,12 +93,61 @@ impl From<Duration> for PomDuration {
     }
 }
 
+/// Noise settings for a [`PomWaveform`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PomWaveformNoise {
+    seed: u64,
+    pink: c_int,
+}
+
+/// Settings for a [`PomWaveform`] that wraps another waveform.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PomWaveformNested {
+    base: *const PomWaveform,
+    /// The `waveform_active_percent` of thin and cut waveforms, or the `drive` of saturated waveforms.
+    amount: f64,
+}
+
+/// Settings for a [`PomWaveform`] that blends two waveforms.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PomWaveformMix {
+    a: *const PomWaveform,
+    b: *const PomWaveform,
+    blend: f64,
+}
+
+/// Settings for a [`PomWaveform`] that scales and shifts another waveform.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PomWaveformOffset {
+    base: *const PomWaveform,
+    gain: f64,
+    bias: f64,
+}
+
+/// Settings for a [`PomWaveform`] that clamps another waveform.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PomWaveformClamp {
+    base: *const PomWaveform,
+    min: f64,
+    max: f64,
+}
+
 /// Data for a [`PomWaveform`].
 #[repr(C)]
 pub union PomWaveformData {
     constant_offset: f64,
     duty_cycle: f64,
     sample_id: SampleID,
+    noise: PomWaveformNoise,
+    nested: PomWaveformNested,
+    mix: PomWaveformMix,
+    offset: PomWaveformOffset,
+    clamp: PomWaveformClamp,
 }
 
 /// Waveform settings for an operator.
@@ -71,18 +157,178 @@ pub struct PomWaveform {
     data: PomWaveformData,
 }
 impl PomWaveform {
-    pub fn to_rust(&self) -> Option<Waveform> {
-        match self.ty {
-            0 => Some(Waveform::Sine),
-            1 => Some(Waveform::Pulse {
+    /// Fails with `UnknownWaveform` if the type of this waveform or a waveform it wraps isn't known,
+    /// or `NullPointer` if a wrapped waveform is null.
+    ///
+    /// SAFETY: if this waveform wraps another waveform, its `base` must be null or point to a valid waveform,
+    /// and so on for that waveform; the waveforms must form a tree.
+    pub unsafe fn to_rust(&self) -> Result<Waveform, PomResultCode> {
+        Ok(match self.ty {
+            0 => Waveform::Sine,
+            1 => Waveform::Pulse {
                 duty_cycle: unsafe { self.data.duty_cycle },
-            }),
-            2 => Some(Waveform::Triangle),
-            3 => Some(Waveform::Sawtooth),
-            4 => Some(Waveform::InvertedSawtooth),
-            5 => Some(Waveform::PCM(unsafe { self.data.sample_id })),
-            6 => Some(Waveform::Constant(unsafe { self.data.constant_offset })),
-            _ => None,
+            },
+            2 => Waveform::Triangle,
+            3 => Waveform::Sawtooth,
+            4 => Waveform::InvertedSawtooth,
+            5 => Waveform::PCM(unsafe { self.data.sample_id }),
+            6 => Waveform::Constant(unsafe { self.data.constant_offset }),
+            7 => {
+                let noise = unsafe { self.data.noise };
+                Waveform::Noise {
+                    seed: noise.seed,
+                    pink: noise.pink != 0,
+                }
+            }
+            8 => Waveform::Wavetable(unsafe { self.data.sample_id }),
+            9..=12 => {
+                let nested = unsafe { self.data.nested };
+                let base = unsafe { Self::nested_to_rust(nested.base) }?;
+                match self.ty {
+                    9 => Waveform::Thin {
+                        base,
+                        waveform_active_percent: nested.amount,
+                    },
+                    10 => Waveform::Cut {
+                        base,
+                        waveform_active_percent: nested.amount,
+                    },
+                    11 => Waveform::Absolute(base),
+                    _ => Waveform::Saturate {
+                        base,
+                        drive: nested.amount,
+                    },
+                }
+            }
+            13 => {
+                let mix = unsafe { self.data.mix };
+                Waveform::Mix {
+                    a: unsafe { Self::nested_to_rust(mix.a) }?,
+                    b: unsafe { Self::nested_to_rust(mix.b) }?,
+                    blend: mix.blend,
+                }
+            }
+            14 => {
+                let offset = unsafe { self.data.offset };
+                Waveform::Offset {
+                    base: unsafe { Self::nested_to_rust(offset.base) }?,
+                    gain: offset.gain,
+                    bias: offset.bias,
+                }
+            }
+            15 => {
+                let clamp = unsafe { self.data.clamp };
+                Waveform::Clamp {
+                    base: unsafe { Self::nested_to_rust(clamp.base) }?,
+                    min: clamp.min,
+                    max: clamp.max,
+                }
+            }
+            _ => return Err(PomResult::UnknownWaveform as PomResultCode),
+        })
+    }
+    /// Copies a waveform out to FFI. Waveforms that wrap other waveforms keep their type and settings,
+    /// but their wrapped waveforms are not copied, and are left as null.
+    ///
+    /// Fails with `UnknownWaveform` if the waveform has no FFI type.
+    pub fn from_rust(waveform: &Waveform) -> Result<Self, PomResultCode> {
+        let nested = |amount| PomWaveformData {
+            nested: PomWaveformNested {
+                base: std::ptr::null(),
+                amount,
+            },
+        };
+        let (ty, data) = match waveform {
+            Waveform::Sine => (0, PomWaveformData { duty_cycle: 0.0 }),
+            Waveform::Pulse { duty_cycle } => (
+                1,
+                PomWaveformData {
+                    duty_cycle: *duty_cycle,
+                },
+            ),
+            Waveform::Triangle => (2, PomWaveformData { duty_cycle: 0.0 }),
+            Waveform::Sawtooth => (3, PomWaveformData { duty_cycle: 0.0 }),
+            Waveform::InvertedSawtooth => (4, PomWaveformData { duty_cycle: 0.0 }),
+            Waveform::PCM(sample_id) => (
+                5,
+                PomWaveformData {
+                    sample_id: *sample_id,
+                },
+            ),
+            Waveform::Constant(constant_offset) => (
+                6,
+                PomWaveformData {
+                    constant_offset: *constant_offset,
+                },
+            ),
+            Waveform::Noise { seed, pink } => (
+                7,
+                PomWaveformData {
+                    noise: PomWaveformNoise {
+                        seed: *seed,
+                        pink: *pink as c_int,
+                    },
+                },
+            ),
+            Waveform::Wavetable(sample_id) => (
+                8,
+                PomWaveformData {
+                    sample_id: *sample_id,
+                },
+            ),
+            Waveform::Thin {
+                waveform_active_percent,
+                ..
+            } => (9, nested(*waveform_active_percent)),
+            Waveform::Cut {
+                waveform_active_percent,
+                ..
+            } => (10, nested(*waveform_active_percent)),
+            Waveform::Absolute(_) => (11, nested(0.0)),
+            Waveform::Saturate { drive, .. } => (12, nested(*drive)),
+            Waveform::Mix { blend, .. } => (
+                13,
+                PomWaveformData {
+                    mix: PomWaveformMix {
+                        a: std::ptr::null(),
+                        b: std::ptr::null(),
+                        blend: *blend,
+                    },
+                },
+            ),
+            Waveform::Offset { gain, bias, .. } => (
+                14,
+                PomWaveformData {
+                    offset: PomWaveformOffset {
+                        base: std::ptr::null(),
+                        gain: *gain,
+                        bias: *bias,
+                    },
+                },
+            ),
+            Waveform::Clamp { min, max, .. } => (
+                15,
+                PomWaveformData {
+                    clamp: PomWaveformClamp {
+                        base: std::ptr::null(),
+                        min: *min,
+                        max: *max,
+                    },
+                },
+            ),
+            Waveform::PhaseDistort { .. }
+            | Waveform::BandLimited { .. }
+            | Waveform::AntialiasedTriangle => {
+                return Err(PomResult::UnknownWaveform as PomResultCode);
+            }
+        };
+        Ok(Self { ty, data })
+    }
+    /// SAFETY: see [`PomWaveform::to_rust`].
+    unsafe fn nested_to_rust(waveform: *const PomWaveform) -> Result<Box<Waveform>, PomResultCode> {
+        match unsafe { waveform.as_ref() } {
+            Some(waveform) => Ok(Box::new(unsafe { waveform.to_rust() }?)),
+            None => Err(PomResult::NullPointer as PomResultCode),
         }
     }
 }
@@ -92,14 +338,34 @@ impl PomWaveform {
 pub struct PomEnvelope {
     attack_time: PomDuration,
     halving_rate: f64,
+    sustain_level: f64,
     release_time: PomDuration,
+    loop_envelope: c_int,
 }
 impl PomEnvelope {
+    /// Copies an envelope out to FFI, dropping its curves.
+    /// Returns `None` for breakpoint envelopes, which have no FFI representation.
+    pub fn from_rust(envelope: &EnvelopeKind) -> Option<Self> {
+        let EnvelopeKind::Envelope(envelope) = envelope else {
+            return None;
+        };
+        Some(Self {
+            attack_time: envelope.attack_time.into(),
+            halving_rate: envelope.halving_rate,
+            sustain_level: envelope.sustain_level,
+            release_time: envelope.release_time.into(),
+            loop_envelope: envelope.loop_envelope as c_int,
+        })
+    }
     pub fn to_rust(&self) -> Envelope {
         Envelope {
             attack_time: self.attack_time.to_rust(),
+            attack_curve: Curve::Linear,
             halving_rate: self.halving_rate,
+            decay_curve: DecayCurve::Exponential,
+            sustain_level: self.sustain_level,
             release_time: self.release_time.to_rust(),
+            loop_envelope: self.loop_envelope != 0,
         }
     }
 }
@@ -108,89 +374,290 @@ impl PomEnvelope {
 #[repr(C)]
 pub struct PomModifiers {
     frequency_multiplier: f64,
+    detune_cents: f64,
     volume_multiplier: f64,
     constant_phase_offset: f64,
+    feedback: f64,
+    key_track: f64,
+    key_track_reference: f64,
+    output_gain: f64,
+    velocity_curve: f64,
+}
+impl From<&OperatorModifiers> for PomModifiers {
+    fn from(modifiers: &OperatorModifiers) -> Self {
+        Self {
+            frequency_multiplier: modifiers.frequency_multiplier,
+            detune_cents: modifiers.detune_cents,
+            volume_multiplier: modifiers.volume_multiplier,
+            constant_phase_offset: modifiers.constant_phase_offset,
+            feedback: modifiers.feedback,
+            key_track: modifiers.key_track,
+            key_track_reference: modifiers.key_track_reference,
+            output_gain: modifiers.output_gain,
+            velocity_curve: modifiers.velocity_curve,
+        }
+    }
 }
 impl PomModifiers {
     pub fn to_rust(&self) -> OperatorModifiers {
         OperatorModifiers {
             frequency_multiplier: self.frequency_multiplier,
+            detune_cents: self.detune_cents,
             volume_multiplier: self.volume_multiplier,
             constant_phase_offset: self.constant_phase_offset,
+            feedback: self.feedback,
+            key_track: self.key_track,
+            key_track_reference: self.key_track_reference,
+            output_gain: self.output_gain,
+            velocity_curve: self.velocity_curve,
+        }
+    }
+}
+
+/// An event for a scheduled synth. `frequency` and `volume` are only used by the events that need them.
+#[repr(C)]
+pub struct PomEvent {
+    ty: c_int,
+    frequency: f64,
+    volume: f64,
+}
+impl PomEvent {
+    pub fn to_rust(&self) -> Option<Event> {
+        Some(match self.ty {
+            0 => Event::Play {
+                frequency: self.frequency,
+                volume: self.volume,
+            },
+            1 => Event::Release,
+            2 => Event::Cut,
+            3 => Event::SetFrequency(self.frequency),
+            _ => return None,
+        })
+    }
+}
+
+/// An instruction of a stacker. `constant` is only used by constant instructions and as the threshold of select-if instructions,
+/// and `operator_index` by sample instructions.
+#[repr(C)]
+pub struct PomStackInstruction {
+    ty: c_int,
+    constant: f64,
+    operator_index: u64,
+}
+impl From<&StackInstruction> for PomStackInstruction {
+    fn from(instruction: &StackInstruction) -> Self {
+        let (ty, constant, operator_index) = match *instruction {
+            StackInstruction::Constant(constant) => (0, constant, 0),
+            StackInstruction::InputPhaseOffset => (1, 0.0, 0),
+            StackInstruction::Sample(operator) => (2, 0.0, operator),
+            StackInstruction::Add => (3, 0.0, 0),
+            StackInstruction::Sub => (4, 0.0, 0),
+            StackInstruction::Mul => (5, 0.0, 0),
+            StackInstruction::Neg => (6, 0.0, 0),
+            StackInstruction::Dupe => (7, 0.0, 0),
+            StackInstruction::Swap => (8, 0.0, 0),
+            StackInstruction::Rot => (9, 0.0, 0),
+            StackInstruction::SelectIf { threshold } => (10, threshold, 0),
+        };
+        Self {
+            ty,
+            constant,
+            operator_index,
         }
     }
 }
 
+/// A low-pass filter for an operator.
+#[repr(C)]
+pub struct PomFilter {
+    enabled: c_int,
+    cutoff_hz: f64,
+    resonance: f64,
+}
+impl From<Option<Filter>> for PomFilter {
+    fn from(filter: Option<Filter>) -> Self {
+        Self {
+            enabled: filter.is_some() as c_int,
+            cutoff_hz: filter.map_or(0.0, |filter| filter.cutoff_hz),
+            resonance: filter.map_or(0.0, |filter| filter.resonance),
+        }
+    }
+}
+impl PomFilter {
+    pub fn to_rust(&self) -> Option<Filter> {
+        (self.enabled != 0).then_some(Filter {
+            cutoff_hz: self.cutoff_hz,
+            resonance: self.resonance,
+        })
+    }
+}
+
 /// Settings for creating an operator.
 #[repr(C)]
 pub struct PomOperatorSettings {
     waveform: PomWaveform,
     envelope: PomEnvelope,
     modifiers: PomModifiers,
+    filter: PomFilter,
+    glide_time: PomDuration,
+    random_start_phase: c_int,
+    start_phase_seed: u64,
+    nyquist_mute: c_int,
+    phase_offset_smoothing: PomDuration,
+}
+impl PomOperatorSettings {
+    /// Copies the settings of an operator out to FFI. See [`PomWaveform::from_rust`] and [`PomEnvelope::from_rust`].
+    ///
+    /// Fails with `UnknownWaveform` if the waveform has no FFI type, or `InvalidInput` if the envelope has no FFI representation.
+    pub fn from_rust(operator: &Operator) -> Result<Self, PomResultCode> {
+        Ok(Self {
+            waveform: PomWaveform::from_rust(&operator.waveform)?,
+            envelope: PomEnvelope::from_rust(&operator.envelope)
+                .ok_or(PomResult::InvalidInput as PomResultCode)?,
+            modifiers: PomModifiers::from(&operator.modifiers),
+            filter: PomFilter::from(operator.filter),
+            glide_time: operator.glide_time.into(),
+            random_start_phase: operator.random_start_phase as c_int,
+            start_phase_seed: operator.start_phase_seed,
+            nyquist_mute: operator.nyquist_mute as c_int,
+            phase_offset_smoothing: operator.phase_offset_smoothing.into(),
+        })
+    }
+    /// SAFETY: see [`PomWaveform::to_rust`].
+    pub unsafe fn to_rust(&self) -> Result<Operator, PomResultCode> {
+        let mut operator = Operator::new(
+            unsafe { self.waveform.to_rust() }?,
+            self.envelope.to_rust(),
+            self.modifiers.to_rust(),
+        );
+        operator.filter = self.filter.to_rust();
+        operator.glide_time = self.glide_time.to_rust();
+        operator.random_start_phase = self.random_start_phase != 0;
+        operator.start_phase_seed = self.start_phase_seed;
+        operator.nyquist_mute = self.nyquist_mute != 0;
+        operator.phase_offset_smoothing = self.phase_offset_smoothing.to_rust();
+        Ok(operator)
+    }
 }
 
 /// Settings for creating a PCM sample.
 #[repr(C)]
 pub struct PomPCMSampleSettings {
+    samples_per_second: f64,
     samples_per_period: f64,
     loop_point: PomDuration,
     loop_duration: PomDuration,
+    interpolation: c_int,
+    start_offset: PomDuration,
+    reverse: c_int,
+    crossfade: PomDuration,
 }
 
-pub fn send_boxed_pom_to_ffi(
-    output: &mut PomOpaqueMut,
-    synth: FFIPomBox,
-) -> PomResultCode {
+// Pointers from C may be null, so every helper that takes one returns `None` for null instead of panicking,
+// and output pointers are `Option<&mut T>`, which has the same representation as a nullable pointer.
+
+/// Fails with `NullPointer` if `output` is null.
+pub fn send_boxed_pom_to_ffi(output: Option<&mut PomOpaqueMut>, synth: FFIPomBox) -> PomResultCode {
+    let Some(output) = output else {
+        return PomResult::NullPointer as PomResultCode;
+    };
     *output = Box::leak(Box::new(synth)) as PomOpaqueMut;
     PomResult::Success as PomResultCode
 }
+/// Fails with `NullPointer` if `output` is null.
 pub fn send_pom_to_ffi(
-    output: &mut PomOpaqueMut,
+    output: Option<&mut PomOpaqueMut>,
     synth: impl Pom<SampleBank> + 'static,
 ) -> PomResultCode {
     send_boxed_pom_to_ffi(output, Box::new(synth))
 }
 /// SAFETY:
-/// - `synth` must be an output of `send_to_ffi`.
+/// - `synth` must be an output of `send_to_ffi`, or null.
 /// - When the result is dropped, `synth` becomes a dangling pointer.
-pub unsafe fn take_pom_from_ffi(synth: PomOpaqueMut) -> Box<FFIPomBox> {
-    let synth = unsafe { Box::from_raw(synth) };
-    synth
+pub unsafe fn take_pom_from_ffi(synth: PomOpaqueMut) -> Option<Box<FFIPomBox>> {
+    (!synth.is_null()).then(|| unsafe { Box::from_raw(synth) })
 }
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
-pub unsafe fn get_pom_from_ffi(synth: PomOpaque) -> &'static FFIPomBox {
-    unsafe { synth.as_ref() }.unwrap()
+/// SAFETY: `synth` must be an output of `send_to_ffi`, or null.
+pub unsafe fn get_pom_from_ffi(synth: PomOpaque) -> Option<&'static FFIPomBox> {
+    unsafe { synth.as_ref() }
 }
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
-pub unsafe fn get_mut_pom_from_ffi(synth: PomOpaqueMut) -> &'static mut FFIPomBox {
-    unsafe { synth.as_mut() }.unwrap()
+/// SAFETY: `synth` must be an output of `send_to_ffi`, or null.
+pub unsafe fn get_mut_pom_from_ffi(synth: PomOpaqueMut) -> Option<&'static mut FFIPomBox> {
+    unsafe { synth.as_mut() }
 }
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
-pub unsafe fn clone_pom_from_ffi(synth: PomOpaque) -> FFIPomBox {
-    unsafe { get_pom_from_ffi(synth) }.box_clone()
+/// SAFETY: `synth` must be an output of `send_to_ffi`, or null.
+pub unsafe fn clone_pom_from_ffi(synth: PomOpaque) -> Option<FFIPomBox> {
+    Some(unsafe { get_pom_from_ffi(synth) }?.box_clone())
+}
+/// Clones every synthesiser in an array, or returns `None` if the array or any synthesiser in it is null.
+///
+/// SAFETY: `synths` must be the base of a `length`-long array of outputs of `send_to_ffi`, or null.
+pub unsafe fn clone_poms_from_ffi(synths: *const PomOpaque, length: u64) -> Option<Vec<FFIPomBox>> {
+    unsafe { slice_from_ffi(synths, length) }?
+        .iter()
+        .map(|&pom| unsafe { clone_pom_from_ffi(pom) })
+        .collect()
+}
+/// Borrows an array from C. A null `data` is only accepted for empty arrays, producing an empty slice.
+///
+/// SAFETY: `data` must be the base of a `length`-long array, or null.
+unsafe fn slice_from_ffi<'a, T>(data: *const T, length: u64) -> Option<&'a [T]> {
+    if data.is_null() {
+        (length == 0).then_some(&[])
+    } else {
+        Some(unsafe { core::slice::from_raw_parts(data, length as usize) })
+    }
+}
+
+/// Fails with `NullPointer` if `output` is null.
+pub fn send_shared_pom_to_ffi(
+    output: Option<&mut PomSharedOpaque>,
+    synth: FFIPomBox,
+) -> PomResultCode {
+    let Some(output) = output else {
+        return PomResult::NullPointer as PomResultCode;
+    };
+    *output = Arc::into_raw(Arc::new(Mutex::new(SendPom(synth))));
+    PomResult::Success as PomResultCode
+}
+/// Locks a shared synthesiser, blocking until it is available.
+/// A synthesiser whose lock was poisoned by a panic is still handed out, as its state is always valid to sample.
+///
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed, or null.
+pub unsafe fn lock_shared_pom_from_ffi(
+    synth: PomSharedOpaque,
+) -> Option<MutexGuard<'static, SendPom>> {
+    Some(
+        unsafe { synth.as_ref() }?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+    )
 }
 
-pub fn send_pcm_bank_to_ffi(out: &mut PomPCMBankMut, bank: SampleBank) -> PomResultCode {
+/// Fails with `NullPointer` if `out` is null.
+pub fn send_pcm_bank_to_ffi(out: Option<&mut PomPCMBankMut>, bank: SampleBank) -> PomResultCode {
+    let Some(out) = out else {
+        return PomResult::NullPointer as PomResultCode;
+    };
     *out = Box::leak(Box::new(bank));
     PomResult::Success as PomResultCode
 }
-pub fn create_ffi_pcm_bank(output: &mut PomPCMBankMut) -> PomResultCode {
+pub fn create_ffi_pcm_bank(output: Option<&mut PomPCMBankMut>) -> PomResultCode {
     send_pcm_bank_to_ffi(output, SampleBank::default())
 }
 /// SAFETY:
-/// - `bank` must be an output of `create_pcm_bank`.
+/// - `bank` must be an output of `create_pcm_bank`, or null.
 /// - When the result is dropped, `bank` becomes a dangling pointer.
-pub unsafe fn take_pcm_bank_from_ffi(bank: PomPCMBankMut) -> Box<SampleBank> {
-    let bank = unsafe { Box::from_raw(bank) };
-    bank
+pub unsafe fn take_pcm_bank_from_ffi(bank: PomPCMBankMut) -> Option<Box<SampleBank>> {
+    (!bank.is_null()).then(|| unsafe { Box::from_raw(bank) })
 }
 /// SAFETY: `bank` must be an output of `create_pcm_bank`, or null.
 pub unsafe fn get_pcm_bank_from_ffi(bank: PomPCMBank) -> &'static SampleBank {
     unsafe { bank.as_ref() }.unwrap_or(&*EMPTY_PCM_BANK)
 }
-/// SAFETY: `bank` must be an output of `create_pcm_bank`.
-pub unsafe fn get_mut_pcm_bank_from_ffi(bank: PomPCMBankMut) -> &'static mut SampleBank {
-    unsafe { bank.as_mut() }.unwrap()
+/// SAFETY: `bank` must be an output of `create_pcm_bank`, or null.
+pub unsafe fn get_mut_pcm_bank_from_ffi(bank: PomPCMBankMut) -> Option<&'static mut SampleBank> {
+    unsafe { bank.as_mut() }
 }
 /// SAFETY: `bank` must be an output of `create_pcm_bank`, or null.
 pub unsafe fn clone_pcm_bank_from_ffi(bank: PomPCMBank) -> SampleBank {
@@ -201,9 +668,25 @@ pub unsafe fn clone_pcm_bank_from_ffi(bank: PomPCMBank) -> SampleBank {
 pub enum PomResult {
     Success = 0,
     InvalidInput = 1,
+    NullPointer = 2,
+    UnknownWaveform = 3,
+    UnknownSampleFormat = 4,
+    SampleNotFound = 5,
+    AllocationFailed = 6,
+    /// A panic was caught before it could unwind into C.
+    InternalError = 7,
+    SampleConflict = 8,
 }
 type PomResultCode = i32;
 
+/// Runs the body of an exported function, returning `default` if it panics,
+/// as unwinding into C is undefined behaviour.
+///
+/// Functions that only do arithmetic that can't panic aren't wrapped.
+fn catch_panic<T>(default: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(default)
+}
+
 #[repr(i32)]
 pub enum PomSampleFormat {
     U8,
@@ -213,302 +696,2010 @@ pub enum PomSampleFormat {
     F64,
 }
 
+/// How [`pom_fill_limited`] keeps samples within -1 to 1 before they are converted to a sample format.
+#[repr(i32)]
+pub enum PomLimiter {
+    /// Samples are passed through, so float formats can exceed -1 to 1.
+    None,
+    /// Samples are hard-clipped to -1 to 1.
+    Brickwall,
+    /// Samples are smoothly compressed towards ±1 with `tanh`, which also bends samples within range.
+    SoftClip,
+}
+impl PomLimiter {
+    fn apply(&self, x: f64) -> f64 {
+        match self {
+            PomLimiter::None => x,
+            // NaN can't be limited, so it is silenced
+            _ if x.is_nan() => 0.0,
+            PomLimiter::Brickwall => x.clamp(-1.0, 1.0),
+            PomLimiter::SoftClip => x.tanh(),
+        }
+    }
+}
+
+/// SAFETY: nested waveforms in `settings` must point to valid waveforms, forming a tree.
 #[unsafe(no_mangle)]
-pub extern "C" fn pom_create_operator(
-    output: &mut PomOpaqueMut,
+pub unsafe extern "C" fn pom_create_operator(
+    output: Option<&mut PomOpaqueMut>,
     settings: PomOperatorSettings,
 ) -> PomResultCode {
-    if let Some(waveform) = settings.waveform.to_rust() {
-        send_pom_to_ffi(
-            output,
-            Operator::new(
-                waveform,
-                settings.envelope.to_rust(),
-                settings.modifiers.to_rust(),
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        match unsafe { settings.to_rust() } {
+            Ok(operator) => send_pom_to_ffi(output, operator),
+            Err(code) => code,
+        }
+    })
+}
+
+/// SAFETY: nested waveforms in `settings` must point to valid waveforms, forming a tree.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_create_unison(
+    output: Option<&mut PomOpaqueMut>,
+    settings: PomOperatorSettings,
+    voices: u64,
+    detune_cents: f64,
+    stereo_spread: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        match unsafe { settings.to_rust() } {
+            Ok(operator) => send_pom_to_ffi(
+                output,
+                Combinator::unison(operator, voices as usize, detune_cents, stereo_spread),
             ),
-        )
-    } else {
-        PomResult::InvalidInput as PomResultCode
-    }
+            Err(code) => code,
+        }
+    })
 }
 
 /// SAFETY: `modulator` and `carrier` must be outputs of `send_to_ffi`.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn pom_create_modulator(
-    output: &mut PomOpaqueMut,
+    output: Option<&mut PomOpaqueMut>,
     modulator: PomOpaque,
     carrier: PomOpaque,
 ) -> PomResultCode {
-    send_pom_to_ffi(
-        output,
-        Combinator {
-            synths: unsafe { vec![clone_pom_from_ffi(modulator), clone_pom_from_ffi(carrier)] },
-            ty: CombinatorType::Modulate,
-        },
-    )
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(modulator), Some(carrier)) =
+            (unsafe { (clone_pom_from_ffi(modulator), clone_pom_from_ffi(carrier)) })
+        else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(
+            output,
+            Combinator::new(vec![modulator, carrier], CombinatorType::Modulate),
+        )
+    })
 }
 
 /// SAFETY: `a` and `b` must be outputs of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub extern "C" fn pom_create_summation(
-    output: &mut PomOpaqueMut,
+pub unsafe extern "C" fn pom_create_summation(
+    output: Option<&mut PomOpaqueMut>,
     a: PomOpaque,
     b: PomOpaque,
 ) -> PomResultCode {
-    send_pom_to_ffi(
-        output,
-        Combinator {
-            synths: unsafe { vec![clone_pom_from_ffi(a), clone_pom_from_ffi(b)] },
-            ty: CombinatorType::Sum,
-        },
-    )
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(a), Some(b)) = (unsafe { (clone_pom_from_ffi(a), clone_pom_from_ffi(b)) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Combinator::new(vec![a, b], CombinatorType::Sum))
+    })
 }
 
-/// SAFETY: `synths` must be the base of a `length`-long array of outputs of `send_to_ffi`.
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_create_combinator(
-    output: &mut PomOpaqueMut,
-    synths: *const PomOpaque,
-    length: u64,
-    ty: c_int,
+pub unsafe extern "C" fn pom_create_scheduled(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
 ) -> PomResultCode {
-    let ty = match ty {
-        0 => CombinatorType::Sum,
-        1 => CombinatorType::Modulate,
-        _ => return PomResult::InvalidInput as PomResultCode,
-    };
-    let slice = unsafe { core::slice::from_raw_parts(synths, length as usize) };
-    let synths = slice
-        .iter()
-        .copied()
-        .map(|pom| unsafe { clone_pom_from_ffi(pom) })
-        .collect::<Vec<_>>();
-    send_pom_to_ffi(output, Combinator { synths, ty })
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Scheduled::new(synth))
+    })
 }
 
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
+/// SAFETY: `master` and `slave` must be outputs of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_play(synth: PomOpaqueMut, frequency: f64, volume: f64) {
-    unsafe { get_mut_pom_from_ffi(synth) }.play(frequency, volume);
+pub unsafe extern "C" fn pom_create_sync(
+    output: Option<&mut PomOpaqueMut>,
+    master: PomOpaque,
+    slave: PomOpaque,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(master), Some(slave)) =
+            (unsafe { (clone_pom_from_ffi(master), clone_pom_from_ffi(slave)) })
+        else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, HardSync::new(master, slave))
+    })
 }
 
-/// SAFETY:
-/// - `synth` must be an output of `send_to_ffi`.
-/// - `bank` must be an output of `create_pcm_bank`, or null.
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_sample(
-    synth: PomOpaqueMut,
-    bank: PomPCMBank,
-    global_time: PomDuration,
-    input_phase_offset: f64,
-) -> f64 {
-    unsafe { get_mut_pom_from_ffi(synth) }
-        .sample(
-            unsafe { get_pcm_bank_from_ffi(bank) },
-            global_time.to_rust(),
-            input_phase_offset,
+pub unsafe extern "C" fn pom_create_chorus(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
+    delay: PomDuration,
+    depth: PomDuration,
+    rate: f64,
+    mix: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(
+            output,
+            Chorus::new(synth, delay.to_rust(), depth.to_rust(), rate, mix),
         )
-        .unwrap_or(0.0)
+    })
 }
 
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub extern "C" fn pom_frequency_to_interval(frequency: f64) -> PomDuration {
-    PomDuration::from(Duration::from_secs(1).div_f64(frequency))
+pub unsafe extern "C" fn pom_create_phaser(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
+    stages: u32,
+    frequency: f64,
+    depth: f64,
+    rate: f64,
+    feedback: f64,
+    mix: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(
+            output,
+            Phaser::new(
+                synth,
+                stages as usize,
+                frequency,
+                depth,
+                rate,
+                feedback,
+                mix,
+            ),
+        )
+    })
 }
 
-/// Helper function for integer PCM.
-/// Maps `x` from `input_min..input_max` to `output_min..output_max`, rounds, then clamps on the output range.
-pub fn quantise(x: f64, input_min: f64, input_max: f64, output_min: f64, output_max: f64) -> f64 {
-    ((x - input_min) / (input_max - input_min) * (output_max - output_min) + output_min)
-        .round()
-        .clamp(output_min, output_max)
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_create_oversampled(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
+    oversample: u32,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Oversampled::new(synth, oversample))
+    })
 }
 
-fn get_sample_format(sample_format: c_int) -> Result<PomSampleFormat, PomResultCode> {
-    Ok(match sample_format {
-        0 => PomSampleFormat::U8,
-        1 => PomSampleFormat::I16,
-        2 => PomSampleFormat::I32,
-        3 => PomSampleFormat::F32,
-        4 => PomSampleFormat::F64,
-        _ => return Err(PomResult::InvalidInput as PomResultCode),
-    })
+/// Unpacks a gate pattern of `length` steps from a bit array, least significant bit first.
+///
+/// SAFETY: `pattern` must be the base of an array of at least `length.div_ceil(8)` bytes, or null if `length` is 0.
+unsafe fn gate_pattern_from_ffi(pattern: *const u8, length: u64) -> Option<Vec<bool>> {
+    let bytes = unsafe { slice_from_ffi(pattern, length.div_ceil(8)) }?;
+    Some(
+        (0..length as usize)
+            .map(|step| bytes[step / 8] & (1 << (step % 8)) != 0)
+            .collect(),
+    )
 }
 
 /// SAFETY:
-/// - `synth` must be an output of `send_to_ffi`.
-/// - `bank` must be an output of `create_pcm_bank`, or null.
-/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`.
+/// - `carrier` must be an output of `send_to_ffi`.
+/// - `pattern` must be the base of an array of at least `pattern_length.div_ceil(8)` bytes, or null if `pattern_length` is 0.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_fill(
-    synth: PomOpaqueMut,
-    bank: PomPCMBank,
-    global_time: PomDuration,
-    sample_interval: PomDuration,
-    data: *mut (),
-    length: u64,
-    sample_format: c_int,
-    constant_phase_offset: f64,
+pub unsafe extern "C" fn pom_create_gate(
+    output: Option<&mut PomOpaqueMut>,
+    carrier: PomOpaque,
+    pattern: *const u8,
+    pattern_length: u64,
+    step: PomDuration,
 ) -> PomResultCode {
-    let length = length as usize;
-    let synth = unsafe { get_mut_pom_from_ffi(synth) };
-    let mut time = global_time.to_rust();
-    let interval = sample_interval.to_rust();
-    let sample_format = match get_sample_format(sample_format) {
-        Ok(format) => format,
-        Err(code) => return code,
-    };
-    let mut get = || -> f64 {
-        let sample = synth
-            .sample(
-                unsafe { get_pcm_bank_from_ffi(bank) },
-                time,
-                constant_phase_offset,
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(carrier), Some(pattern)) = (unsafe {
+            (
+                clone_pom_from_ffi(carrier),
+                gate_pattern_from_ffi(pattern, pattern_length),
             )
-            .unwrap_or(0.0);
-        time += interval;
-        sample
-    };
-    match sample_format {
-        PomSampleFormat::U8 => {
-            let data: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
-            for i in 0..length {
-                data[i] = quantise(get(), -1.0, 1.0, u8::MIN as f64, u8::MAX as f64) as u8;
-            }
-        }
-        PomSampleFormat::I16 => {
-            let data: &mut [i16] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
-            for i in 0..length {
-                data[i] = quantise(get(), -1.0, 1.0, i16::MIN as f64, i16::MAX as f64) as i16;
-            }
-        }
-        PomSampleFormat::I32 => {
-            let data: &mut [i32] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
-            for i in 0..length {
-                data[i] = quantise(get(), -1.0, 1.0, i32::MIN as f64, i32::MAX as f64) as i32;
-            }
-        }
-        PomSampleFormat::F32 => {
-            let data: &mut [f32] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
-            for i in 0..length {
-                data[i] = get() as f32;
-            }
-        }
-        PomSampleFormat::F64 => {
-            let data: &mut [f64] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
-            for i in 0..length {
-                data[i] = get();
-            }
-        }
-    }
-    PomResult::Success as PomResultCode
+        }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Gate::new(carrier, pattern, step.to_rust()))
+    })
 }
 
 /// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_release(synth: PomOpaqueMut) {
-    unsafe { get_mut_pom_from_ffi(synth) }.release();
+pub unsafe extern "C" fn pom_create_panned(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
+    pan: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Panned::new(synth, pan))
+    })
 }
 
 /// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_cut(synth: PomOpaqueMut) {
-    unsafe { get_mut_pom_from_ffi(synth) }.cut();
+pub unsafe extern "C" fn pom_create_stereo(
+    output: Option<&mut PomOpaqueMut>,
+    synth: PomOpaque,
+    pan: f64,
+    width: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { clone_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Stereo::new(synth, pan, width))
+    })
 }
 
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
+/// SAFETY: `synths` must be the base of a `length`-long array of outputs of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_destroy_synth(pom: PomOpaqueMut) {
-    drop(unsafe { take_pom_from_ffi(pom) })
+pub unsafe extern "C" fn pom_create_combinator(
+    output: Option<&mut PomOpaqueMut>,
+    synths: *const PomOpaque,
+    length: u64,
+    ty: c_int,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let ty = match ty {
+            0 => CombinatorType::Sum,
+            1 => CombinatorType::Modulate,
+            2 => CombinatorType::Multiply,
+            3 => CombinatorType::Min,
+            4 => CombinatorType::Max,
+            5 => CombinatorType::Average,
+            6 => CombinatorType::WeightedSum,
+            _ => return PomResult::InvalidInput as PomResultCode,
+        };
+        let Some(synths) = (unsafe { clone_poms_from_ffi(synths, length) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Combinator::new(synths, ty))
+    })
 }
 
-/// SAFETY: `synth` must be an output of `send_to_ffi`.
+/// SAFETY: `synths` must be the base of a `length`-long array of outputs of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_clone_synth(out: &mut PomOpaqueMut, source: PomOpaque) -> PomResultCode {
-    send_boxed_pom_to_ffi(out, unsafe { clone_pom_from_ffi(source) })
+pub unsafe extern "C" fn pom_create_normalized_sum(
+    output: Option<&mut PomOpaqueMut>,
+    synths: *const PomOpaque,
+    length: u64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synths) = (unsafe { clone_poms_from_ffi(synths, length) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_pom_to_ffi(output, Combinator::normalized_sum(synths))
+    })
 }
 
-fn map_normalise(x: f64, min: f64, max: f64) -> f64 {
-    2.0 * (x - min) / (max - min) - 1.0
+/// SAFETY:
+/// - `synths` must be the base of a `length`-long array of outputs of `send_to_ffi`.
+/// - `weights` must be the base of a `length`-long array.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_create_weighted_sum(
+    output: Option<&mut PomOpaqueMut>,
+    synths: *const PomOpaque,
+    weights: *const f64,
+    length: u64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synths), Some(weights)) = (unsafe {
+            (
+                clone_poms_from_ffi(synths, length),
+                slice_from_ffi(weights, length),
+            )
+        }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let weights = weights.to_vec();
+        send_pom_to_ffi(output, Combinator::weighted_sum(synths, weights))
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_play(synth: PomOpaqueMut, frequency: f64, volume: f64) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.play(frequency, volume);
+        }
+    })
 }
 
+/// Fails with `InvalidInput` if `synth` was not created by `pom_create_scheduled`, or `event` is unknown.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
 #[unsafe(no_mangle)]
-pub extern "C" fn pom_create_pcm_bank(output: &mut PomPCMBankMut) -> PomResultCode {
-    create_ffi_pcm_bank(output)
+pub unsafe extern "C" fn pom_schedule_event(
+    synth: PomOpaqueMut,
+    when: PomDuration,
+    event: PomEvent,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(scheduled) = synth.as_any_mut().downcast_mut::<Scheduled<SampleBank>>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        let Some(event) = event.to_rust() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        scheduled.schedule(when.to_rust(), event);
+        PomResult::Success as PomResultCode
+    })
 }
 
+/// Fails with `InvalidInput` if `synth` was not created by `pom_create_gate`.
+///
 /// SAFETY:
-/// - `bank` must be an output of `create_pcm_bank`.
-/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`, containing PCM data for the PCM sample.
+/// - `synth` must be an output of `send_to_ffi`.
+/// - `pattern` must be the base of an array of at least `pattern_length.div_ceil(8)` bytes, or null if `pattern_length` is 0.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_add_pcm_sample(
-    bank: PomPCMBankMut,
-    pcm_data: *const (),
-    pcm_length: u64,
-    pcm_sample_format: c_int,
-    identifier: SampleID,
-    pcm_sample_settings: PomPCMSampleSettings,
+pub unsafe extern "C" fn pom_set_gate_pattern(
+    synth: PomOpaqueMut,
+    pattern: *const u8,
+    pattern_length: u64,
+    step: PomDuration,
 ) -> PomResultCode {
-    let pcm_length = pcm_length as usize;
-    let sample_bank = unsafe { get_mut_pcm_bank_from_ffi(bank) };
-    let sample_format = match get_sample_format(pcm_sample_format) {
-        Ok(format) => format,
-        Err(code) => return code,
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(pattern)) = (unsafe {
+            (
+                get_mut_pom_from_ffi(synth),
+                gate_pattern_from_ffi(pattern, pattern_length),
+            )
+        }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(gate) = synth.as_any_mut().downcast_mut::<Gate<SampleBank>>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        gate.pattern = pattern;
+        gate.step = step.to_rust();
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Adds a copy of `child` to the end of a [`Combinator`]'s synths.
+/// Fails with `InvalidInput` if `combinator` is not a combinator.
+///
+/// SAFETY: `combinator` and `child` must be outputs of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_combinator_push(
+    combinator: PomOpaqueMut,
+    child: PomOpaque,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        // cloned before borrowing `combinator`, which may be the same synth
+        let Some(child) = (unsafe { clone_pom_from_ffi(child) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(combinator) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(combinator) = synth.as_any_mut().downcast_mut::<Combinator<SampleBank>>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        combinator.synths.push(child);
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Removes and destroys the synth at `index` of a [`Combinator`], along with its weight, if it has one.
+/// Fails with `InvalidInput` if `combinator` is not a combinator or `index` is out of range.
+///
+/// SAFETY: `combinator` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_combinator_remove(
+    combinator: PomOpaqueMut,
+    index: u64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(combinator) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(combinator) = synth.as_any_mut().downcast_mut::<Combinator<SampleBank>>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        let Some(index) = usize::try_from(index)
+            .ok()
+            .filter(|&index| index < combinator.synths.len())
+        else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        combinator.synths.remove(index);
+        if index < combinator.weights.len() {
+            combinator.weights.remove(index);
+        }
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Finds the operator at `index` within a synth: the synth itself for an operator (where `index` must be 0),
+/// one of the operators of a stacker, or one of the synths of a combinator, if that synth is an operator.
+fn operator_at_mut(synth: &mut FFIPomBox, index: u64) -> Option<&mut Operator> {
+    let index = usize::try_from(index).ok()?;
+    let synth = synth.as_any_mut();
+    if synth.is::<Operator>() {
+        return synth.downcast_mut::<Operator>().filter(|_| index == 0);
+    }
+    if synth.is::<Stacker>() {
+        return synth.downcast_mut::<Stacker>()?.operators.get_mut(index);
+    }
+    synth
+        .downcast_mut::<Combinator<SampleBank>>()?
+        .synths
+        .get_mut(index)?
+        .as_any_mut()
+        .downcast_mut::<Operator>()
+}
+
+/// Fails with `InvalidInput` if there is no operator at `index`. See [`operator_at_mut`].
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_set_operator_muted(
+    synth: PomOpaqueMut,
+    index: u64,
+    muted: c_int,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(operator) = operator_at_mut(synth, index) else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        operator.muted = muted != 0;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_set_start(synth: PomOpaqueMut, when: PomDuration) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.set_start(when.to_rust());
+        }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_set_frequency(synth: PomOpaqueMut, frequency: f64) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.set_frequency(frequency);
+        }
+    })
+}
+
+/// SAFETY:
+/// - `synth` must be an output of `send_to_ffi`.
+/// - `bank` must be an output of `create_pcm_bank`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_sample(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    input_phase_offset: f64,
+) -> f64 {
+    catch_panic(0.0, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return 0.0;
+        };
+        synth
+            .sample(
+                unsafe { get_pcm_bank_from_ffi(bank) },
+                global_time.to_rust(),
+                input_phase_offset,
+            )
+            .unwrap_or(0.0)
+    })
+}
+
+/// Like [`pom_sample`], but distinguishes silence from the synth being off.
+/// Returns 1 and writes the sample to `output` if the synth produced one, or returns 0 if it is off.
+/// `output` can be null to only check whether the synth is off.
+///
+/// SAFETY: see [`pom_sample`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_sample_opt(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    input_phase_offset: f64,
+    output: Option<&mut f64>,
+) -> c_int {
+    catch_panic(0, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return 0;
+        };
+        let Some(sample) = synth.sample(
+            unsafe { get_pcm_bank_from_ffi(bank) },
+            global_time.to_rust(),
+            input_phase_offset,
+        ) else {
+            return 0;
+        };
+        if let Some(output) = output {
+            *output = sample;
+        }
+        1
+    })
+}
+
+/// Evaluates a waveform at a phase in cycles, without any synth state.
+/// Returns 0 if the waveform can't be converted.
+///
+/// SAFETY:
+/// - `waveform` must satisfy the requirements of [`PomWaveform::to_rust`].
+/// - `bank` must be an output of `create_pcm_bank`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_sample_waveform(
+    waveform: PomWaveform,
+    bank: PomPCMBank,
+    phase: f64,
+) -> f64 {
+    catch_panic(0.0, || {
+        let Ok(waveform) = (unsafe { waveform.to_rust() }) else {
+            return 0.0;
+        };
+        waveform.sample(
+            unsafe { get_pcm_bank_from_ffi(bank) },
+            Period::from_secs_f64(phase.rem_euclid(1.0)),
+            0.0,
+        )
+    })
+}
+
+/// Returns a zero duration if `frequency` is not positive,
+/// and saturates to the maximum duration if `frequency` is too small.
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_frequency_to_interval(frequency: f64) -> PomDuration {
+    if frequency.is_nan() || frequency <= 0.0 {
+        return PomDuration::from(Duration::ZERO);
+    }
+    PomDuration::from(Duration::try_from_secs_f64(1.0 / frequency).unwrap_or(Duration::MAX))
+}
+
+/// Returns the start time of the block that follows a block of `length` samples starting at `global_time`.
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_advance_time(
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    length: u64,
+) -> PomDuration {
+    PomDuration::from(time::advance(
+        global_time.to_rust(),
+        sample_interval.to_rust(),
+        length,
+    ))
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_midi_to_frequency(note: u8) -> f64 {
+    pitch::midi_to_frequency(note)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_midi_to_frequency_tuned(note: u8, a4_frequency: f64) -> f64 {
+    pitch::midi_to_frequency_tuned(note, a4_frequency)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_frequency_to_midi(frequency: f64) -> f64 {
+    pitch::frequency_to_midi(frequency)
+}
+
+/// Helper function for integer PCM.
+/// Maps `x` from `input_min..input_max` to `output_min..output_max`, rounds, then clamps on the output range.
+pub fn quantise(x: f64, input_min: f64, input_max: f64, output_min: f64, output_max: f64) -> f64 {
+    ((x - input_min) / (input_max - input_min) * (output_max - output_min) + output_min)
+        .round()
+        .clamp(output_min, output_max)
+}
+
+fn get_sample_format(sample_format: c_int) -> Result<PomSampleFormat, PomResultCode> {
+    Ok(match sample_format {
+        0 => PomSampleFormat::U8,
+        1 => PomSampleFormat::I16,
+        2 => PomSampleFormat::I32,
+        3 => PomSampleFormat::F32,
+        4 => PomSampleFormat::F64,
+        _ => return Err(PomResult::UnknownSampleFormat as PomResultCode),
+    })
+}
+
+fn get_limiter(limiter: c_int) -> Result<PomLimiter, PomResultCode> {
+    Ok(match limiter {
+        0 => PomLimiter::None,
+        1 => PomLimiter::Brickwall,
+        2 => PomLimiter::SoftClip,
+        _ => return Err(PomResult::InvalidInput as PomResultCode),
+    })
+}
+
+/// Copies `length` values of PCM data, failing with `AllocationFailed` instead of aborting if there isn't enough memory.
+///
+/// Fails with `NullPointer` if `data` is null and `length` isn't 0.
+///
+/// SAFETY: `data` must be the base of a `length`-long array of `T`, or null.
+unsafe fn copy_pcm<T: Copy>(data: *const (), length: u64) -> Result<Vec<T>, PomResultCode> {
+    let Some(data) = (unsafe { slice_from_ffi(data.cast(), length) }) else {
+        return Err(PomResult::NullPointer as PomResultCode);
+    };
+    let mut copy = Vec::new();
+    if copy.try_reserve_exact(data.len()).is_err() {
+        return Err(PomResult::AllocationFailed as PomResultCode);
+    }
+    copy.extend_from_slice(data);
+    Ok(copy)
+}
+
+/// Deterministic dither for [`write_pcm`].
+struct Dither {
+    seed: u64,
+    /// The position of the first written sample in the whole render, so that consecutive blocks
+    /// continue the dither instead of repeating it.
+    first_sample: u64,
+}
+
+/// Fills `data` with `length` samples produced by `get`, converting them to `sample_format`.
+///
+/// If `dither` is given, triangular dither of up to one quantisation step is added before
+/// rounding to integer formats, generated deterministically from its seed. Float formats are never dithered.
+///
+/// Fails with `NullPointer` if `data` is null and `length` isn't 0.
+///
+/// SAFETY: `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`, or null.
+unsafe fn write_pcm(
+    data: *mut (),
+    length: usize,
+    sample_format: PomSampleFormat,
+    dither: Option<Dither>,
+    mut get: impl FnMut() -> f64,
+) -> PomResultCode {
+    if data.is_null() {
+        return if length == 0 {
+            PomResult::Success as PomResultCode
+        } else {
+            PomResult::NullPointer as PomResultCode
+        };
+    }
+    // the sum of two uniform values has a triangular distribution over (-1, 1) steps
+    let dither = |i: usize, min: f64, max: f64| match dither {
+        Some(Dither { seed, first_sample }) => {
+            let i = first_sample.wrapping_add(i as u64).wrapping_mul(2);
+            let steps = random::unit(seed, i) + random::unit(seed, i.wrapping_add(1)) - 1.0;
+            // one step of the output is this wide in the input range of -1..1
+            steps * 2.0 / (max - min)
+        }
+        None => 0.0,
     };
-    let mut converted_data = vec![0.0; pcm_length];
     match sample_format {
         PomSampleFormat::U8 => {
-            let data: &[u8] = unsafe { core::slice::from_raw_parts(pcm_data.cast(), pcm_length) };
-            for i in 0..pcm_length {
-                converted_data[i] = map_normalise(data[i] as f64, u8::MIN as f64, u8::MAX as f64);
+            let data: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
+            let (min, max) = (u8::MIN as f64, u8::MAX as f64);
+            for (i, sample) in data.iter_mut().enumerate() {
+                *sample = quantise(get() + dither(i, min, max), -1.0, 1.0, min, max) as u8;
             }
         }
         PomSampleFormat::I16 => {
-            let data: &[i16] = unsafe { core::slice::from_raw_parts(pcm_data.cast(), pcm_length) };
-            for i in 0..pcm_length {
-                converted_data[i] = map_normalise(data[i] as f64, i16::MIN as f64, i16::MAX as f64);
+            let data: &mut [i16] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
+            let (min, max) = (i16::MIN as f64, i16::MAX as f64);
+            for (i, sample) in data.iter_mut().enumerate() {
+                *sample = quantise(get() + dither(i, min, max), -1.0, 1.0, min, max) as i16;
             }
         }
         PomSampleFormat::I32 => {
-            let data: &[i32] = unsafe { core::slice::from_raw_parts(pcm_data.cast(), pcm_length) };
-            for i in 0..pcm_length {
-                converted_data[i] = map_normalise(data[i] as f64, i32::MIN as f64, i32::MAX as f64);
+            let data: &mut [i32] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
+            let (min, max) = (i32::MIN as f64, i32::MAX as f64);
+            for (i, sample) in data.iter_mut().enumerate() {
+                *sample = quantise(get() + dither(i, min, max), -1.0, 1.0, min, max) as i32;
             }
         }
         PomSampleFormat::F32 => {
-            let data: &[f32] = unsafe { core::slice::from_raw_parts(pcm_data.cast(), pcm_length) };
-            for i in 0..pcm_length {
-                converted_data[i] = data[i] as f64;
+            let data: &mut [f32] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
+            for i in 0..length {
+                data[i] = get() as f32;
             }
         }
         PomSampleFormat::F64 => {
-            let data: &[f64] = unsafe { core::slice::from_raw_parts(pcm_data.cast(), pcm_length) };
-            converted_data.copy_from_slice(data);
+            let data: &mut [f64] = unsafe { core::slice::from_raw_parts_mut(data.cast(), length) };
+            for i in 0..length {
+                data[i] = get();
+            }
         }
     }
-    sample_bank.samples.insert(
-        identifier,
-        Sample {
-            samples_per_period: pcm_sample_settings.samples_per_period,
-            loop_point: pcm_sample_settings.loop_point.to_rust(),
-            loop_duration: pcm_sample_settings.loop_duration.to_rust(),
-            pcm_data: converted_data,
-        },
-    );
     PomResult::Success as PomResultCode
 }
 
-/// SAFETY: `bank` must be an output of `create_ffi_pcm_bank`.
+/// SAFETY:
+/// - `synth` must be an output of `send_to_ffi`.
+/// - `bank` must be an output of `create_pcm_bank`, or null.
+/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_destroy_pcm_bank(bank: PomPCMBankMut) {
-    drop(unsafe { take_pcm_bank_from_ffi(bank) })
+pub unsafe extern "C" fn pom_fill(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || unsafe {
+        pom_fill_interleaved(
+            synth,
+            bank,
+            global_time,
+            sample_interval,
+            data,
+            length,
+            1,
+            sample_format,
+            constant_phase_offset,
+        )
+    })
 }
 
-/// SAFETY: `bank` must be an output of `create_ffi_pcm_bank`.
+/// SAFETY:
+/// - `synth` must be an output of `send_to_ffi`.
+/// - `bank` must be an output of `create_pcm_bank`, or null.
+/// - `data` must be the base of a `frame_count * channels`-long array of samples whose size is governed by `sample_format`.
+///
+/// Stereo synths write their left channel to even channels and their right channel to odd channels,
+/// unless there is only one channel. Mono synths are duplicated across all channels.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn pom_clone_pcm_bank(
-    out: &mut PomPCMBankMut,
+pub unsafe extern "C" fn pom_fill_interleaved(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    frame_count: u64,
+    channels: u32,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || unsafe {
+        fill_interleaved(
+            synth,
+            bank,
+            global_time,
+            sample_interval,
+            data,
+            frame_count,
+            channels,
+            sample_format,
+            constant_phase_offset,
+            None,
+            PomLimiter::None,
+        )
+    })
+}
+
+/// Like [`pom_fill`], but adds deterministic triangular dither before rounding to integer sample formats,
+/// which turns the distortion of quantising quiet signals into a constant noise floor.
+/// The same `dither_seed` always produces the same dither, so renders are reproducible.
+///
+/// SAFETY: see [`pom_fill`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_dithered(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+    dither_seed: u64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || unsafe {
+        fill_interleaved(
+            synth,
+            bank,
+            global_time,
+            sample_interval,
+            data,
+            length,
+            1,
+            sample_format,
+            constant_phase_offset,
+            Some(dither_seed),
+            PomLimiter::None,
+        )
+    })
+}
+
+/// Like [`pom_fill`], but passes each sample through `limiter` before converting it to `sample_format`,
+/// keeping float formats within -1 to 1 even if the synth produces runaway values, such as from feedback.
+/// Integer formats are clamped to their range regardless, so a brickwall limiter doesn't change them.
+///
+/// Fails with `InvalidInput` if `limiter` is unknown.
+///
+/// SAFETY: see [`pom_fill`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_limited(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+    limiter: c_int,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let limiter = match get_limiter(limiter) {
+            Ok(limiter) => limiter,
+            Err(code) => return code,
+        };
+        unsafe {
+            fill_interleaved(
+                synth,
+                bank,
+                global_time,
+                sample_interval,
+                data,
+                length,
+                1,
+                sample_format,
+                constant_phase_offset,
+                None,
+                limiter,
+            )
+        }
+    })
+}
+
+/// Like [`pom_fill`], but sets the oversampling factor of an [`Oversampled`] synth to `oversample` first.
+/// Its decimation filter keeps its state between blocks unless the factor changes.
+///
+/// Fails with `InvalidInput` if the synth was not created by [`pom_create_oversampled`].
+///
+/// SAFETY: see [`pom_fill`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_oversampled(
+    synth: PomOpaqueMut,
     bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+    oversample: u32,
 ) -> PomResultCode {
-    send_pcm_bank_to_ffi(out, unsafe { clone_pcm_bank_from_ffi(bank) })
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(pom) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(oversampled) = pom.as_any_mut().downcast_mut::<Oversampled<SampleBank>>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        oversampled.set_factor(oversample);
+        unsafe {
+            pom_fill(
+                synth,
+                bank,
+                global_time,
+                sample_interval,
+                data,
+                length,
+                sample_format,
+                constant_phase_offset,
+            )
+        }
+    })
+}
+
+/// Like [`pom_fill`], but also measures the block as it is filled, writing the peak absolute sample to `peak`
+/// and the root mean square of the samples to `rms`. Both are measured before the samples are converted
+/// to `sample_format`, so they don't depend on it, and either can be null to skip it.
+///
+/// SAFETY: see [`pom_fill`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_metered(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+    peak: Option<&mut f64>,
+    rms: Option<&mut f64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let start_time = global_time.to_rust();
+        let interval = sample_interval.to_rust();
+        let sample_format = match get_sample_format(sample_format) {
+            Ok(format) => format,
+            Err(code) => return code,
+        };
+        let bank = unsafe { get_pcm_bank_from_ffi(bank) };
+        let mut index = 0;
+        let mut max = 0.0f64;
+        let mut sum_of_squares = 0.0;
+        let get = || -> f64 {
+            let sample = synth
+                .sample(
+                    bank,
+                    time::advance(start_time, interval, index),
+                    constant_phase_offset,
+                )
+                .unwrap_or(0.0);
+            index += 1;
+            max = max.max(sample.abs());
+            sum_of_squares += sample * sample;
+            sample
+        };
+        let result = unsafe { write_pcm(data, length as usize, sample_format, None, get) };
+        if result == PomResult::Success as PomResultCode {
+            if let Some(peak) = peak {
+                *peak = max;
+            }
+            if let Some(rms) = rms {
+                *rms = if length == 0 {
+                    0.0
+                } else {
+                    (sum_of_squares / length as f64).sqrt()
+                };
+            }
+        }
+        result
+    })
+}
+
+/// SAFETY: see [`pom_fill_interleaved`].
+#[allow(clippy::too_many_arguments)]
+unsafe fn fill_interleaved(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    frame_count: u64,
+    channels: u32,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+    dither_seed: Option<u64>,
+    limiter: PomLimiter,
+) -> PomResultCode {
+    let channels = channels as usize;
+    let Some(length) = (frame_count as usize).checked_mul(channels) else {
+        return PomResult::InvalidInput as PomResultCode;
+    };
+    if channels == 0 {
+        return PomResult::InvalidInput as PomResultCode;
+    }
+    let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+        return PomResult::NullPointer as PomResultCode;
+    };
+    let start_time = global_time.to_rust();
+    let interval = sample_interval.to_rust();
+    let sample_format = match get_sample_format(sample_format) {
+        Ok(format) => format,
+        Err(code) => return code,
+    };
+    let mut channel = 0;
+    let mut frame_index = 0;
+    let mut frame = (0.0, 0.0);
+    let get = || -> f64 {
+        if channel == 0 {
+            let bank = unsafe { get_pcm_bank_from_ffi(bank) };
+            let time = time::advance(start_time, interval, frame_index);
+            frame = if channels > 1 {
+                synth
+                    .sample_as_stereo(bank, time, constant_phase_offset)
+                    .unwrap_or((0.0, 0.0))
+            } else {
+                let sample = synth
+                    .sample(bank, time, constant_phase_offset)
+                    .unwrap_or(0.0);
+                (sample, sample)
+            };
+            frame_index += 1;
+        }
+        let sample = if channel % 2 == 0 { frame.0 } else { frame.1 };
+        channel = (channel + 1) % channels;
+        limiter.apply(sample)
+    };
+    // the dither follows the position of each sample in the whole render, not just this block
+    let dither = dither_seed.map(|seed| Dither {
+        seed,
+        first_sample: start_time
+            .as_nanos()
+            .checked_div(interval.as_nanos())
+            .map_or(0, |frame| (frame as u64).wrapping_mul(channels as u64)),
+    });
+    unsafe { write_pcm(data, length, sample_format, dither, get) }
+}
+
+/// Like [`pom_fill`], but sets the frequency of the synth to `frequencies[i]` before producing each sample `i`.
+/// If `frequencies` is null, this is equivalent to [`pom_fill`].
+///
+/// SAFETY:
+/// - `synth` must be an output of `send_to_ffi`.
+/// - `bank` must be an output of `create_pcm_bank`, or null.
+/// - `frequencies` must be the base of a `length`-long array, or null.
+/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_automated(
+    synth: PomOpaqueMut,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    frequencies: *const f64,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        if frequencies.is_null() {
+            return unsafe {
+                pom_fill(
+                    synth,
+                    bank,
+                    global_time,
+                    sample_interval,
+                    data,
+                    length,
+                    sample_format,
+                    constant_phase_offset,
+                )
+            };
+        }
+        let length = length as usize;
+        let frequencies = unsafe { core::slice::from_raw_parts(frequencies, length) };
+        let Some(synth) = (unsafe { get_mut_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let start_time = global_time.to_rust();
+        let interval = sample_interval.to_rust();
+        let sample_format = match get_sample_format(sample_format) {
+            Ok(format) => format,
+            Err(code) => return code,
+        };
+        let mut frequencies = frequencies.iter().enumerate();
+        let get = || -> f64 {
+            let Some((index, frequency)) = frequencies.next() else {
+                return 0.0;
+            };
+            synth.set_frequency(*frequency);
+            synth
+                .sample(
+                    unsafe { get_pcm_bank_from_ffi(bank) },
+                    time::advance(start_time, interval, index as u64),
+                    constant_phase_offset,
+                )
+                .unwrap_or(0.0)
+        };
+        unsafe { write_pcm(data, length, sample_format, None, get) }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_release(synth: PomOpaqueMut) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.release();
+        }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_cut(synth: PomOpaqueMut) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.cut();
+        }
+    })
+}
+
+/// Returns 1 if the synthesiser is playing or scheduled to play, or 0 if it has finished.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_is_active(synth: PomOpaque) -> c_int {
+    catch_panic(0, || {
+        unsafe { get_pom_from_ffi(synth) }.is_some_and(|synth| synth.is_active()) as c_int
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_reset_phase(synth: PomOpaqueMut) {
+    catch_panic((), || {
+        if let Some(synth) = unsafe { get_mut_pom_from_ffi(synth) } {
+            synth.reset_phase();
+        }
+    })
+}
+
+/// Writes the wrapped phase of the synth's oscillator to `output`.
+/// Fails with `InvalidInput` if the synth doesn't have a single oscillator.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_get_phase(
+    synth: PomOpaque,
+    output: Option<&mut f64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(output)) = (unsafe { get_pom_from_ffi(synth) }, output) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(period) = synth.oscillator_period() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        *output = period.subsec_nanos() as f64 / NANOS_PER_SEC as f64;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Fails with `InvalidInput` if the synth is not a stacker.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_stacker_operator_count(
+    synth: PomOpaque,
+    output: Option<&mut u64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(output)) = (unsafe { get_pom_from_ffi(synth) }, output) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(stacker) = synth.as_any().downcast_ref::<Stacker>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        *output = stacker.operators.len() as u64;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Fails with `InvalidInput` if the synth is not a stacker.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_stacker_instruction_count(
+    synth: PomOpaque,
+    output: Option<&mut u64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(output)) = (unsafe { get_pom_from_ffi(synth) }, output) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(stacker) = synth.as_any().downcast_ref::<Stacker>() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        *output = stacker.instructions.len() as u64;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Copies out the settings of the operator at `index`. See [`PomOperatorSettings::from_rust`].
+///
+/// Fails with `InvalidInput` if the synth is not a stacker or `index` is out of range.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_stacker_get_operator(
+    synth: PomOpaque,
+    index: u64,
+    output: Option<&mut PomOperatorSettings>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(output)) = (unsafe { get_pom_from_ffi(synth) }, output) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(operator) = synth
+            .as_any()
+            .downcast_ref::<Stacker>()
+            .and_then(|stacker| stacker.operators.get(index as usize))
+        else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        match PomOperatorSettings::from_rust(operator) {
+            Ok(settings) => {
+                *output = settings;
+                PomResult::Success as PomResultCode
+            }
+            Err(code) => code,
+        }
+    })
+}
+
+/// Fails with `InvalidInput` if the synth is not a stacker or `index` is out of range.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_stacker_get_instruction(
+    synth: PomOpaque,
+    index: u64,
+    output: Option<&mut PomStackInstruction>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(output)) = (unsafe { get_pom_from_ffi(synth) }, output) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(instruction) = synth
+            .as_any()
+            .downcast_ref::<Stacker>()
+            .and_then(|stacker| stacker.instructions.get(index as usize))
+        else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        *output = PomStackInstruction::from(instruction);
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Returns 0 if the synth is off.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_current_volume(synth: PomOpaque, global_time: PomDuration) -> f64 {
+    catch_panic(0.0, || {
+        unsafe { get_pom_from_ffi(synth) }
+            .and_then(|synth| synth.current_volume(global_time.to_rust()))
+            .unwrap_or(0.0)
+    })
+}
+
+/// Returns [`PomDuration::INFINITE`] if the synth is sustaining indefinitely.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_remaining(synth: PomOpaque) -> PomDuration {
+    catch_panic(Duration::ZERO.into(), || {
+        let Some(synth) = (unsafe { get_pom_from_ffi(synth) }) else {
+            return Duration::ZERO.into();
+        };
+        match synth.remaining() {
+            Some(remaining) => remaining.into(),
+            None => PomDuration::INFINITE,
+        }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_destroy_synth(pom: PomOpaqueMut) {
+    catch_panic((), || drop(unsafe { take_pom_from_ffi(pom) }))
+}
+
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_clone_synth(
+    out: Option<&mut PomOpaqueMut>,
+    source: PomOpaque,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(source) = (unsafe { clone_pom_from_ffi(source) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_boxed_pom_to_ffi(out, source)
+    })
+}
+
+/// SAFETY: nested waveforms in `settings` must point to valid waveforms, forming a tree.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_create_operator_shared(
+    output: Option<&mut PomSharedOpaque>,
+    settings: PomOperatorSettings,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        match unsafe { settings.to_rust() } {
+            Ok(operator) => send_shared_pom_to_ffi(output, Box::new(operator)),
+            Err(code) => code,
+        }
+    })
+}
+
+/// SAFETY: `source` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_share_synth(
+    output: Option<&mut PomSharedOpaque>,
+    source: PomOpaque,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(source) = (unsafe { clone_pom_from_ffi(source) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        send_shared_pom_to_ffi(output, source)
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_clone_shared_handle(
+    output: Option<&mut PomSharedOpaque>,
+    synth: PomSharedOpaque,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(output) = output else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        if synth.is_null() {
+            return PomResult::NullPointer as PomResultCode;
+        }
+        unsafe { Arc::increment_strong_count(synth) };
+        *output = synth;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_play_locked(synth: PomSharedOpaque, frequency: f64, volume: f64) {
+    catch_panic((), || {
+        if let Some(mut synth) = unsafe { lock_shared_pom_from_ffi(synth) } {
+            synth.0.play(frequency, volume);
+        }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_release_locked(synth: PomSharedOpaque) {
+    catch_panic((), || {
+        if let Some(mut synth) = unsafe { lock_shared_pom_from_ffi(synth) } {
+            synth.0.release();
+        }
+    })
+}
+
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_cut_locked(synth: PomSharedOpaque) {
+    catch_panic((), || {
+        if let Some(mut synth) = unsafe { lock_shared_pom_from_ffi(synth) } {
+            synth.0.cut();
+        }
+    })
+}
+
+/// SAFETY:
+/// - `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+/// - `bank` must be an output of `create_pcm_bank`, or null, and must not be mutated during the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_sample_locked(
+    synth: PomSharedOpaque,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    input_phase_offset: f64,
+) -> f64 {
+    catch_panic(0.0, || {
+        let Some(mut synth) = (unsafe { lock_shared_pom_from_ffi(synth) }) else {
+            return 0.0;
+        };
+        unsafe { pom_sample(&mut synth.0, bank, global_time, input_phase_offset) }
+    })
+}
+
+/// The lock is held for the entire fill.
+///
+/// SAFETY:
+/// - `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+/// - `bank` must be an output of `create_pcm_bank`, or null, and must not be mutated during the call.
+/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_fill_locked(
+    synth: PomSharedOpaque,
+    bank: PomPCMBank,
+    global_time: PomDuration,
+    sample_interval: PomDuration,
+    data: *mut (),
+    length: u64,
+    sample_format: c_int,
+    constant_phase_offset: f64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(mut synth) = (unsafe { lock_shared_pom_from_ffi(synth) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        unsafe {
+            pom_fill(
+                &mut synth.0,
+                bank,
+                global_time,
+                sample_interval,
+                data,
+                length,
+                sample_format,
+                constant_phase_offset,
+            )
+        }
+    })
+}
+
+/// Releases this handle's reference to the shared synthesiser.
+/// The synthesiser is destroyed once every handle to it has been destroyed.
+///
+/// SAFETY: `synth` must be an output of `send_shared_pom_to_ffi` that has not been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_destroy_shared(synth: PomSharedOpaque) {
+    catch_panic((), || {
+        if !synth.is_null() {
+            drop(unsafe { Arc::from_raw(synth) })
+        }
+    })
+}
+
+/// Encodes a synthesiser into a newly allocated byte buffer, which must be destroyed with `pom_destroy_buffer`.
+///
+/// SAFETY: `synth` must be an output of `send_to_ffi`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_serialize_synth(
+    synth: PomOpaque,
+    out_buffer: Option<&mut *mut u8>,
+    out_length: Option<&mut u64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let (Some(synth), Some(out_buffer), Some(out_length)) =
+            (unsafe { get_pom_from_ffi(synth) }, out_buffer, out_length)
+        else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(serialized) = synth.to_serialized() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        let Ok(bytes) = serialized.to_bytes() else {
+            return PomResult::InvalidInput as PomResultCode;
+        };
+        *out_length = bytes.len() as u64;
+        *out_buffer = Box::leak(bytes.into_boxed_slice()).as_mut_ptr();
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `bytes` must be the base of a `length`-long array of bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_deserialize_synth(
+    output: Option<&mut PomOpaqueMut>,
+    bytes: *const u8,
+    length: u64,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(bytes) = (unsafe { slice_from_ffi(bytes, length) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        match SerializedSynth::from_bytes(bytes) {
+            Ok(serialized) => send_boxed_pom_to_ffi(output, serialized.into_pom()),
+            Err(_) => PomResult::InvalidInput as PomResultCode,
+        }
+    })
+}
+
+/// SAFETY: `buffer` and `length` must be outputs of `pom_serialize_synth`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_destroy_buffer(buffer: *mut u8, length: u64) {
+    catch_panic((), || {
+        if !buffer.is_null() {
+            drop(unsafe {
+                Box::from_raw(core::ptr::slice_from_raw_parts_mut(buffer, length as usize))
+            })
+        }
+    })
+}
+
+/// Exposes [`quantise`], so hosts can convert PCM exactly as the crate does.
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_quantise(
+    x: f64,
+    input_min: f64,
+    input_max: f64,
+    output_min: f64,
+    output_max: f64,
+) -> f64 {
+    quantise(x, input_min, input_max, output_min, output_max)
+}
+
+/// Exposes [`normalise`], so hosts can convert PCM exactly as the crate does.
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_normalise(x: f64, min: f64, max: f64) -> f64 {
+    normalise(x, min, max)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn pom_create_pcm_bank(output: Option<&mut PomPCMBankMut>) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        create_ffi_pcm_bank(output)
+    })
+}
+
+/// Fails with `InvalidInput` if the loop region extends past the end of the data. See [`Sample::loop_fits`].
+///
+/// SAFETY:
+/// - `bank` must be an output of `create_pcm_bank`.
+/// - `data` must be the base of a `length`-long array of samples whose size is governed by `sample_format`, containing PCM data for the PCM sample.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_add_pcm_sample(
+    bank: PomPCMBankMut,
+    pcm_data: *const (),
+    pcm_length: u64,
+    pcm_sample_format: c_int,
+    identifier: SampleID,
+    pcm_sample_settings: PomPCMSampleSettings,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(sample_bank) = (unsafe { get_mut_pcm_bank_from_ffi(bank) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let sample_format = match get_sample_format(pcm_sample_format) {
+            Ok(format) => format,
+            Err(code) => return code,
+        };
+        let interpolation = match pcm_sample_settings.interpolation {
+            0 => Interpolation::Nearest,
+            1 => Interpolation::Linear,
+            2 => Interpolation::Cubic,
+            _ => return PomResult::InvalidInput as PomResultCode,
+        };
+        // kept in its original format to avoid widening large samples to f64
+        let pcm_data = match sample_format {
+            PomSampleFormat::U8 => unsafe { copy_pcm(pcm_data, pcm_length) }.map(PcmData::U8),
+            PomSampleFormat::I16 => unsafe { copy_pcm(pcm_data, pcm_length) }.map(PcmData::I16),
+            PomSampleFormat::I32 => unsafe { copy_pcm(pcm_data, pcm_length) }.map(PcmData::I32),
+            PomSampleFormat::F32 => unsafe { copy_pcm(pcm_data, pcm_length) }.map(PcmData::F32),
+            PomSampleFormat::F64 => unsafe { copy_pcm(pcm_data, pcm_length) }.map(PcmData::F64),
+        };
+        let pcm_data = match pcm_data {
+            Ok(pcm_data) => pcm_data,
+            Err(code) => return code,
+        };
+        let sample = Sample {
+            samples_per_second: pcm_sample_settings.samples_per_second,
+            samples_per_period: pcm_sample_settings.samples_per_period,
+            start_offset: pcm_sample_settings.start_offset.to_rust(),
+            loop_point: pcm_sample_settings.loop_point.to_rust(),
+            loop_duration: pcm_sample_settings.loop_duration.to_rust(),
+            crossfade: pcm_sample_settings.crossfade.to_rust(),
+            pcm_data,
+            interpolation,
+            reverse: pcm_sample_settings.reverse != 0,
+        };
+        if !sample.loop_fits() {
+            return PomResult::InvalidInput as PomResultCode;
+        }
+        sample_bank.insert(identifier, sample);
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_pcm_bank`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_pcm_sample_length(
+    bank: PomPCMBank,
+    identifier: SampleID,
+    output: Option<&mut u64>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(output) = output else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(sample) = unsafe { get_pcm_bank_from_ffi(bank) }.get(identifier) else {
+            return PomResult::SampleNotFound as PomResultCode;
+        };
+        *output = sample.len() as u64;
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_pcm_bank`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_pcm_sample_duration(
+    bank: PomPCMBank,
+    identifier: SampleID,
+    output: Option<&mut PomDuration>,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(output) = output else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(sample) = unsafe { get_pcm_bank_from_ffi(bank) }.get(identifier) else {
+            return PomResult::SampleNotFound as PomResultCode;
+        };
+        *output = PomDuration::from(sample.duration());
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// Moves the loop region of a sample in place, without copying its PCM data again.
+/// Fails with `InvalidInput`, leaving the sample unchanged, if the new loop doesn't fit in the data.
+///
+/// SAFETY: `bank` must be an output of `create_pcm_bank`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_set_pcm_loop(
+    bank: PomPCMBankMut,
+    identifier: SampleID,
+    loop_point: PomDuration,
+    loop_duration: PomDuration,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(bank) = (unsafe { get_mut_pcm_bank_from_ffi(bank) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        let Some(sample) = bank.get_mut(identifier) else {
+            return PomResult::SampleNotFound as PomResultCode;
+        };
+        let previous = (sample.loop_point, sample.loop_duration);
+        sample.loop_point = loop_point.to_rust();
+        sample.loop_duration = loop_duration.to_rust();
+        if !sample.loop_fits() {
+            (sample.loop_point, sample.loop_duration) = previous;
+            return PomResult::InvalidInput as PomResultCode;
+        }
+        PomResult::Success as PomResultCode
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_pcm_bank`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_remove_pcm_sample(
+    bank: PomPCMBankMut,
+    identifier: SampleID,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let Some(bank) = (unsafe { get_mut_pcm_bank_from_ffi(bank) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        match bank.remove(identifier) {
+            Some(_) => PomResult::Success as PomResultCode,
+            None => PomResult::SampleNotFound as PomResultCode,
+        }
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_pcm_bank`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_clear_pcm_bank(bank: PomPCMBankMut) {
+    catch_panic((), || {
+        if let Some(bank) = unsafe { get_mut_pcm_bank_from_ffi(bank) } {
+            bank.clear();
+            bank.samples.shrink_to_fit();
+            bank.dense.shrink_to_fit();
+        }
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_ffi_pcm_bank`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_destroy_pcm_bank(bank: PomPCMBankMut) {
+    catch_panic((), || drop(unsafe { take_pcm_bank_from_ffi(bank) }))
+}
+
+/// Copies every sample of `source` into `destination`. `policy` is a [`ConflictPolicy`]:
+/// 0 overwrites samples already in `destination`, 1 skips them, and 2 fails with `SampleConflict`,
+/// leaving `destination` unchanged. Unknown policies fail with `InvalidInput`.
+///
+/// SAFETY: `destination` must be an output of `create_pcm_bank`, and `source` must be one or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_merge_pcm_banks(
+    destination: PomPCMBankMut,
+    source: PomPCMBank,
+    policy: c_int,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        let policy = match policy {
+            0 => ConflictPolicy::Overwrite,
+            1 => ConflictPolicy::Skip,
+            2 => ConflictPolicy::Error,
+            _ => return PomResult::InvalidInput as PomResultCode,
+        };
+        // cloned before borrowing `destination`, which may be the same bank
+        let source = unsafe { clone_pcm_bank_from_ffi(source) };
+        let Some(destination) = (unsafe { get_mut_pcm_bank_from_ffi(destination) }) else {
+            return PomResult::NullPointer as PomResultCode;
+        };
+        match destination.merge(source, policy) {
+            Ok(()) => PomResult::Success as PomResultCode,
+            Err(_) => PomResult::SampleConflict as PomResultCode,
+        }
+    })
+}
+
+/// SAFETY: `bank` must be an output of `create_ffi_pcm_bank`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pom_clone_pcm_bank(
+    out: Option<&mut PomPCMBankMut>,
+    bank: PomPCMBank,
+) -> PomResultCode {
+    catch_panic(PomResult::InternalError as PomResultCode, || {
+        send_pcm_bank_to_ffi(out, unsafe { clone_pcm_bank_from_ffi(bank) })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_to_interval_handles_non_positive_frequencies() {
+        for frequency in [0.0, -0.0, -1.0, f64::NAN, f64::NEG_INFINITY] {
+            assert_eq!(
+                pom_frequency_to_interval(frequency).to_rust(),
+                Duration::ZERO
+            );
+        }
+        assert_eq!(
+            pom_frequency_to_interval(4.0).to_rust(),
+            Duration::from_millis(250)
+        );
+    }
+
+    /// Fills `length` samples of `synth` as 64-bit floats at 48kHz, from `global_time`.
+    fn fill(synth: PomOpaqueMut, global_time: PomDuration, length: usize) -> Vec<f64> {
+        let mut output = vec![0.0; length];
+        let interval = PomDuration::from(Duration::from_secs(1) / 48000);
+        let result = unsafe {
+            pom_fill(
+                synth,
+                std::ptr::null(),
+                global_time,
+                interval,
+                output.as_mut_ptr().cast(),
+                length as u64,
+                PomSampleFormat::F64 as c_int,
+                0.0,
+            )
+        };
+        assert_eq!(result, PomResult::Success as PomResultCode);
+        output
+    }
+    fn send_sine(frequency: f64, volume: f64) -> PomOpaqueMut {
+        let mut operator = Operator::builder().waveform(Waveform::Sine).build();
+        operator.play(frequency, volume);
+        let mut synth = std::ptr::null_mut();
+        send_boxed_pom_to_ffi(Some(&mut synth), Box::new(operator));
+        synth
+    }
+
+    #[test]
+    fn filling_in_blocks_matches_filling_at_once() {
+        // any drift in time between blocks would show up in the phase of the sine
+        let whole = send_sine(441.0, 1.0);
+        let split = send_sine(441.0, 1.0);
+        let start = Duration::from_millis(3);
+        let interval = Duration::from_secs(1) / 48000;
+        let at_once = fill(whole, start.into(), 1000);
+        let middle = pom_advance_time(start.into(), interval.into(), 500);
+        let mut in_blocks = fill(split, start.into(), 500);
+        in_blocks.extend(fill(split, middle, 500));
+        assert_eq!(
+            at_once.iter().map(|x| x.to_bits()).collect::<Vec<_>>(),
+            in_blocks.iter().map(|x| x.to_bits()).collect::<Vec<_>>()
+        );
+        unsafe {
+            drop(take_pom_from_ffi(whole));
+            drop(take_pom_from_ffi(split));
+        }
+    }
+
+    #[test]
+    fn dither_whitens_the_quantisation_error() {
+        let interval = Duration::from_secs(1) / 48000;
+        // the error of quantising a sine two steps high to 16 bits, in steps, over one second rendered in blocks
+        let error = |dither_seed: Option<u64>| {
+            let volume = 2.0 / 65535.0;
+            let (quantised, exact) = (send_sine(1000.0, volume), send_sine(1000.0, volume));
+            let mut error = vec![];
+            for block in 0..10 {
+                let start = time::advance(Duration::ZERO, interval, block * 4800);
+                let mut output = vec![0i16; 4800];
+                let result = unsafe {
+                    match dither_seed {
+                        Some(seed) => pom_fill_dithered(
+                            quantised,
+                            std::ptr::null(),
+                            start.into(),
+                            interval.into(),
+                            output.as_mut_ptr().cast(),
+                            4800,
+                            PomSampleFormat::I16 as c_int,
+                            0.0,
+                            seed,
+                        ),
+                        None => pom_fill(
+                            quantised,
+                            std::ptr::null(),
+                            start.into(),
+                            interval.into(),
+                            output.as_mut_ptr().cast(),
+                            4800,
+                            PomSampleFormat::I16 as c_int,
+                            0.0,
+                        ),
+                    }
+                };
+                assert_eq!(result, PomResult::Success as PomResultCode);
+                let ideal = fill(exact, start.into(), 4800);
+                error.extend(output.iter().zip(ideal).map(|(&quantised, ideal)| {
+                    quantised as f64 - ((ideal + 1.0) / 2.0 * 65535.0 - 32768.0)
+                }));
+            }
+            unsafe {
+                drop(take_pom_from_ffi(quantised));
+                drop(take_pom_from_ffi(exact));
+            }
+            error
+        };
+        // power in `frequencies`, from single bins of a DFT over the whole second
+        let power = |error: &[f64], frequencies: &mut dyn Iterator<Item = u32>| {
+            frequencies
+                .map(|frequency| {
+                    let (re, im) = error
+                        .iter()
+                        .enumerate()
+                        .fold((0.0, 0.0), |(re, im), (i, x)| {
+                            let angle =
+                                std::f64::consts::TAU * frequency as f64 * i as f64 / 48000.0;
+                            (re + x * angle.cos(), im - x * angle.sin())
+                        });
+                    re * re + im * im
+                })
+                .sum::<f64>()
+        };
+        // without dither, the error repeats with the sine, so it is all at its harmonics.
+        // white noise spreads evenly, including between the lines that dither repeating each block would produce
+        for (dither_seed, whitened) in [(None, false), (Some(7), true)] {
+            let error = error(dither_seed);
+            let harmonics = power(&error, &mut (1..=10).map(|harmonic| harmonic * 1000));
+            let between = power(&error, &mut (1..=10).map(|harmonic| harmonic * 1000 + 5));
+            let ratio = between / harmonics;
+            assert_eq!((0.1..10.0).contains(&ratio), whitened, "{ratio}");
+        }
+    }
+
+    #[test]
+    fn null_pointers_are_rejected_without_panicking() {
+        use std::ptr::{null, null_mut};
+        let null_pointer = PomResult::NullPointer as PomResultCode;
+        unsafe {
+            pom_play(null_mut(), 440.0, 1.0);
+            pom_set_start(null_mut(), Duration::ZERO.into());
+            pom_set_frequency(null_mut(), 440.0);
+            pom_release(null_mut());
+            pom_cut(null_mut());
+            pom_reset_phase(null_mut());
+            pom_play_locked(null(), 440.0, 1.0);
+            pom_release_locked(null());
+            pom_clear_pcm_bank(null_mut());
+            pom_destroy_synth(null_mut());
+            pom_destroy_shared(null());
+            pom_destroy_pcm_bank(null_mut());
+
+            assert_eq!(
+                pom_sample(null_mut(), null(), Duration::ZERO.into(), 0.0),
+                0.0
+            );
+            assert_eq!(
+                pom_sample_locked(null(), null(), Duration::ZERO.into(), 0.0),
+                0.0
+            );
+            assert_eq!(
+                pom_sample_opt(null_mut(), null(), Duration::ZERO.into(), 0.0, None),
+                0
+            );
+            assert_eq!(pom_is_active(null()), 0);
+            assert_eq!(pom_current_volume(null(), Duration::ZERO.into()), 0.0);
+            assert_eq!(pom_remaining(null()).to_rust(), Duration::ZERO);
+            assert_eq!(pom_get_phase(null(), None), null_pointer);
+            assert_eq!(pom_stacker_operator_count(null(), None), null_pointer);
+            assert_eq!(pom_clone_synth(None, null()), null_pointer);
+            assert_eq!(pom_remove_pcm_sample(null_mut(), 0), null_pointer);
+            let interval = PomDuration::from(Duration::from_secs(1) / 48000);
+            let format = PomSampleFormat::F64 as c_int;
+            let mut output = [0.0f64; 4];
+            let data = output.as_mut_ptr().cast();
+            assert_eq!(
+                pom_fill(
+                    null_mut(),
+                    null(),
+                    Duration::ZERO.into(),
+                    interval,
+                    data,
+                    4,
+                    format,
+                    0.0
+                ),
+                null_pointer
+            );
+            let synth = send_sine(440.0, 1.0);
+            let interval = PomDuration::from(Duration::from_secs(1) / 48000);
+            assert_eq!(
+                pom_fill(
+                    synth,
+                    null(),
+                    Duration::ZERO.into(),
+                    interval,
+                    null_mut(),
+                    4,
+                    format,
+                    0.0
+                ),
+                null_pointer
+            );
+            pom_destroy_synth(synth);
+        }
+    }
+
+    #[test]
+    fn limiters_keep_loud_synths_in_range() {
+        let interval = Duration::from_secs(1) / 48000;
+        // a sine three times louder than full scale
+        let fill_limited = |limiter: c_int| {
+            let synth = send_sine(440.0, 3.0);
+            let mut output = vec![0.0f64; 480];
+            let result = unsafe {
+                pom_fill_limited(
+                    synth,
+                    std::ptr::null(),
+                    Duration::ZERO.into(),
+                    interval.into(),
+                    output.as_mut_ptr().cast(),
+                    output.len() as u64,
+                    PomSampleFormat::F64 as c_int,
+                    0.0,
+                    limiter,
+                )
+            };
+            unsafe { drop(take_pom_from_ffi(synth)) };
+            (result, output)
+        };
+        let (result, unlimited) = fill_limited(PomLimiter::None as c_int);
+        assert_eq!(result, PomResult::Success as PomResultCode);
+        assert!(unlimited.iter().any(|x| x.abs() > 2.9));
+
+        let (result, brickwall) = fill_limited(PomLimiter::Brickwall as c_int);
+        assert_eq!(result, PomResult::Success as PomResultCode);
+        for (limited, x) in brickwall.iter().zip(&unlimited) {
+            assert_eq!(*limited, x.clamp(-1.0, 1.0));
+        }
+
+        let (result, soft_clipped) = fill_limited(PomLimiter::SoftClip as c_int);
+        assert_eq!(result, PomResult::Success as PomResultCode);
+        for (limited, x) in soft_clipped.iter().zip(&unlimited) {
+            assert!(limited.abs() < 1.0);
+            assert!((limited - x.tanh()).abs() < 1e-12);
+        }
+
+        let (result, _) = fill_limited(3);
+        assert_eq!(result, PomResult::InvalidInput as PomResultCode);
+    }
 }
@@ -1,51 +1,376 @@
 #![feature(bigint_helper_methods)]
+#![no_std]
 
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(feature = "analysis")]
+pub mod analysis;
+#[cfg(feature = "std")]
 mod ffi;
 
-use std::{collections::HashMap, f64::consts::TAU, time::Duration};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use core::{
+    any::Any,
+    borrow::Borrow,
+    f64::consts::{PI, TAU},
+    time::Duration,
+};
 
+#[cfg(feature = "std")]
 use decent::{Decodable, Encodable};
+#[cfg(feature = "std")]
 use decent_macros::Binary;
+#[cfg(not(any(feature = "std", test)))]
+use math::Float;
 
 /// A looser definition of [`Duration`]. Every "second" is instead a period of a waveform.
 /// Invaluable for fixed-point time math.
 pub type Period = Duration;
 pub type SampleID = u64;
 
-/// Some time utilities used internally.
+/// The amount of noise values generated per period by [`Waveform::Noise`].
+pub const NOISE_STEPS_PER_PERIOD: f64 = 64.0;
+/// The amount of octave rows summed by pink [`Waveform::Noise`].
+pub const PINK_NOISE_OCTAVES: u32 = 8;
+/// Envelope levels below this are treated as silence, ending decaying notes
+/// instead of producing slow denormal floats forever.
+pub const INAUDIBLE_LEVEL: f64 = 1e-9;
+/// How long an [`Operator`] retriggered while still sounding crossfades from its previous waveform to the restarted one,
+/// so restarting the phase doesn't make the output jump.
+pub const RETRIGGER_CROSSFADE: Duration = Duration::from_millis(5);
+
+/// The floating-point functions that `core` lacks, computed with `libm` when the crate is built without `std`.
+/// With `std`, the inherent methods of `f64` take precedence, so this is only imported without it.
+#[cfg(not(any(feature = "std", test)))]
+mod math {
+    pub trait Float {
+        fn sin(self) -> Self;
+        fn cos(self) -> Self;
+        fn sin_cos(self) -> (Self, Self)
+        where
+            Self: Sized;
+        fn tan(self) -> Self;
+        fn tanh(self) -> Self;
+        fn exp(self) -> Self;
+        fn log2(self) -> Self;
+        fn powf(self, n: Self) -> Self;
+        fn powi(self, n: i32) -> Self;
+        fn floor(self) -> Self;
+        fn hypot(self, other: Self) -> Self;
+        fn rem_euclid(self, rhs: Self) -> Self;
+    }
+    impl Float for f64 {
+        fn sin(self) -> f64 {
+            libm::sin(self)
+        }
+        fn cos(self) -> f64 {
+            libm::cos(self)
+        }
+        fn sin_cos(self) -> (f64, f64) {
+            libm::sincos(self)
+        }
+        fn tan(self) -> f64 {
+            libm::tan(self)
+        }
+        fn tanh(self) -> f64 {
+            libm::tanh(self)
+        }
+        fn exp(self) -> f64 {
+            libm::exp(self)
+        }
+        fn log2(self) -> f64 {
+            libm::log2(self)
+        }
+        fn powf(self, n: f64) -> f64 {
+            libm::pow(self, n)
+        }
+        fn powi(self, n: i32) -> f64 {
+            libm::pow(self, n as f64)
+        }
+        fn floor(self) -> f64 {
+            libm::floor(self)
+        }
+        fn hypot(self, other: f64) -> f64 {
+            libm::hypot(self, other)
+        }
+        fn rem_euclid(self, rhs: f64) -> f64 {
+            let remainder = self % rhs;
+            if remainder < 0.0 {
+                remainder + rhs.abs()
+            } else {
+                remainder
+            }
+        }
+    }
+}
+
+/// Time utilities used throughout the crate, exact to the nanosecond where possible.
 pub mod time {
-    use std::time::Duration;
+    use core::time::Duration;
 
+    /// The number of nanoseconds in a second.
     pub const NANOS_PER_SEC: u32 = 1_000_000_000;
+    /// Wraps `duration` into `[0, max)` using the remainder of their nanosecond counts,
+    /// so a `duration` less than `max` is unchanged, and one equal to `max` wraps to zero.
+    ///
+    /// Returns zero if `max` is zero.
     pub fn wrap_duration(duration: Duration, max: Duration) -> Duration {
-        let nanos = duration.as_nanos() % max.as_nanos();
+        let nanos = duration.as_nanos().checked_rem(max.as_nanos()).unwrap_or(0);
         Duration::new(
             (nanos / NANOS_PER_SEC as u128) as u64,
             (nanos % NANOS_PER_SEC as u128) as u32,
         )
     }
+    /// Multiplies `lhs` by `rhs`, saturating to [`Duration::MAX`] if the result is too large to represent.
+    ///
+    /// Returns zero if `rhs` is negative or NaN, or if the product is NaN (such as zero times infinity).
     pub fn duration_saturating_mul_f64(lhs: Duration, rhs: f64) -> Duration {
-        if rhs < 0.0 {
+        let product = rhs * lhs.as_secs_f64();
+        if rhs < 0.0 || product.is_nan() {
             return Duration::ZERO;
         }
-        match Duration::try_from_secs_f64(rhs * lhs.as_secs_f64()) {
+        match Duration::try_from_secs_f64(product) {
             Ok(duration) => duration,
             Err(_) => Duration::MAX,
         }
     }
+    /// Returns the time of the `count`th sample after `start`, where samples are `interval` apart.
+    ///
+    /// This is exact, so filling a buffer in several blocks, each starting at `advance(start, interval, offset)`
+    /// samples every synth at exactly the same times as filling it all at once.
+    pub fn advance(start: Duration, interval: Duration, count: u64) -> Duration {
+        let nanos = interval.as_nanos().saturating_mul(count as u128);
+        match u64::try_from(nanos / NANOS_PER_SEC as u128) {
+            Ok(secs) => {
+                start.saturating_add(Duration::new(secs, (nanos % NANOS_PER_SEC as u128) as u32))
+            }
+            Err(_) => Duration::MAX,
+        }
+    }
+}
+
+/// Conversions between MIDI note numbers and frequencies, in twelve-tone equal temperament.
+pub mod pitch {
+    #[cfg(not(any(feature = "std", test)))]
+    use crate::math::Float;
+
+    /// The frequency of A4 (MIDI note 69) in standard tuning, in hertz.
+    pub const A4_FREQUENCY: f64 = 440.0;
+    /// The MIDI note number of A4.
+    pub const A4_NOTE: u8 = 69;
+
+    /// Converts a MIDI note number to a frequency in hertz, with A4 at 440Hz.
+    pub fn midi_to_frequency(note: u8) -> f64 {
+        midi_to_frequency_tuned(note, A4_FREQUENCY)
+    }
+    /// Converts a MIDI note number to a frequency in hertz, with A4 at `a4_frequency`.
+    pub fn midi_to_frequency_tuned(note: u8, a4_frequency: f64) -> f64 {
+        a4_frequency * 2f64.powf((note as f64 - A4_NOTE as f64) / 12.0)
+    }
+    /// Converts a frequency in hertz to a fractional MIDI note number, with A4 at 440Hz.
+    pub fn frequency_to_midi(frequency: f64) -> f64 {
+        frequency_to_midi_tuned(frequency, A4_FREQUENCY)
+    }
+    /// Converts a frequency in hertz to a fractional MIDI note number, with A4 at `a4_frequency`.
+    pub fn frequency_to_midi_tuned(frequency: f64, a4_frequency: f64) -> f64 {
+        A4_NOTE as f64 + 12.0 * (frequency / a4_frequency).log2()
+    }
+}
+
+/// Conversions from musical time, in beats and bars at a tempo, to [`Duration`]s.
+///
+/// A beat is always a quarter note, regardless of time signature.
+pub mod tempo {
+    use core::time::Duration;
+
+    use crate::time;
+
+    /// The length of a note, relative to a whole note.
+    #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum NoteValue {
+        Whole,
+        Half,
+        Quarter,
+        Eighth,
+        Sixteenth,
+        ThirtySecond,
+        SixtyFourth,
+    }
+    impl NoteValue {
+        /// The length of the note in beats (quarter notes).
+        ///
+        /// Dotted notes are 1.5 times as long, and triplets are 2/3 as long.
+        pub fn beats(&self) -> f64 {
+            match self {
+                NoteValue::Whole => 4.0,
+                NoteValue::Half => 2.0,
+                NoteValue::Quarter => 1.0,
+                NoteValue::Eighth => 0.5,
+                NoteValue::Sixteenth => 0.25,
+                NoteValue::ThirtySecond => 0.125,
+                NoteValue::SixtyFourth => 0.0625,
+            }
+        }
+    }
+
+    /// A tempo in beats per minute.
+    ///
+    /// ```
+    /// use pommel::tempo::{NoteValue, Tempo};
+    /// use std::time::Duration;
+    ///
+    /// let tempo = Tempo::new(120.0);
+    /// assert_eq!(tempo.note_value_to_duration(NoteValue::Quarter), Duration::from_millis(500));
+    /// assert_eq!(tempo.bars_to_duration(1.0, 4.0), Duration::from_secs(2));
+    /// ```
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub struct Tempo {
+        pub bpm: f64,
+    }
+    impl Tempo {
+        pub fn new(bpm: f64) -> Self {
+            Self { bpm }
+        }
+        /// The duration of `beats` beats, saturating to [`Duration::MAX`].
+        /// Negative or NaN lengths are zero, and a tempo of zero makes every nonzero length [`Duration::MAX`].
+        pub fn beats_to_duration(&self, beats: f64) -> Duration {
+            time::duration_saturating_mul_f64(Duration::from_secs(1), beats * 60.0 / self.bpm)
+        }
+        /// The duration of `bars` bars of `beats_per_bar` beats each, such as 4 in 4/4 time.
+        pub fn bars_to_duration(&self, bars: f64, beats_per_bar: f64) -> Duration {
+            self.beats_to_duration(bars * beats_per_bar)
+        }
+        pub fn note_value_to_duration(&self, note_value: NoteValue) -> Duration {
+            self.beats_to_duration(note_value.beats())
+        }
+        /// The frequency in hertz that repeats once every `note_value`, such as for tempo-synced LFO rates.
+        pub fn note_value_to_frequency(&self, note_value: NoteValue) -> f64 {
+            self.bpm / (60.0 * note_value.beats())
+        }
+    }
+}
+
+/// Deterministic pseudo-random utilities used internally.
+pub mod random {
+    /// The SplitMix64 mixing function; a fast, stateless hash with good avalanche behaviour.
+    pub fn splitmix64(x: u64) -> u64 {
+        let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Produces a value in [0, 1) from a seed and an index. The same inputs always produce the same output.
+    pub fn unit(seed: u64, index: u64) -> f64 {
+        (splitmix64(splitmix64(seed) ^ index) >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// Produces a value in [-1, 1) from a seed and an index. The same inputs always produce the same output.
+    pub fn signed_unit(seed: u64, index: u64) -> f64 {
+        unit(seed, index) * 2.0 - 1.0
+    }
+}
+
+/// How a [`Sample`] computes values between its PCM samples.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum Interpolation {
+    /// Uses the PCM sample at or before the position.
+    #[default]
+    Nearest,
+    /// Linearly interpolates between the two surrounding PCM samples.
+    Linear,
+    /// Interpolates through the four surrounding PCM samples with a Catmull-Rom spline.
+    Cubic,
+}
+
+/// Maps `x` from `min..max` to -1..1, without clamping.
+pub fn normalise(x: f64, min: f64, max: f64) -> f64 {
+    2.0 * (x - min) / (max - min) - 1.0
+}
+
+/// PCM data, kept in the format it was provided in and converted to [-1, 1] when read.
+///
+/// Integer formats are normalised from their full range; float formats are used as-is.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum PcmData {
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+impl Default for PcmData {
+    fn default() -> Self {
+        PcmData::F64(vec![])
+    }
+}
+impl From<Vec<f64>> for PcmData {
+    fn from(value: Vec<f64>) -> Self {
+        PcmData::F64(value)
+    }
+}
+impl PcmData {
+    pub fn len(&self) -> usize {
+        match self {
+            PcmData::U8(data) => data.len(),
+            PcmData::I16(data) => data.len(),
+            PcmData::I32(data) => data.len(),
+            PcmData::F32(data) => data.len(),
+            PcmData::F64(data) => data.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Reads and converts the PCM sample at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<f64> {
+        Some(match self {
+            PcmData::U8(data) => {
+                normalise(*data.get(index)? as f64, u8::MIN as f64, u8::MAX as f64)
+            }
+            PcmData::I16(data) => {
+                normalise(*data.get(index)? as f64, i16::MIN as f64, i16::MAX as f64)
+            }
+            PcmData::I32(data) => {
+                normalise(*data.get(index)? as f64, i32::MIN as f64, i32::MAX as f64)
+            }
+            PcmData::F32(data) => *data.get(index)? as f64,
+            PcmData::F64(data) => *data.get(index)?,
+        })
+    }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Binary)]
+/// PCM data that plays back at a speed proportional to the frequency it is played at.
+///
+/// Played at its [natural frequency](Sample::natural_frequency), a sample plays at its original speed;
+/// at other frequencies, it is sped up or slowed down by the ratio between the two.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub struct Sample {
+    /// The sample rate the PCM data was recorded at.
+    pub samples_per_second: f64,
     pub samples_per_period: f64,
+    /// Where playback starts in the PCM data, skipping any silence or pre-roll before it.
+    /// The loop point is still measured from the start of the data.
+    pub start_offset: Period,
     pub loop_point: Period,
     pub loop_duration: Period,
-    pub pcm_data: Vec<f64>,
+    /// How long before the end of the loop to start blending in the audio leading up to the loop point,
+    /// which the loop then seamlessly wraps back into. This hides clicks when the ends of the loop don't match.
+    /// Limited to the loop duration and the audio available before the loop point. Zero disables crossfading.
+    pub crossfade: Period,
+    pub pcm_data: PcmData,
+    pub interpolation: Interpolation,
+    /// Plays the PCM data backwards. All positions, including the start offset and loop region,
+    /// are measured from the end of the data, as if it were reversed.
+    pub reverse: bool,
 }
 impl Sample {
     /// Converts floating-point seconds into period locations.
     pub fn new(
-        data: Vec<f64>,
+        data: impl Into<PcmData>,
         samples_per_second: f64,
         samples_per_period: f64,
         loop_point_secs: f64,
@@ -55,46 +380,320 @@ impl Sample {
         let loop_point_periods = Period::from_secs_f64(loop_point_secs * periods_per_second);
         let loop_duration_periods = Period::from_secs_f64(loop_duration_secs * periods_per_second);
         Self {
+            samples_per_second,
             samples_per_period,
+            start_offset: Period::ZERO,
             loop_point: loop_point_periods,
             loop_duration: loop_duration_periods,
-            pcm_data: data,
+            crossfade: Period::ZERO,
+            pcm_data: data.into(),
+            interpolation: Interpolation::Nearest,
+            reverse: false,
+        }
+    }
+    /// Like [`Sample::new`], but derives `samples_per_period` from the frequency that the sample
+    /// should play at its original speed, such as the pitch of the recorded note.
+    pub fn with_natural_frequency(
+        data: impl Into<PcmData>,
+        samples_per_second: f64,
+        natural_frequency: f64,
+        loop_point_secs: f64,
+        loop_duration_secs: f64,
+    ) -> Self {
+        Self::new(
+            data,
+            samples_per_second,
+            samples_per_second / natural_frequency,
+            loop_point_secs,
+            loop_duration_secs,
+        )
+    }
+    /// Skips the first `start_offset_secs` seconds of the PCM data.
+    pub fn with_start_offset(self, start_offset_secs: f64) -> Self {
+        Self {
+            start_offset: Period::from_secs_f64(start_offset_secs * self.natural_frequency()),
+            ..self
+        }
+    }
+    /// The frequency at which the sample plays at its original speed.
+    pub fn natural_frequency(&self) -> f64 {
+        self.samples_per_second / self.samples_per_period
+    }
+    /// The amount of PCM samples in the data.
+    pub fn len(&self) -> usize {
+        self.pcm_data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pcm_data.is_empty()
+    }
+    /// The length of the data in periods, the unit of the start offset and loop region.
+    pub fn duration(&self) -> Period {
+        Period::try_from_secs_f64(self.len() as f64 / self.samples_per_period)
+            .unwrap_or(Period::MAX)
+    }
+    /// Whether the loop region ends within the PCM data, to the nearest PCM sample.
+    /// A loop region past the end of the data plays silence. Samples without a loop always fit.
+    pub fn loop_fits(&self) -> bool {
+        if self.loop_duration.is_zero() {
+            return true;
+        }
+        let loop_end = self.loop_point.saturating_add(self.loop_duration);
+        time::duration_saturating_mul_f64(loop_end, self.samples_per_period).as_secs()
+            <= self.len() as u64
+    }
+    /// How much faster than its original speed the sample plays at `frequency`.
+    pub fn playback_rate(&self, frequency: f64) -> f64 {
+        frequency / self.natural_frequency()
+    }
+    /// Gets the PCM sample at `index`, counting from the end of the data if the sample is reversed.
+    fn data_at(&self, index: usize) -> Option<f64> {
+        if self.reverse {
+            let index = self.pcm_data.len().checked_sub(index.checked_add(1)?)?;
+            self.pcm_data.get(index)
+        } else {
+            self.pcm_data.get(index)
         }
     }
+    /// Gets the PCM sample at `index`, wrapping indices past the end of the loop back into it.
+    fn pcm_at(&self, index: usize) -> f64 {
+        let samples_at = |period: Period| {
+            time::duration_saturating_mul_f64(period, self.samples_per_period).as_secs() as usize
+        };
+        let loop_start = samples_at(self.loop_point);
+        let loop_end = samples_at(self.loop_point.saturating_add(self.loop_duration));
+        let index = if loop_end > loop_start && index >= loop_end {
+            loop_start + (index - loop_end) % (loop_end - loop_start)
+        } else {
+            index
+        };
+        self.data_at(index).unwrap_or(0.0)
+    }
     pub fn get(&self, mut period: Period, phase_offset: f64) -> f64 {
         if phase_offset < 0.0 {
-            let negative_phase_offset_period = Period::from_secs_f64(-phase_offset);
+            let negative_phase_offset_period =
+                Period::try_from_secs_f64(-phase_offset).unwrap_or(Period::MAX);
             if negative_phase_offset_period > period {
                 return 0.0;
             }
             period = period.saturating_sub(negative_phase_offset_period);
         } else {
-            period = period.saturating_add(Period::from_secs_f64(phase_offset));
+            period = period
+                .saturating_add(Period::try_from_secs_f64(phase_offset).unwrap_or(Period::MAX));
         }
+        period = period.saturating_add(self.start_offset);
         period = if period < self.loop_point {
             period
         } else {
             time::wrap_duration(period.saturating_sub(self.loop_point), self.loop_duration)
                 .saturating_add(self.loop_point)
         };
-        let sample_index =
-            time::duration_saturating_mul_f64(period, self.samples_per_period).as_secs() as usize;
-        self.pcm_data.get(sample_index).copied().unwrap_or(0.0)
+        let value = self.interpolate(period);
+
+        let crossfade = self.crossfade.min(self.loop_duration).min(self.loop_point);
+        let fade_start = self
+            .loop_point
+            .saturating_add(self.loop_duration)
+            .saturating_sub(crossfade);
+        if crossfade.is_zero() || period < fade_start {
+            return value;
+        }
+        let fade = period.saturating_sub(fade_start).as_secs_f64() / crossfade.as_secs_f64();
+        let lead_in = self.interpolate(period.saturating_sub(self.loop_duration));
+        value + (lead_in - value) * fade
+    }
+    /// Reads the PCM data at `period`, interpolating between samples.
+    fn interpolate(&self, period: Period) -> f64 {
+        let position = time::duration_saturating_mul_f64(period, self.samples_per_period);
+        let sample_index = position.as_secs() as usize;
+        let fraction = position.subsec_nanos() as f64 / time::NANOS_PER_SEC as f64;
+        match self.interpolation {
+            Interpolation::Nearest => self.data_at(sample_index).unwrap_or(0.0),
+            Interpolation::Linear => {
+                let current = self.pcm_at(sample_index);
+                let next = self.pcm_at(sample_index + 1);
+                current + (next - current) * fraction
+            }
+            Interpolation::Cubic => {
+                let previous = match sample_index.checked_sub(1) {
+                    Some(index) => self.pcm_at(index),
+                    None => self.pcm_at(sample_index),
+                };
+                let current = self.pcm_at(sample_index);
+                let next = self.pcm_at(sample_index + 1);
+                let after_next = self.pcm_at(sample_index + 2);
+                // catmull-rom coefficients
+                let a = 0.5 * (3.0 * (current - next) + after_next - previous);
+                let b = previous - 2.5 * current + 2.0 * next - 0.5 * after_next;
+                let c = 0.5 * (next - previous);
+                ((a * fraction + b) * fraction + c) * fraction + current
+            }
+        }
+    }
+    /// Treats the PCM data as a single cycle, linearly interpolating between adjacent samples at `phase`.
+    /// The end of the data wraps around to the start.
+    pub fn get_wavetable(&self, phase: f64) -> f64 {
+        let length = self.pcm_data.len();
+        if length == 0 {
+            return 0.0;
+        }
+        let position = phase.rem_euclid(1.0) * length as f64;
+        let index = (position.floor() as usize).min(length - 1);
+        let fraction = position - index as f64;
+        let current = self.data_at(index).unwrap_or(0.0);
+        let next = self.data_at((index + 1) % length).unwrap_or(0.0);
+        current + (next - current) * fraction
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Binary)]
+/// The map a [`SampleBank`] keeps its samples in: a `HashMap` with the `std` feature, or a `BTreeMap` without it.
+#[cfg(feature = "std")]
+pub type SampleMap = std::collections::HashMap<SampleID, Sample>;
+/// The map a [`SampleBank`] keeps its samples in: a `HashMap` with the `std` feature, or a `BTreeMap` without it.
+#[cfg(not(feature = "std"))]
+pub type SampleMap = alloc::collections::BTreeMap<SampleID, Sample>;
+
+/// IDs below this are stored by [`SampleBank::insert`] in a `Vec` indexed by ID, so playing them doesn't hash.
+pub const DENSE_SAMPLE_IDS: SampleID = 1024;
+
+/// A collection of PCM samples, keyed by [`SampleID`].
+///
+/// Samples are looked up on every frame of a [`Waveform::PCM`] or [`Waveform::Wavetable`],
+/// so [`SampleBank::insert`] keeps IDs below [`DENSE_SAMPLE_IDS`] in `dense`, indexed by ID.
+/// Samples can still be added to `samples` directly, but a sample in `dense` takes precedence over one with the same ID there.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub struct SampleBank {
-    pub samples: HashMap<SampleID, Sample>,
+    pub samples: SampleMap,
+    pub dense: Vec<Option<Sample>>,
+}
+impl SampleBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The sample with the ID `id`, if any.
+    pub fn get(&self, id: SampleID) -> Option<&Sample> {
+        match self.dense.get(id as usize) {
+            Some(Some(sample)) => Some(sample),
+            _ => self.samples.get(&id),
+        }
+    }
+    /// The sample with the ID `id`, if any.
+    pub fn get_mut(&mut self, id: SampleID) -> Option<&mut Sample> {
+        match self.dense.get_mut(id as usize) {
+            Some(Some(sample)) => Some(sample),
+            _ => self.samples.get_mut(&id),
+        }
+    }
+    /// Adds a sample to the bank, returning the sample it replaced.
+    pub fn insert(&mut self, id: SampleID, sample: Sample) -> Option<Sample> {
+        if id >= DENSE_SAMPLE_IDS {
+            return self.samples.insert(id, sample);
+        }
+        let index = id as usize;
+        if self.dense.len() <= index {
+            self.dense.resize(index + 1, None);
+        }
+        self.dense[index]
+            .replace(sample)
+            .or_else(|| self.samples.remove(&id))
+    }
+    /// Removes the sample with the ID `id`, returning it if it was in the bank.
+    pub fn remove(&mut self, id: SampleID) -> Option<Sample> {
+        let dense = self.dense.get_mut(id as usize).and_then(Option::take);
+        let mapped = self.samples.remove(&id);
+        dense.or(mapped)
+    }
+    /// Removes every sample from the bank.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.dense.clear();
+    }
+    /// The IDs of every sample in the bank, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = SampleID> + '_ {
+        let dense = self
+            .dense
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.is_some())
+            .map(|(id, _)| id as SampleID);
+        let mapped = self
+            .samples
+            .keys()
+            .copied()
+            .filter(|&id| !matches!(self.dense.get(id as usize), Some(Some(_))));
+        dense.chain(mapped)
+    }
+    /// Moves every sample of `other` into this bank, resolving samples with the same ID using `on_conflict`.
+    ///
+    /// With [`ConflictPolicy::Error`], this bank is left unchanged if any ID is in both banks,
+    /// and the lowest such ID is returned.
+    pub fn merge(
+        &mut self,
+        other: SampleBank,
+        on_conflict: ConflictPolicy,
+    ) -> Result<(), SampleConflict> {
+        if on_conflict == ConflictPolicy::Error
+            && let Some(id) = other.ids().filter(|&id| self.get(id).is_some()).min()
+        {
+            return Err(SampleConflict { id });
+        }
+        // dense samples go last, as they take precedence over mapped ones with the same ID
+        let dense = other
+            .dense
+            .into_iter()
+            .enumerate()
+            .filter_map(|(id, sample)| Some((id as SampleID, sample?)));
+        for (id, sample) in other.samples.into_iter().chain(dense) {
+            if on_conflict == ConflictPolicy::Skip && self.get(id).is_some() {
+                continue;
+            }
+            self.insert(id, sample);
+        }
+        Ok(())
+    }
+}
+
+/// Banks are equal if they have the same samples under the same IDs, whether those are in `samples` or `dense`.
+impl PartialEq for SampleBank {
+    fn eq(&self, other: &Self) -> bool {
+        self.ids().count() == other.ids().count()
+            && self.ids().all(|id| self.get(id) == other.get(id))
+    }
+}
+
+/// How [`SampleBank::merge`] handles a sample whose ID is already in the bank.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConflictPolicy {
+    /// Replaces the sample in the bank with the merged sample.
+    #[default]
+    Overwrite,
+    /// Keeps the sample in the bank, discarding the merged sample.
+    Skip,
+    /// Fails without changing the bank.
+    Error,
+}
+
+/// A sample ID found in both banks of a [`SampleBank::merge`] with [`ConflictPolicy::Error`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SampleConflict {
+    pub id: SampleID,
+}
+impl core::fmt::Display for SampleConflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "sample {} is in both banks", self.id)
+    }
 }
+impl core::error::Error for SampleConflict {}
 
 /// A waveform, with a phase wrapped to be within [0, 1).
-#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Binary)]
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub enum Waveform {
     #[default]
     /// A sinusoid.
     Sine,
     /// A pulse wave with a given duty cycle.
+    /// The wave is high (1) for the first `duty_cycle` fraction of each period, and low (-1) for the rest.
     Pulse { duty_cycle: f64 },
     /// A triangle wave.
     Triangle,
@@ -104,33 +703,226 @@ pub enum Waveform {
     InvertedSawtooth,
     /// A PCM sample from a [`SampleBank`].
     PCM(SampleID),
+    /// A single-cycle wavetable from a [`SampleBank`], indexed by phase rather than time.
+    /// The sample's entire PCM data is one period, and is linearly interpolated.
+    Wavetable(SampleID),
+    /// Deterministic noise, stepping [`NOISE_STEPS_PER_PERIOD`] times per period.
+    /// Sampling the same period with the same seed always produces the same value.
+    ///
+    /// When `pink` is set, [`PINK_NOISE_OCTAVES`] rows of white noise, each held twice as long as the last,
+    /// are averaged (Voss-McCartney), producing a spectrum that falls off at roughly 3dB per octave.
+    Noise { seed: u64, pink: bool },
 
-    /// A constant, unchanging value.
+    /// A constant, unchanging value. Non-finite values produce 0.
     Constant(f64),
     /// Transforms the phase domain of `base` to be [0, `waveform_active_percent`), with phases outside of the domain returning 0.
+    /// A percentage of 0 or less (or NaN) is silent, and one above 1 stretches `base` without ever reaching its end.
     Thin {
         base: Box<Waveform>,
         waveform_active_percent: f64,
     },
-    /// Any phase greater than `waveform_active_percent` will produce a value of 0.
+    /// Any phase at or past `waveform_active_percent` will produce a value of 0,
+    /// so a percentage of 0 or less (or NaN) is silent, and one of 1 or more passes `base` through unchanged.
     Cut {
         base: Box<Waveform>,
         waveform_active_percent: f64,
     },
     /// Computes the absolute value of the output of a waveform.
     Absolute(Box<Waveform>),
+    /// Casio-style phase distortion. The phase is warped through two linear segments that meet at a knee,
+    /// which maps to the middle of `base`'s period, compressing one side and stretching the other.
+    ///
+    /// The knee moves from 0.5 towards `pivot` as `amount` goes from 0 to 1, so an `amount` of 0 is transparent.
+    PhaseDistort {
+        base: Box<Waveform>,
+        amount: f64,
+        pivot: f64,
+    },
+    /// Additively synthesises `base` from its harmonic series, up to and including `max_harmonic`,
+    /// which removes the aliasing that the naive formulas produce at high frequencies.
+    ///
+    /// Only [`Waveform::Sine`], [`Waveform::Pulse`], [`Waveform::Triangle`], [`Waveform::Sawtooth`],
+    /// and [`Waveform::InvertedSawtooth`] are band-limited; other waveforms are sampled as-is.
+    /// See [`Waveform::band_limited`] to derive `max_harmonic` from a frequency and sample rate.
+    BandLimited {
+        base: Box<Waveform>,
+        max_harmonic: u32,
+    },
+    /// A triangle wave with its corners smoothed by polynomial band-limited ramps (PolyBLAMP),
+    /// the integral of a PolyBLEP-corrected square wave, which suppresses most aliasing while keeping the amplitude of a triangle.
+    ///
+    /// The rounding depends on how far the phase advances per sample, which an [`Operator`] derives from its
+    /// current frequency and the time between samples. Without it, such as through [`Waveform::sample`], this is a naive triangle.
+    AntialiasedTriangle,
+    /// Soft-clips the output of `base` with `tanh(drive * x) / tanh(drive)`, which keeps ±1 in place
+    /// while compressing anything beyond it towards ±1. A `drive` of 0 leaves `base` unchanged.
+    Saturate { base: Box<Waveform>, drive: f64 },
+    /// Crossfades between two waveforms, producing `a * (1 - blend) + b * blend`, with `blend` clamped to [0, 1].
+    Mix {
+        a: Box<Waveform>,
+        b: Box<Waveform>,
+        blend: f64,
+    },
+    /// Scales and shifts `base`, producing `base * gain + bias`.
+    /// This covers inversion (a `gain` of -1), attenuation, and DC offsets. Non-finite results produce 0.
+    Offset {
+        base: Box<Waveform>,
+        gain: f64,
+        bias: f64,
+    },
+    /// Hard-clips `base` to between `min` and `max`, flattening the tops of waves with a hard knee,
+    /// unlike [`Waveform::Saturate`]. If `min` is greater than `max`, they are swapped. A NaN bound is ignored.
+    Clamp {
+        base: Box<Waveform>,
+        min: f64,
+        max: f64,
+    },
 }
 impl Waveform {
+    /// Wraps `base` in a [`Waveform::BandLimited`] that keeps every harmonic below the Nyquist frequency
+    /// when played at `frequency` with a sample rate of `sample_rate`.
+    pub fn band_limited(base: Waveform, frequency: f64, sample_rate: f64) -> Waveform {
+        let nyquist = sample_rate / 2.0;
+        let max_harmonic = if frequency > 0.0 {
+            (nyquist / frequency).floor().min(u32::MAX as f64) as u32
+        } else {
+            0
+        };
+        Waveform::BandLimited {
+            base: Box::new(base),
+            max_harmonic,
+        }
+    }
+    /// Returns whether any part of the waveform depends on the absolute period rather than just the phase,
+    /// meaning the period must not be wrapped before [`Waveform::sample`] is called.
+    ///
+    /// This is true if the tree contains a [`Waveform::PCM`] sample, or [`Waveform::Noise`], which is indexed by time.
+    /// Otherwise, a host may wrap the period to a single cycle without changing the output.
+    ///
+    /// ```
+    /// # use pommel::Waveform;
+    /// let pcm = Waveform::Mix {
+    ///     a: Box::new(Waveform::Sine),
+    ///     b: Box::new(Waveform::Thin {
+    ///         base: Box::new(Waveform::Absolute(Box::new(Waveform::PCM(0)))),
+    ///         waveform_active_percent: 0.5,
+    ///     }),
+    ///     blend: 0.5,
+    /// };
+    /// assert!(pcm.uses_absolute_time());
+    ///
+    /// let simple = Waveform::Saturate {
+    ///     base: Box::new(Waveform::Cut {
+    ///         base: Box::new(Waveform::Wavetable(0)),
+    ///         waveform_active_percent: 0.5,
+    ///     }),
+    ///     drive: 2.0,
+    /// };
+    /// assert!(!simple.uses_absolute_time());
+    /// ```
+    pub fn uses_absolute_time(&self) -> bool {
+        match self {
+            Waveform::PCM(_) | Waveform::Noise { .. } => true,
+            Waveform::Thin { base, .. }
+            | Waveform::Cut { base, .. }
+            | Waveform::Absolute(base)
+            | Waveform::PhaseDistort { base, .. }
+            | Waveform::BandLimited { base, .. }
+            | Waveform::Saturate { base, .. }
+            | Waveform::Offset { base, .. }
+            | Waveform::Clamp { base, .. } => base.uses_absolute_time(),
+            Waveform::Mix { a, b, .. } => a.uses_absolute_time() || b.uses_absolute_time(),
+            Waveform::Sine
+            | Waveform::Pulse { .. }
+            | Waveform::Triangle
+            | Waveform::Sawtooth
+            | Waveform::InvertedSawtooth
+            | Waveform::Wavetable(_)
+            | Waveform::Constant(_)
+            | Waveform::AntialiasedTriangle => false,
+        }
+    }
+    /// Sums the harmonic series of the waveform up to `max_harmonic`,
+    /// or returns `None` if the waveform has no known series.
+    fn harmonic_series(&self, phase: f64, max_harmonic: u32) -> Option<f64> {
+        let harmonics = 1..=max_harmonic as u64;
+        Some(match self {
+            Waveform::Sine => {
+                if max_harmonic >= 1 {
+                    (phase * TAU).sin()
+                } else {
+                    0.0
+                }
+            }
+            Waveform::Pulse { duty_cycle } => {
+                let duty_cycle = unit_clamp(*duty_cycle);
+                let series: f64 = harmonics
+                    .map(|n| n as f64)
+                    .map(|n| {
+                        (PI * n * duty_cycle).sin() * (TAU * n * (phase - duty_cycle / 2.0)).cos()
+                            / n
+                    })
+                    .sum();
+                2.0 * duty_cycle - 1.0 + 4.0 / PI * series
+            }
+            Waveform::Triangle => {
+                let series: f64 = harmonics
+                    .step_by(2)
+                    .map(|n| n as f64)
+                    .map(|n| (TAU * n * phase).cos() / (n * n))
+                    .sum();
+                -8.0 / (PI * PI) * series
+            }
+            Waveform::Sawtooth | Waveform::InvertedSawtooth => {
+                let series: f64 = harmonics
+                    .map(|n| n as f64)
+                    .map(|n| (TAU * n * phase).sin() / n)
+                    .sum();
+                let sawtooth = -2.0 / PI * series;
+                if let Waveform::InvertedSawtooth = self {
+                    -sawtooth
+                } else {
+                    sawtooth
+                }
+            }
+            _ => return None,
+        })
+    }
     /// `period` should preferably *not* be wrapped before being passed into this function;
     /// PCM samples will not work properly.
-    pub fn sample(&self, samples: &SampleBank, mut period: Period, phase_offset: f64) -> f64 {
+    ///
+    /// This never panics, and never produces NaN or infinite values unless a PCM sample contains them;
+    /// out-of-range parameters are clamped or treated as described on each variant, and a non-finite `phase_offset` is treated as 0.
+    ///
+    /// `phase_offset` is in cycles and may be any size, such as from FM with a modulation index above 1.
+    /// Periodic waveforms only depend on its fractional part, so it is wrapped before being added to keep precision,
+    /// but aperiodic waveforms (PCM and noise) and waveforms wrapping them receive it, and the period, unwrapped.
+    ///
+    /// [`Waveform::AntialiasedTriangle`] is not antialiased by this; see [`Waveform::sample_with_increment`].
+    pub fn sample(&self, samples: &SampleBank, period: Period, phase_offset: f64) -> f64 {
+        self.sample_with_increment(samples, period, phase_offset, 0.0)
+    }
+    /// Like [`Waveform::sample`], for a waveform being sampled every `phase_increment` periods,
+    /// which is its frequency divided by the sample rate. Antialiased waveforms use this to smooth their corners.
+    pub fn sample_with_increment(
+        &self,
+        samples: &SampleBank,
+        period: Period,
+        phase_offset: f64,
+        phase_increment: f64,
+    ) -> f64 {
+        let phase_offset = if phase_offset.is_finite() {
+            phase_offset
+        } else {
+            0.0
+        };
         let monotonic_period = period;
-        period = Period::from_nanos(period.subsec_nanos() as u64);
-        let phase = (period.as_secs_f64() + phase_offset.rem_euclid(1.0)).rem_euclid(1.0);
+        let cycle_phase = period.subsec_nanos() as f64 / time::NANOS_PER_SEC as f64;
+        let phase = (cycle_phase + phase_offset.rem_euclid(1.0)).rem_euclid(1.0);
         match self {
             Waveform::Sine => (phase * TAU).sin(),
             Waveform::Pulse { duty_cycle } => {
-                if phase > *duty_cycle {
+                if phase < *duty_cycle {
                     1.0
                 } else {
                     -1.0
@@ -143,86 +935,619 @@ impl Waveform {
                     3.0 - phase * 4.0
                 }
             }
+            Waveform::AntialiasedTriangle => {
+                let triangle = if phase < 0.5 {
+                    phase * 4.0 - 1.0
+                } else {
+                    3.0 - phase * 4.0
+                };
+                // the slope changes by 8 per period at each corner, or by `8 * phase_increment` per sample
+                let step = 8.0 * unit_clamp(phase_increment).min(0.5);
+                triangle
+                    + step
+                        * (poly_blamp(phase, phase_increment)
+                            - poly_blamp(phase - 0.5, phase_increment))
+            }
             Waveform::Sawtooth => phase * 2.0 - 1.0,
             Waveform::InvertedSawtooth => phase * -2.0 + 1.0,
             Waveform::PCM(sample_id) => {
-                let Some(sample) = samples.samples.get(sample_id) else {
+                let Some(sample) = samples.get(*sample_id) else {
                     return 0.0;
                 };
                 sample.get(monotonic_period, phase_offset)
             }
+            Waveform::Wavetable(sample_id) => {
+                let Some(sample) = samples.get(*sample_id) else {
+                    return 0.0;
+                };
+                sample.get_wavetable(phase)
+            }
+            Waveform::Noise { seed, pink } => {
+                let position = monotonic_period.as_secs_f64() + phase_offset;
+                let index = (position * NOISE_STEPS_PER_PERIOD).floor() as i64 as u64;
+                if *pink {
+                    let sum: f64 = (0..PINK_NOISE_OCTAVES)
+                        .map(|octave| {
+                            random::signed_unit(seed.wrapping_add(octave as u64), index >> octave)
+                        })
+                        .sum();
+                    sum / PINK_NOISE_OCTAVES as f64
+                } else {
+                    random::signed_unit(*seed, index)
+                }
+            }
 
-            Waveform::Constant(value) => *value,
+            Waveform::Constant(value) => {
+                if value.is_finite() {
+                    *value
+                } else {
+                    0.0
+                }
+            }
             Waveform::Thin {
                 base,
                 waveform_active_percent,
             } => {
-                if phase > *waveform_active_percent {
-                    0.0
-                } else {
-                    base.sample(
+                // a percentage that is NaN or not positive never passes, so the division is always finite
+                if phase < *waveform_active_percent {
+                    // the phase offset has already been applied
+                    base.sample_with_increment(
                         samples,
                         Period::from_secs_f64(phase / *waveform_active_percent),
-                        phase_offset,
+                        0.0,
+                        phase_increment,
                     )
+                } else {
+                    0.0
                 }
             }
             Waveform::Cut {
                 base,
                 waveform_active_percent,
             } => {
-                if phase > *waveform_active_percent {
+                if phase < *waveform_active_percent {
+                    base.sample_with_increment(
+                        samples,
+                        monotonic_period,
+                        phase_offset,
+                        phase_increment,
+                    )
+                } else {
+                    0.0
+                }
+            }
+            Waveform::Absolute(base) => base
+                .sample_with_increment(samples, monotonic_period, phase_offset, phase_increment)
+                .abs(),
+            Waveform::PhaseDistort {
+                base,
+                amount,
+                pivot,
+            } => {
+                let knee = 0.5 + (unit_clamp(*pivot) - 0.5) * unit_clamp(*amount);
+                let distorted_phase = if phase < knee {
+                    0.5 * phase / knee
+                } else {
+                    0.5 + 0.5 * (phase - knee) / (1.0 - knee)
+                };
+                // the phase offset has already been applied
+                base.sample_with_increment(
+                    samples,
+                    Period::from_secs_f64(distorted_phase),
+                    0.0,
+                    phase_increment,
+                )
+            }
+            Waveform::BandLimited { base, max_harmonic } => base
+                .harmonic_series(phase, *max_harmonic)
+                .unwrap_or_else(|| {
+                    base.sample_with_increment(
+                        samples,
+                        monotonic_period,
+                        phase_offset,
+                        phase_increment,
+                    )
+                }),
+            Waveform::Saturate { base, drive } => {
+                let value = base.sample_with_increment(
+                    samples,
+                    monotonic_period,
+                    phase_offset,
+                    phase_increment,
+                );
+                if drive.is_nan() || drive.abs() < f64::EPSILON {
+                    value
+                } else {
+                    // an infinite drive would make `drive * 0` NaN
+                    let drive = drive.clamp(-f64::MAX, f64::MAX);
+                    (drive * value).tanh() / drive.tanh()
+                }
+            }
+            Waveform::Mix { a, b, blend } => {
+                let a = a.sample_with_increment(
+                    samples,
+                    monotonic_period,
+                    phase_offset,
+                    phase_increment,
+                );
+                let b = b.sample_with_increment(
+                    samples,
+                    monotonic_period,
+                    phase_offset,
+                    phase_increment,
+                );
+                a + (b - a) * unit_clamp(*blend)
+            }
+            Waveform::Offset { base, gain, bias } => {
+                let value = base.sample_with_increment(
+                    samples,
+                    monotonic_period,
+                    phase_offset,
+                    phase_increment,
+                ) * gain
+                    + bias;
+                if value.is_finite() { value } else { 0.0 }
+            }
+            Waveform::Clamp { base, min, max } => {
+                let (low, high) = if min > max { (max, min) } else { (min, max) };
+                // `max` and `min` ignore NaN bounds, where `clamp` would panic
+                base.sample_with_increment(samples, monotonic_period, phase_offset, phase_increment)
+                    .max(*low)
+                    .min(*high)
+            }
+        }
+    }
+}
+
+/// Clamps `x` to [0, 1], treating NaN as 0.
+fn unit_clamp(x: f64) -> f64 {
+    if x.is_nan() { 0.0 } else { x.clamp(0.0, 1.0) }
+}
+
+/// Replaces NaN and infinite values with 0.
+fn finite_or_zero(x: f64) -> f64 {
+    if x.is_finite() { x } else { 0.0 }
+}
+
+/// The two-sample polynomial band-limited ramp residual for a corner at a phase of 0, which is added to a
+/// naive waveform with a unit change in slope per sample at the corner to round it off.
+fn poly_blamp(phase: f64, phase_increment: f64) -> f64 {
+    if phase_increment <= 0.0 {
+        return 0.0;
+    }
+    // the distance to the nearest corner, in samples
+    let distance = ((phase + 0.5).rem_euclid(1.0) - 0.5).abs() / phase_increment.min(0.5);
+    if distance < 1.0 {
+        (1.0 - distance).powi(3) / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// A mapping of [0, 1] onto itself, used to shape transitions such as an [`Envelope`]'s attack.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum Curve {
+    /// Leaves the input unchanged.
+    #[default]
+    Linear,
+    /// Raises the input to the power of `exponent`, which should be positive.
+    /// Exponents above 1 start slowly and end quickly; exponents below 1 do the opposite.
+    /// Zero, negative and NaN exponents are treated as linear.
+    Exponential { exponent: f64 },
+    /// Eases in and out with the smoothstep polynomial, `3x^2 - 2x^3`.
+    Smoothstep,
+}
+impl Curve {
+    /// Maps `x`, which should be within [0, 1]. 0 always maps to 0, and 1 always maps to 1.
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Curve::Linear => x,
+            // a zero exponent would jump straight to 1, and a negative one would start at infinity
+            Curve::Exponential { exponent } if *exponent <= 0.0 || exponent.is_nan() => x,
+            Curve::Exponential { exponent } => x.powf(*exponent),
+            Curve::Smoothstep => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+/// The shape of an [`Envelope`]'s decay from peak volume towards its sustain level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum DecayCurve {
+    /// Halves the distance to the sustain level `halving_rate` times per second,
+    /// approaching it without ever quite reaching it.
+    #[default]
+    Exponential,
+    /// Falls in a straight line, reaching the sustain level after `time`.
+    Linear { time: Duration },
+    /// Holds near peak volume, then falls increasingly quickly,
+    /// reaching the sustain level after `1 / halving_rate` seconds.
+    Logarithmic,
+}
+impl DecayCurve {
+    /// How far from the sustain level (0) to peak volume (1) the envelope is, `time` into the decay.
+    pub fn sample(&self, time: Duration, halving_rate: f64) -> f64 {
+        match self {
+            DecayCurve::Exponential => 0.5f64.powf(time.as_secs_f64() * halving_rate),
+            DecayCurve::Linear { time: decay_time } => {
+                if decay_time.is_zero() {
                     0.0
                 } else {
-                    base.sample(samples, period, phase_offset)
+                    (1.0 - time.as_secs_f64() / decay_time.as_secs_f64()).max(0.0)
                 }
             }
-            Waveform::Absolute(base) => base.sample(samples, period, phase_offset).abs(),
+            DecayCurve::Logarithmic => (2.0 - time.as_secs_f64() * halving_rate).max(1.0).log2(),
+        }
+    }
+    /// How long the decay takes to fall below [`INAUDIBLE_LEVEL`], or `None` if it never does.
+    pub fn duration(&self, halving_rate: f64) -> Option<Duration> {
+        let at_rate = |halvings: f64| {
+            (halving_rate > 0.0)
+                .then(|| Duration::try_from_secs_f64(halvings / halving_rate).ok())
+                .flatten()
+        };
+        match self {
+            DecayCurve::Exponential => at_rate(-INAUDIBLE_LEVEL.log2()),
+            DecayCurve::Linear { time } => Some(*time),
+            DecayCurve::Logarithmic => at_rate(1.0),
         }
     }
 }
 
-/// An envelope consisting of a peak volume, attack time, halving rate, and release time.
-#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Binary)]
+/// An envelope consisting of a peak volume, attack time, decay, sustain level, and release time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub struct Envelope {
-    /// Linear attack time; the time it takes to reach peak volume.
+    /// Attack time; the time it takes to reach peak volume.
     pub attack_time: Duration,
-    /// Exponential decay; the amount of times the output volume halves in one second.
+    /// The shape of the attack.
+    pub attack_curve: Curve,
+    /// The rate of the decay, whose meaning depends on `decay_curve`.
+    /// For exponential decays, this is the amount of times the distance between the output volume and
+    /// the sustain level halves in one second.
     /// Takes effect after attack time.
     pub halving_rate: f64,
+    /// The shape of the decay.
+    pub decay_curve: DecayCurve,
+    /// The level that the decay asymptotically approaches, and is held at until release.
+    /// A sustain level of 0 decays to silence.
+    pub sustain_level: f64,
     /// Linear release time; the time it takes to reach zero volume.
     /// Multiplied by the rest of the envelope.
     pub release_time: Duration,
+    /// Restarts the attack whenever the decay has fallen below [`INAUDIBLE_LEVEL`] while the note is held,
+    /// for rhythmic swells. Later attacks rise from the sustain level. Once released, the current cycle
+    /// continues under the release as usual, without restarting.
+    ///
+    /// Exponential decays only fall that far after about 30 halvings, so linear and logarithmic decays suit this better.
+    pub loop_envelope: bool,
 }
 impl Envelope {
+    /// Speeds up the envelope by `factor`, dividing its timings and multiplying its halving rate.
+    /// Factors that aren't finite and positive leave the envelope as-is.
+    pub fn scaled(&self, factor: f64) -> Envelope {
+        if !(factor.is_finite() && factor > 0.0) {
+            return *self;
+        }
+        let scale = |duration| time::duration_saturating_mul_f64(duration, 1.0 / factor);
+        Envelope {
+            attack_time: scale(self.attack_time),
+            halving_rate: self.halving_rate * factor,
+            decay_curve: match self.decay_curve {
+                DecayCurve::Linear { time } => DecayCurve::Linear { time: scale(time) },
+                curve => curve,
+            },
+            release_time: scale(self.release_time),
+            ..*self
+        }
+    }
+    /// How long one cycle of a looping envelope lasts, or `None` if it doesn't loop.
+    pub fn loop_duration(&self) -> Option<Duration> {
+        if !self.loop_envelope {
+            return None;
+        }
+        let cycle = self
+            .attack_time
+            .checked_add(self.decay_curve.duration(self.halving_rate)?)?;
+        (!cycle.is_zero()).then_some(cycle)
+    }
     /// If `None`, the envelope has finished.
     pub fn sample_volume(&self, note_time: Duration, stop_point: Option<Duration>) -> Option<f64> {
+        self.sample_volume_from(note_time, stop_point, 0.0)
+    }
+    /// Like [`Envelope::sample_volume`], but the attack rises from `attack_start_level` instead of silence.
+    pub fn sample_volume_from(
+        &self,
+        note_time: Duration,
+        stop_point: Option<Duration>,
+        attack_start_level: f64,
+    ) -> Option<f64> {
+        let held = stop_point.is_none_or(|stop_point| note_time < stop_point);
+        let (note_time, stop_point, attack_start_level) = match self.loop_duration() {
+            Some(cycle) => {
+                // cycles only restart while the note is held
+                let held_time =
+                    stop_point.map_or(note_time, |stop_point| note_time.min(stop_point));
+                let cycles = held_time.as_nanos() / cycle.as_nanos();
+                let cycle_start = time::advance(Duration::ZERO, cycle, cycles as u64);
+                (
+                    note_time.saturating_sub(cycle_start),
+                    stop_point.map(|stop_point| stop_point.saturating_sub(cycle_start)),
+                    if cycles == 0 {
+                        attack_start_level
+                    } else {
+                        self.sustain_level
+                    },
+                )
+            }
+            None => (note_time, stop_point, attack_start_level),
+        };
+        let looping = self.loop_envelope && held;
+
         let release_multiplier = if let Some(stop_point) = stop_point {
             if note_time > stop_point.saturating_add(self.release_time) {
                 return None;
             }
-            let release_progress = note_time.saturating_sub(stop_point);
-            let release_fraction = release_progress.as_secs_f64() / self.release_time.as_secs_f64();
-            1.0 - release_fraction
+            if self.release_time.is_zero() {
+                // cut instantly at the stop point instead of dividing by zero
+                if note_time >= stop_point {
+                    return None;
+                }
+                1.0
+            } else {
+                let release_progress = note_time.saturating_sub(stop_point);
+                let release_fraction =
+                    release_progress.as_secs_f64() / self.release_time.as_secs_f64();
+                1.0 - release_fraction
+            }
         } else {
             1.0
         };
 
+        // a zero attack time never enters this branch, jumping straight to full volume
         if note_time < self.attack_time {
             let attack_fraction = note_time.as_secs_f64() / self.attack_time.as_secs_f64();
-            Some(attack_fraction * release_multiplier)
+            let attack = self.attack_curve.apply(attack_fraction);
+            Some((attack_start_level + (1.0 - attack_start_level) * attack) * release_multiplier)
         } else {
             let time_from_decay_start = note_time.saturating_sub(self.attack_time);
-            let decay_multiplier =
-                0.5f64.powf(time_from_decay_start.as_secs_f64() * self.halving_rate);
-            Some(decay_multiplier * release_multiplier)
+            let decay_multiplier = self
+                .decay_curve
+                .sample(time_from_decay_start, self.halving_rate);
+            let decay_multiplier = if decay_multiplier < INAUDIBLE_LEVEL {
+                0.0
+            } else {
+                decay_multiplier
+            };
+            let level = self.sustain_level + (1.0 - self.sustain_level) * decay_multiplier;
+            let volume = level * release_multiplier;
+            // past the attack, the level can only fall, unless the envelope is about to loop
+            if volume < INAUDIBLE_LEVEL && !looping {
+                return None;
+            }
+            Some(volume)
         }
     }
 }
 
-/// A synthesiser that supports phase-offset modulation.
+/// Builds an [`Envelope`] one setting at a time, starting from [`Envelope::default`].
+///
+/// ```
+/// # use pommel::{DecayCurve, Envelope};
+/// # use std::time::Duration;
+/// let envelope = Envelope::builder()
+///     .attack(Duration::from_millis(5))
+///     .halving_rate(4.0)
+///     .sustain(0.5)
+///     .release(Duration::from_millis(200))
+///     .build();
+/// assert_eq!(envelope.attack_time, Duration::from_millis(5));
+/// assert_eq!(envelope.decay_curve, DecayCurve::Exponential);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnvelopeBuilder {
+    envelope: Envelope,
+}
+impl Envelope {
+    pub fn builder() -> EnvelopeBuilder {
+        EnvelopeBuilder::default()
+    }
+}
+impl EnvelopeBuilder {
+    pub fn attack(mut self, attack_time: Duration) -> Self {
+        self.envelope.attack_time = attack_time;
+        self
+    }
+    pub fn attack_curve(mut self, attack_curve: Curve) -> Self {
+        self.envelope.attack_curve = attack_curve;
+        self
+    }
+    pub fn halving_rate(mut self, halving_rate: f64) -> Self {
+        self.envelope.halving_rate = halving_rate;
+        self
+    }
+    pub fn decay_curve(mut self, decay_curve: DecayCurve) -> Self {
+        self.envelope.decay_curve = decay_curve;
+        self
+    }
+    pub fn sustain(mut self, sustain_level: f64) -> Self {
+        self.envelope.sustain_level = sustain_level;
+        self
+    }
+    pub fn release(mut self, release_time: Duration) -> Self {
+        self.envelope.release_time = release_time;
+        self
+    }
+    pub fn looping(mut self, loop_envelope: bool) -> Self {
+        self.envelope.loop_envelope = loop_envelope;
+        self
+    }
+    pub fn build(self) -> Envelope {
+        self.envelope
+    }
+}
+
+/// A level that a [`BreakpointEnvelope`] reaches at a time into the note.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub struct Breakpoint {
+    pub time: Duration,
+    pub level: f64,
+}
+
+/// An envelope that linearly interpolates between any number of timed levels, for contours that an [`Envelope`]
+/// can't express, such as a pluck followed by a swell.
 ///
-/// TODO: `set_frequency`, `set_start`
+/// The level starts at 0 (or wherever a retriggered note was) and moves linearly to each point in turn,
+/// then holds the level of the last point until release. Releasing fades linearly from the level at the time of release
+/// to silence over `release_time`. If the last point is silent, the note ends once it is reached.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub struct BreakpointEnvelope {
+    /// The points of the envelope, sorted by time.
+    pub points: Vec<Breakpoint>,
+    pub release_time: Duration,
+}
+impl BreakpointEnvelope {
+    /// Sorts `points` by time.
+    pub fn new(mut points: Vec<Breakpoint>, release_time: Duration) -> Self {
+        points.sort_by_key(|point| point.time);
+        Self {
+            points,
+            release_time,
+        }
+    }
+    /// Speeds up the envelope by `factor`, dividing its timings.
+    /// Factors that aren't finite and positive leave the envelope as-is.
+    pub fn scaled(&self, factor: f64) -> BreakpointEnvelope {
+        if !(factor.is_finite() && factor > 0.0) {
+            return self.clone();
+        }
+        let scale = |duration| time::duration_saturating_mul_f64(duration, 1.0 / factor);
+        BreakpointEnvelope {
+            points: self
+                .points
+                .iter()
+                .map(|point| Breakpoint {
+                    time: scale(point.time),
+                    ..*point
+                })
+                .collect(),
+            release_time: scale(self.release_time),
+        }
+    }
+    /// The level `note_time` into the note, ignoring release, starting from `start_level`.
+    fn level(&self, note_time: Duration, start_level: f64) -> f64 {
+        let next = self.points.partition_point(|point| point.time <= note_time);
+        let Some(to) = self.points.get(next) else {
+            return self.points.last().map_or(start_level, |point| point.level);
+        };
+        let from = match next.checked_sub(1) {
+            Some(previous) => self.points[previous],
+            None => Breakpoint {
+                time: Duration::ZERO,
+                level: start_level,
+            },
+        };
+        let fraction = note_time.saturating_sub(from.time).as_secs_f64()
+            / to.time.saturating_sub(from.time).as_secs_f64();
+        from.level + (to.level - from.level) * fraction
+    }
+    /// If `None`, the envelope has finished.
+    pub fn sample_volume(&self, note_time: Duration, stop_point: Option<Duration>) -> Option<f64> {
+        self.sample_volume_from(note_time, stop_point, 0.0)
+    }
+    /// Like [`BreakpointEnvelope::sample_volume`], but the envelope starts from `start_level` instead of silence.
+    pub fn sample_volume_from(
+        &self,
+        note_time: Duration,
+        stop_point: Option<Duration>,
+        start_level: f64,
+    ) -> Option<f64> {
+        let end = self
+            .points
+            .last()
+            .map_or(Duration::ZERO, |point| point.time);
+        let volume = match stop_point {
+            Some(stop_point) if note_time >= stop_point => {
+                if note_time >= stop_point.saturating_add(self.release_time) {
+                    return None;
+                }
+                let release_fraction = note_time.saturating_sub(stop_point).as_secs_f64()
+                    / self.release_time.as_secs_f64();
+                self.level(stop_point, start_level) * (1.0 - release_fraction)
+            }
+            _ => self.level(note_time, start_level),
+        };
+        // the level can rise again until the last point, after which it only falls
+        if note_time >= end && volume < INAUDIBLE_LEVEL {
+            return None;
+        }
+        Some(volume)
+    }
+}
+
+/// The envelope of an [`Operator`].
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum EnvelopeKind {
+    Envelope(Envelope),
+    Breakpoint(BreakpointEnvelope),
+}
+impl Default for EnvelopeKind {
+    fn default() -> Self {
+        EnvelopeKind::Envelope(Envelope::default())
+    }
+}
+impl From<Envelope> for EnvelopeKind {
+    fn from(envelope: Envelope) -> Self {
+        EnvelopeKind::Envelope(envelope)
+    }
+}
+impl From<BreakpointEnvelope> for EnvelopeKind {
+    fn from(envelope: BreakpointEnvelope) -> Self {
+        EnvelopeKind::Breakpoint(envelope)
+    }
+}
+impl EnvelopeKind {
+    /// Speeds up the envelope by `factor`. See [`Envelope::scaled`] and [`BreakpointEnvelope::scaled`].
+    pub fn scaled(&self, factor: f64) -> EnvelopeKind {
+        match self {
+            EnvelopeKind::Envelope(envelope) => EnvelopeKind::Envelope(envelope.scaled(factor)),
+            EnvelopeKind::Breakpoint(envelope) => EnvelopeKind::Breakpoint(envelope.scaled(factor)),
+        }
+    }
+    /// How long the envelope takes to fade out once released.
+    pub fn release_time(&self) -> Duration {
+        match self {
+            EnvelopeKind::Envelope(envelope) => envelope.release_time,
+            EnvelopeKind::Breakpoint(envelope) => envelope.release_time,
+        }
+    }
+    /// If `None`, the envelope has finished.
+    pub fn sample_volume(&self, note_time: Duration, stop_point: Option<Duration>) -> Option<f64> {
+        self.sample_volume_from(note_time, stop_point, 0.0)
+    }
+    /// Like [`EnvelopeKind::sample_volume`], but the envelope starts from `start_level` instead of silence.
+    pub fn sample_volume_from(
+        &self,
+        note_time: Duration,
+        stop_point: Option<Duration>,
+        start_level: f64,
+    ) -> Option<f64> {
+        match self {
+            EnvelopeKind::Envelope(envelope) => {
+                envelope.sample_volume_from(note_time, stop_point, start_level)
+            }
+            EnvelopeKind::Breakpoint(envelope) => {
+                envelope.sample_volume_from(note_time, stop_point, start_level)
+            }
+        }
+    }
+}
+
+/// A synthesiser that supports phase-offset modulation.
 pub trait Pom<Data> {
     /// Samples the synthesiser. `global_time` represents the current time.
     ///
@@ -230,58 +1555,626 @@ pub trait Pom<Data> {
     fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64>;
     /// Starts the synthesiser at the last global time.
     fn play(&mut self, frequency: f64, volume: f64);
+    /// Schedules the synthesiser to start at `when`, in global time. Sampling before then produces `None`.
+    ///
+    /// Does nothing by default, leaving synthesisers that can't be scheduled to start when they are played.
+    fn set_start(&mut self, when: Duration) {
+        let _ = when;
+    }
+    /// Changes the frequency of the synthesiser without restarting its envelope.
+    fn set_frequency(&mut self, frequency: f64) {
+        let _ = frequency;
+    }
     /// Stops the synthesiser immediately.
     fn cut(&mut self);
     /// Sets the synthesiser into the release section of its envelope.
     fn release(&mut self);
+    /// Whether the synthesiser is playing, or is scheduled to play.
+    /// Returns false once the note has been cut or has finished its release,
+    /// as of the last sampled global time.
+    fn is_active(&self) -> bool;
+    /// How long until the synthesiser goes silent, as of the last time it was sampled.
+    ///
+    /// Returns `None` if it is sustaining indefinitely, and `Some(Duration::ZERO)` if it is already off.
+    fn remaining(&self) -> Option<Duration> {
+        if self.is_active() {
+            None
+        } else {
+            Some(Duration::ZERO)
+        }
+    }
+    /// The volume the synthesiser would be enveloped to at `global_time`, without advancing it.
+    /// Intended for metering.
+    ///
+    /// Returns `None` if the synthesiser is off, or doesn't support metering.
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        let _ = global_time;
+        None
+    }
     /// Clones the synthesiser into a boxed trait object.
     fn box_clone(&self) -> Box<dyn Pom<Data>>;
+    /// Returns this synthesiser as [`Any`], so that it can be downcast back to its concrete type.
+    ///
+    /// ```
+    /// use pommel::{Operator, Pom, SampleBank, Waveform};
+    ///
+    /// let synth: Box<dyn Pom<SampleBank>> = Box::new(Operator::builder().waveform(Waveform::Triangle).build());
+    /// let operator = synth.as_any().downcast_ref::<Operator>().unwrap();
+    /// assert_eq!(operator.waveform, Waveform::Triangle);
+    /// ```
+    fn as_any(&self) -> &dyn Any;
+    /// Like [`Pom::as_any`], but mutable.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Restarts the waveforms of the synthesiser from the beginning of their period, without affecting envelopes.
+    fn reset_phase(&mut self) {}
+    /// The current position of the synthesiser's oscillator in periods,
+    /// or `None` if it doesn't have a single oscillator.
+    fn oscillator_period(&self) -> Option<Period> {
+        None
+    }
+    /// Whether the synthesiser is muted, such as an [`Operator`] with [`Operator::muted`] set.
+    /// Muted synthesisers act as the identity in modulation chains.
+    fn is_muted(&self) -> bool {
+        false
+    }
+    /// Returns this synthesiser as a [`StereoPom`], if it produces stereo output.
+    fn as_stereo_mut(&mut self) -> Option<&mut dyn StereoPom<Data>> {
+        None
+    }
+    /// Samples the synthesiser as a `(left, right)` pair, whether or not it produces stereo output.
+    /// Mono synthesisers are centred, with their output on both sides at full volume.
+    fn sample_as_stereo(
+        &mut self,
+        data: &Data,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<(f64, f64)> {
+        if let Some(stereo) = self.as_stereo_mut() {
+            return stereo.sample_stereo(data, global_time, phase_offset);
+        }
+        self.sample(data, global_time, phase_offset)
+            .map(|sample| (sample, sample))
+    }
+    /// Converts the synthesiser into a form that can be encoded,
+    /// or `None` if the synthesiser (or one of its children) cannot be.
+    fn to_serialized(&self) -> Option<SerializedSynth> {
+        None
+    }
+    /// Renders a single note offline, such as for previews and examples.
+    ///
+    /// The note is played from a global time of zero and released after `note_length`,
+    /// then rendered for a further `tail` to capture the release, at `sample_rate` samples per second.
+    fn render(
+        &mut self,
+        data: &Data,
+        frequency: f64,
+        volume: f64,
+        sample_rate: u32,
+        note_length: Duration,
+        tail: Duration,
+    ) -> Vec<f32> {
+        if sample_rate == 0 {
+            return vec![];
+        }
+        // sample times are computed exactly, as a sample interval in nanoseconds usually isn't whole
+        let nanos_per_sec = time::NANOS_PER_SEC as u128;
+        let time_of = |index: u128| {
+            let nanos = index * nanos_per_sec / sample_rate as u128;
+            Duration::new(
+                (nanos / nanos_per_sec) as u64,
+                (nanos % nanos_per_sec) as u32,
+            )
+        };
+        let length =
+            note_length.saturating_add(tail).as_nanos() * sample_rate as u128 / nanos_per_sec;
+
+        self.play(frequency, volume);
+        self.set_start(Duration::ZERO);
+        let mut released = false;
+        (0..length)
+            .map(|index| {
+                let time = time_of(index);
+                if !released && time >= note_length {
+                    self.release();
+                    released = true;
+                }
+                self.sample(data, time, 0.0).unwrap_or_default() as f32
+            })
+            .collect()
+    }
+}
+
+/// An identifier for a note played by a [`VoiceManager`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VoiceId(pub u64);
+
+/// A single voice of a [`VoiceManager`].
+pub struct Voice<Data> {
+    pub id: VoiceId,
+    pub synth: Box<dyn Pom<Data>>,
+}
+
+/// Plays multiple notes at once by cloning a template synthesiser into up to `max_voices` voices.
+///
+/// When every voice is active, the oldest note is cut to make room for a new one.
+/// The template itself is never played or sampled, so new voices always start fresh.
+pub struct VoiceManager<Data> {
+    pub template: Box<dyn Pom<Data>>,
+    pub max_voices: usize,
+    pub voices: Vec<Voice<Data>>,
+    next_id: u64,
+}
+impl<Data> VoiceManager<Data> {
+    pub fn new(template: Box<dyn Pom<Data>>, max_voices: usize) -> Self {
+        Self {
+            template,
+            max_voices,
+            voices: Vec::with_capacity(max_voices),
+            next_id: 0,
+        }
+    }
+    /// Plays a new note on a fresh clone of the template, starting at the next sampled global time.
+    pub fn note_on(&mut self, frequency: f64, volume: f64) -> VoiceId {
+        let id = VoiceId(self.next_id);
+        self.next_id += 1;
+        if self.max_voices == 0 {
+            return id;
+        }
+        let mut synth = self.template.box_clone();
+        synth.play(frequency, volume);
+        let voice = Voice { id, synth };
+
+        if self.voices.len() < self.max_voices {
+            self.voices.push(voice);
+        } else {
+            let index = self
+                .voices
+                .iter()
+                .position(|voice| !voice.synth.is_active())
+                .or_else(|| {
+                    self.voices
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, voice)| voice.id)
+                        .map(|(index, _)| index)
+                })
+                .unwrap_or(0);
+            self.voices[index].synth.cut();
+            self.voices[index] = voice;
+        }
+        id
+    }
+    /// Releases the note played on the given voice, if it is still held by the manager.
+    pub fn note_off(&mut self, id: VoiceId) {
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+            voice.synth.release();
+        }
+    }
+    /// Cuts every voice.
+    pub fn cut_all(&mut self) {
+        self.voices.iter_mut().for_each(|voice| voice.synth.cut());
+    }
+    /// The amount of voices that are still playing.
+    pub fn active_voices(&self) -> usize {
+        self.voices
+            .iter()
+            .filter(|voice| voice.synth.is_active())
+            .count()
+    }
+    /// Samples and sums every active voice.
+    pub fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> f64 {
+        self.voices
+            .iter_mut()
+            .filter(|voice| voice.synth.is_active())
+            .map(|voice| {
+                voice
+                    .synth
+                    .sample(data, global_time, phase_offset)
+                    .unwrap_or_default()
+            })
+            .sum()
+    }
 }
 
 /// Constants for [`Operator`]s to tweak their behaviour.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Binary)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub struct OperatorModifiers {
     pub frequency_multiplier: f64,
+    /// Detune in cents (hundredths of a semitone), composed multiplicatively with `frequency_multiplier`.
+    pub detune_cents: f64,
     pub volume_multiplier: f64,
     pub constant_phase_offset: f64,
+    /// How much of the operator's previous output is fed back into its own phase offset.
+    pub feedback: f64,
+    /// How strongly the envelope's speed follows the played frequency. 0 disables key-tracking.
+    ///
+    /// The envelope is sped up by `(frequency / key_track_reference).powf(key_track)`,
+    /// so positive values shorten the envelopes of notes above the reference.
+    pub key_track: f64,
+    /// The frequency at which key-tracking leaves the envelope unchanged, in hertz.
+    pub key_track_reference: f64,
+    /// A final multiplier on the operator's output, independent of the volume it is played at.
+    /// Unlike `volume_multiplier`, this doesn't affect the operator's feedback.
+    pub output_gain: f64,
+    /// The exponent that played volumes (velocities) are raised to before becoming the peak volume.
+    /// 1 is linear, and higher values make quiet notes quieter, like many acoustic instruments.
+    /// Zero, negative and NaN curves are treated as linear.
+    pub velocity_curve: f64,
 }
 impl Default for OperatorModifiers {
     fn default() -> Self {
         Self {
             frequency_multiplier: 1.0,
+            detune_cents: 0.0,
             volume_multiplier: 1.0,
             constant_phase_offset: 0.0,
+            feedback: 0.0,
+            key_track: 0.0,
+            key_track_reference: 440.0,
+            output_gain: 1.0,
+            velocity_curve: 1.0,
+        }
+    }
+}
+impl OperatorModifiers {
+    /// Applies the frequency multiplier and detune to a played frequency.
+    pub fn effective_frequency(&self, frequency: f64) -> f64 {
+        frequency * self.frequency_multiplier * 2f64.powf(self.detune_cents / 1200.0)
+    }
+    /// Applies the velocity curve and volume multiplier to a played volume.
+    /// The sign of the volume is kept, so negative volumes still invert the output.
+    pub fn effective_volume(&self, volume: f64) -> f64 {
+        // a zero curve would otherwise play silence at full volume, and a negative one at infinity
+        let linear = self.velocity_curve == 1.0
+            || self.velocity_curve <= 0.0
+            || self.velocity_curve.is_nan();
+        let curved = if volume == 0.0 || linear {
+            volume
+        } else {
+            volume.abs().powf(self.velocity_curve).copysign(volume)
+        };
+        curved * self.volume_multiplier
+    }
+    /// The factor that key-tracking speeds up the envelope by when playing `frequency`.
+    pub fn key_track_factor(&self, frequency: f64) -> f64 {
+        if self.key_track == 0.0 {
+            1.0
+        } else {
+            (frequency / self.key_track_reference).powf(self.key_track)
+        }
+    }
+}
+
+/// Builds an [`Operator`] one setting at a time, starting from [`Operator::default`].
+///
+/// ```
+/// # use pommel::{Envelope, Operator, Waveform};
+/// # use std::time::Duration;
+/// let operator = Operator::builder()
+///     .waveform(Waveform::Sawtooth)
+///     .envelope(Envelope::builder().release(Duration::from_millis(100)).build())
+///     .frequency_multiplier(2.0)
+///     .build();
+/// assert_eq!(operator.waveform, Waveform::Sawtooth);
+/// assert_eq!(operator.modifiers.frequency_multiplier, 2.0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OperatorBuilder {
+    waveform: Waveform,
+    envelope: EnvelopeKind,
+    modifiers: OperatorModifiers,
+    filter: Option<Filter>,
+    glide_time: Duration,
+}
+impl Operator {
+    pub fn builder() -> OperatorBuilder {
+        OperatorBuilder::default()
+    }
+}
+impl OperatorBuilder {
+    pub fn waveform(mut self, waveform: Waveform) -> Self {
+        self.waveform = waveform;
+        self
+    }
+    pub fn envelope(mut self, envelope: impl Into<EnvelopeKind>) -> Self {
+        self.envelope = envelope.into();
+        self
+    }
+    /// Replaces all modifiers. Set this before the individual modifiers below, or they will be overwritten.
+    pub fn modifiers(mut self, modifiers: OperatorModifiers) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+    pub fn frequency_multiplier(mut self, frequency_multiplier: f64) -> Self {
+        self.modifiers.frequency_multiplier = frequency_multiplier;
+        self
+    }
+    pub fn detune_cents(mut self, detune_cents: f64) -> Self {
+        self.modifiers.detune_cents = detune_cents;
+        self
+    }
+    pub fn volume_multiplier(mut self, volume_multiplier: f64) -> Self {
+        self.modifiers.volume_multiplier = volume_multiplier;
+        self
+    }
+    pub fn feedback(mut self, feedback: f64) -> Self {
+        self.modifiers.feedback = feedback;
+        self
+    }
+    pub fn output_gain(mut self, output_gain: f64) -> Self {
+        self.modifiers.output_gain = output_gain;
+        self
+    }
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+    pub fn glide_time(mut self, glide_time: Duration) -> Self {
+        self.glide_time = glide_time;
+        self
+    }
+    pub fn build(self) -> Operator {
+        let mut operator = Operator::new(self.waveform, self.envelope, self.modifiers);
+        operator.filter = self.filter;
+        operator.glide_time = self.glide_time;
+        operator
+    }
+}
+
+/// A resonant low-pass filter, applied to the output of an [`Operator`].
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub struct Filter {
+    /// The frequency above which the output is attenuated, in hertz.
+    pub cutoff_hz: f64,
+    /// The Q factor of the filter. [`std::f64::consts::FRAC_1_SQRT_2`] gives a flat response,
+    /// and higher values produce a resonant peak at the cutoff.
+    pub resonance: f64,
+}
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            cutoff_hz: 20000.0,
+            resonance: core::f64::consts::FRAC_1_SQRT_2,
+        }
+    }
+}
+
+/// The state of a [`Filter`], implemented as a trapezoidal state-variable filter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub struct FilterState {
+    pub low_state: f64,
+    pub band_state: f64,
+}
+impl FilterState {
+    /// Filters one sample. `sample_interval` is the time since the last sample.
+    pub fn process(&mut self, filter: &Filter, input: f64, sample_interval: Duration) -> f64 {
+        let sample_rate = 1.0 / sample_interval.as_secs_f64();
+        if !sample_rate.is_finite() {
+            return self.low_state;
         }
+        // keep the cutoff under nyquist to stay stable
+        let cutoff = filter.cutoff_hz.clamp(0.0, sample_rate * 0.49);
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 1.0 / filter.resonance.max(0.5);
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = input - self.low_state;
+        let band = a1 * self.band_state + a2 * v3;
+        let low = self.low_state + a2 * self.band_state + a3 * v3;
+        self.band_state = 2.0 * band - self.band_state;
+        self.low_state = 2.0 * low - self.low_state;
+        low
     }
 }
 
 /// A synthesiser that produces an enveloped waveform at a set frequency.
-#[derive(Clone, Debug, Default, PartialEq, PartialOrd, Binary)]
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub struct Operator {
     pub waveform: Waveform,
-    pub envelope: Envelope,
+    pub envelope: EnvelopeKind,
     pub modifiers: OperatorModifiers,
+    /// A low-pass filter applied to the output of the operator.
+    pub filter: Option<Filter>,
+    pub filter_state: FilterState,
 
+    /// When the current note starts in global time, `Some(None)` if it starts at the next sample,
+    /// or `None` if no note is playing.
+    ///
+    /// A note is first heard at the first sample at or after its start. If it starts between two samples,
+    /// that sample is attenuated by the fraction of the time since the previous sample that the note covers,
+    /// so its onset isn't quantised to samples. A note starting exactly on a sample plays that sample at full level.
     pub start_time: Option<Option<Duration>>,
     pub stop_point: Option<Duration>,
+    /// The frequency the oscillator is currently running at, which glides towards `target_frequency`.
     pub frequency: f64,
+    /// The frequency given by the last call to [`Pom::play`] or [`Pom::set_frequency`], with modifiers applied.
+    pub target_frequency: f64,
+    /// The time constant of portamento after [`Pom::set_frequency`]; the gap between the current
+    /// and target frequency shrinks by a factor of e every `glide_time`. Zero jumps to new frequencies instantly.
+    /// Playing a note always jumps to its frequency.
+    pub glide_time: Duration,
+    /// The time constant of a one-pole low-pass filter on the incoming phase offset, which smooths out
+    /// sudden jumps in modulation, such as from sparse control updates, that would otherwise cause zipper noise.
+    /// The gap between the smoothed and incoming phase offset shrinks by a factor of e every `phase_offset_smoothing`.
+    /// Zero disables smoothing.
+    pub phase_offset_smoothing: Duration,
+    /// The smoothed phase offset as of the last sample, or `None` if smoothing starts from the next phase offset.
+    smoothed_phase_offset: Option<f64>,
     pub peak_volume: f64,
     pub last_global_time: Option<Duration>,
     pub current_waveform_period: Period,
+    /// The previous output of the operator, used for feedback.
+    pub last_output: f64,
+    /// Whether playing a note restarts the waveform from the beginning of its period.
+    /// A note retriggered while still sounding crossfades into the restarted waveform over [`RETRIGGER_CROSSFADE`].
+    ///
+    /// Disabling this gives legato behaviour, where retriggered notes continue the waveform where it was.
+    pub retrigger_resets_phase: bool,
+    /// Whether notes that restart the waveform start it at a phase chosen by `start_phase_seed`,
+    /// instead of the beginning of its period. Giving each voice of a unison a different seed
+    /// stops them from starting in phase with each other.
+    pub random_start_phase: bool,
+    /// Picks the starting phase when `random_start_phase` is set. The same seed always starts at the same phase.
+    pub start_phase_seed: u64,
+    /// Whether the operator is silenced while its frequency is above the Nyquist frequency (half the sample rate),
+    /// where it would alias down into lower frequencies. The sample rate is inferred from the time between samples,
+    /// so it is only known from the second sample onwards.
+    pub nyquist_mute: bool,
+    /// Silences the operator without removing it, such as for auditioning the other operators of a patch.
+    /// A muted operator keeps running, so unmuting it doesn't restart its note or waveform.
+    ///
+    /// In a [`CombinatorType::Modulate`] chain or a [`Stacker`], a muted operator passes the phase offset it is
+    /// given through unchanged instead of silencing the operators it modulates. Muting the last operator of a chain
+    /// therefore outputs the modulation itself.
+    pub muted: bool,
+    /// The envelope level the current note's attack rises from, so retriggering doesn't jump back to silence.
+    pub attack_start_level: f64,
+    /// Where the previous note's waveform has reached, if the operator was retriggered while sounding
+    /// and restarted its waveform less than [`RETRIGGER_CROSSFADE`] ago.
+    retrigger_period: Option<Period>,
+    /// The frequency the current note was played at, before modifiers, which key-tracking follows.
+    note_frequency: f64,
+}
+impl Default for Operator {
+    fn default() -> Self {
+        Self::new(
+            Waveform::default(),
+            Envelope::default(),
+            OperatorModifiers::default(),
+        )
+    }
 }
 impl Operator {
-    pub fn new(waveform: Waveform, envelope: Envelope, modifiers: OperatorModifiers) -> Self {
+    pub fn new(
+        waveform: Waveform,
+        envelope: impl Into<EnvelopeKind>,
+        modifiers: OperatorModifiers,
+    ) -> Self {
+        let envelope = envelope.into();
         Self {
             waveform,
             envelope,
             modifiers,
+            filter: None,
+            filter_state: FilterState::default(),
             frequency: 0.0,
+            target_frequency: 0.0,
+            glide_time: Duration::ZERO,
+            phase_offset_smoothing: Duration::ZERO,
+            smoothed_phase_offset: None,
             peak_volume: 0.0,
             start_time: None,
             stop_point: None,
             last_global_time: None,
             current_waveform_period: Period::ZERO,
+            last_output: 0.0,
+            retrigger_resets_phase: true,
+            random_start_phase: false,
+            start_phase_seed: 0,
+            nyquist_mute: false,
+            muted: false,
+            attack_start_level: 0.0,
+            retrigger_period: None,
+            note_frequency: 0.0,
+        }
+    }
+    /// Moves the frequency towards the target frequency after `delta_time` has passed.
+    fn glide(&mut self, delta_time: Duration) {
+        if self.glide_time.is_zero() {
+            self.frequency = self.target_frequency;
+        } else {
+            let remaining = (-delta_time.as_secs_f64() / self.glide_time.as_secs_f64()).exp();
+            self.frequency =
+                self.target_frequency + (self.frequency - self.target_frequency) * remaining;
+        }
+    }
+    /// Applies `phase_offset_smoothing` to the incoming phase offset after `delta_time` has passed.
+    fn smooth_phase_offset(&mut self, phase_offset: f64, delta_time: Duration) -> f64 {
+        if self.phase_offset_smoothing.is_zero() || !phase_offset.is_finite() {
+            return phase_offset;
+        }
+        let smoothed = match self.smoothed_phase_offset {
+            Some(previous) => {
+                let remaining =
+                    (-delta_time.as_secs_f64() / self.phase_offset_smoothing.as_secs_f64()).exp();
+                phase_offset + (previous - phase_offset) * remaining
+            }
+            None => phase_offset,
+        };
+        self.smoothed_phase_offset = Some(smoothed);
+        smoothed
+    }
+    /// The period that playing a note restarts the waveform from.
+    fn start_period(&self) -> Period {
+        if self.random_start_phase {
+            let phase = random::unit(self.start_phase_seed, 0);
+            Period::from_nanos((phase * time::NANOS_PER_SEC as f64) as u64)
+        } else {
+            Period::ZERO
+        }
+    }
+    /// Whether the operator should be muted for being above the Nyquist frequency when sampled `delta_time` apart.
+    fn above_nyquist(&self, delta_time: Duration) -> bool {
+        self.nyquist_mute
+            && !delta_time.is_zero()
+            && self.frequency.abs() * delta_time.as_secs_f64() > 0.5
+    }
+    /// The factor that key-tracking speeds up the envelope by for the current note,
+    /// or `None` if it leaves the envelope as-is.
+    fn key_track_factor(&self) -> Option<f64> {
+        let factor = self.modifiers.key_track_factor(self.note_frequency);
+        (factor != 1.0 && factor.is_finite() && factor > 0.0).then_some(factor)
+    }
+    /// Scales a time relative to the start of the note by the current note's key-tracking factor.
+    /// Speeding up the envelope is the same as speeding up time, so the envelope is sampled at scaled times
+    /// instead of being scaled itself, which picks up changes to the envelope and modifiers straight away.
+    fn key_tracked(&self, note_time: Duration) -> Duration {
+        match self.key_track_factor() {
+            Some(factor) => time::duration_saturating_mul_f64(note_time, factor),
+            None => note_time,
+        }
+    }
+    /// Samples the envelope `note_time` into the note, with key-tracking applied.
+    fn tracked_envelope_volume(&self, note_time: Duration, start_time: Duration) -> Option<f64> {
+        self.envelope.sample_volume_from(
+            self.key_tracked(note_time),
+            self.note_stop_point(start_time)
+                .map(|stop_point| self.key_tracked(stop_point)),
+            self.attack_start_level,
+        )
+    }
+    /// `stop_point` is in global time, but envelopes expect it relative to the start of the note.
+    fn note_stop_point(&self, start_time: Duration) -> Option<Duration> {
+        self.stop_point
+            .map(|stop_point| stop_point.saturating_sub(start_time))
+    }
+    /// The current phase of the operator's oscillator, wrapped to be within [0, 1).
+    pub fn phase(&self) -> f64 {
+        self.current_waveform_period.subsec_nanos() as f64 / time::NANOS_PER_SEC as f64
+    }
+    /// The envelope level at the last sampled time, or `None` if the note is off or has ended.
+    fn envelope_level(&self) -> Option<f64> {
+        self.envelope_level_at(self.last_global_time)
+    }
+    /// The envelope level at `global_time`, or `None` if the note is off or has ended.
+    fn envelope_level_at(&self, global_time: Option<Duration>) -> Option<f64> {
+        let Some(start_time) = self.start_time else {
+            return None; // note is off
+        };
+        let (Some(start_time), Some(global_time)) = (start_time, global_time) else {
+            return Some(0.0); // note is waiting for its first sample
+        };
+        if global_time < start_time {
+            return Some(0.0); // note hasnt started
         }
+        let note_time = global_time.saturating_sub(start_time);
+        self.tracked_envelope_volume(note_time, start_time)
     }
 }
 impl Pom<SampleBank> for Operator {
@@ -306,40 +2199,141 @@ impl Pom<SampleBank> for Operator {
             }
         };
         if global_time < start_time {
+            // the previous note has been silenced, so the new one can't continue from it
+            self.attack_start_level = 0.0;
+            self.retrigger_period = None;
             return None; // note hasnt started
         }
 
         let note_time = global_time.saturating_sub(start_time);
-        let Some(envelope_multiplier) = self.envelope.sample_volume(note_time, self.stop_point)
-        else {
+        let Some(envelope_multiplier) = self.tracked_envelope_volume(note_time, start_time) else {
             return None; // note has ended
         };
+        // a note that started in (last sample, this sample] only covers part of the time since the last sample,
+        // so its first sample is attenuated by that fraction, and its waveform only advances from the start.
+        // a note starting exactly at this sample covers all of it, and retriggered notes continue from the
+        // previous note instead of silence, so neither is attenuated
+        let (onset, phase_delta_time) = if note_time < delta_time {
+            let onset = if self.attack_start_level == 0.0 && !note_time.is_zero() {
+                note_time.as_secs_f64() / delta_time.as_secs_f64()
+            } else {
+                1.0
+            };
+            if self.retrigger_resets_phase {
+                (onset, note_time)
+            } else {
+                (onset, delta_time)
+            }
+        } else {
+            (1.0, delta_time)
+        };
 
         // println!("{self:?} {} {}", self.frequency, self.peak_volume);
 
+        self.glide(delta_time);
+        let phase_offset = self.smooth_phase_offset(phase_offset, delta_time);
+
         // at
         self.current_waveform_period =
             self.current_waveform_period
                 .saturating_add(time::duration_saturating_mul_f64(
-                    delta_time,
+                    phase_delta_time,
                     self.frequency,
                 ));
-        Some(
-            self.waveform.sample(
+        if note_time >= RETRIGGER_CROSSFADE {
+            self.retrigger_period = None;
+        }
+        self.retrigger_period = self.retrigger_period.map(|period| {
+            period.saturating_add(time::duration_saturating_mul_f64(
+                delta_time,
+                self.frequency,
+            ))
+        });
+        let output = if self.above_nyquist(delta_time) {
+            0.0
+        } else {
+            let phase_offset = phase_offset
+                + self.modifiers.constant_phase_offset
+                + self.modifiers.feedback * self.last_output;
+            // how far the waveform moves between samples at the current frequency, for antialiasing
+            let phase_increment = self.frequency.abs() * delta_time.as_secs_f64();
+            let mut value = self.waveform.sample_with_increment(
                 data,
                 self.current_waveform_period,
-                phase_offset + self.modifiers.constant_phase_offset,
-            ) * envelope_multiplier
-                * self.peak_volume,
-        )
+                phase_offset,
+                phase_increment,
+            );
+            if let Some(period) = self.retrigger_period {
+                let fade = note_time.as_secs_f64() / RETRIGGER_CROSSFADE.as_secs_f64();
+                let previous = self.waveform.sample_with_increment(
+                    data,
+                    period,
+                    phase_offset,
+                    phase_increment,
+                );
+                value = previous + (value - previous) * fade;
+            }
+            value * envelope_multiplier * onset * self.peak_volume
+        };
+        let output = match &self.filter {
+            Some(filter) => self.filter_state.process(filter, output, delta_time),
+            None => output,
+        };
+        self.last_output = output;
+        if self.muted {
+            return Some(0.0);
+        }
+        Some(output * self.modifiers.output_gain)
     }
 
+    /// NaN and infinite frequencies and volumes, including after modifiers are applied, are treated as 0,
+    /// so they can't poison the operator's state.
     fn play(&mut self, frequency: f64, volume: f64) {
-        self.peak_volume = volume * self.modifiers.volume_multiplier;
-        self.frequency = frequency * self.modifiers.frequency_multiplier;
+        let (frequency, volume) = (finite_or_zero(frequency), finite_or_zero(volume));
+        let previous_volume = self.envelope_level().unwrap_or(0.0) * self.peak_volume;
+        self.peak_volume = finite_or_zero(self.modifiers.effective_volume(volume));
+        self.frequency = finite_or_zero(self.modifiers.effective_frequency(frequency));
+        self.target_frequency = self.frequency;
+        self.note_frequency = frequency;
+        // start the attack from wherever the previous note was, relative to the new peak
+        let attack_start_level = previous_volume / self.peak_volume;
+        self.attack_start_level = if attack_start_level.is_finite() {
+            attack_start_level
+        } else {
+            0.0
+        };
+        // restarting the waveform of a sounding note crossfades from where it was, instead of jumping
+        self.retrigger_period = None;
+        if self.retrigger_resets_phase {
+            if self.attack_start_level != 0.0 {
+                self.retrigger_period = Some(self.current_waveform_period);
+            }
+            self.current_waveform_period = self.start_period();
+            self.last_output = 0.0;
+            self.smoothed_phase_offset = None;
+        }
         self.start_time = Some(self.last_global_time);
         self.stop_point = None;
     }
+    /// A note scheduled after the next sample still continues from the previous note if it is retriggered,
+    /// unless a sample is taken before it starts, which silences the previous note.
+    ///
+    /// A start exactly on a sample plays that sample at full level. A start between two samples is first heard
+    /// at the later one, attenuated by the fraction of the interval after the start. See [`Operator::start_time`].
+    fn set_start(&mut self, when: Duration) {
+        self.start_time = Some(Some(when));
+        self.stop_point = None;
+    }
+    /// Like `play`, NaN and infinite frequencies are treated as 0.
+    fn set_frequency(&mut self, frequency: f64) {
+        self.target_frequency = finite_or_zero(
+            self.modifiers
+                .effective_frequency(finite_or_zero(frequency)),
+        );
+        if self.glide_time.is_zero() {
+            self.frequency = self.target_frequency;
+        }
+    }
     fn release(&mut self) {
         self.stop_point
             .get_or_insert(self.last_global_time.unwrap_or(Duration::ZERO));
@@ -347,41 +2341,224 @@ impl Pom<SampleBank> for Operator {
     fn cut(&mut self) {
         self.start_time = None;
         self.stop_point = None;
+        self.last_output = 0.0;
+        self.filter_state = FilterState::default();
+        self.attack_start_level = 0.0;
+        self.retrigger_period = None;
+    }
+    fn reset_phase(&mut self) {
+        self.current_waveform_period = Period::ZERO;
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        Some(self.current_waveform_period)
+    }
+    fn is_muted(&self) -> bool {
+        self.muted
+    }
+    fn is_active(&self) -> bool {
+        self.envelope_level().is_some()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        if !self.is_active() {
+            return Some(Duration::ZERO);
+        }
+        let stop_point = self.stop_point?; // note is sustaining
+        let start_time = self.start_time.flatten().unwrap_or_default();
+        let release_time = match self.key_track_factor() {
+            Some(factor) => {
+                time::duration_saturating_mul_f64(self.envelope.release_time(), 1.0 / factor)
+            }
+            None => self.envelope.release_time(),
+        };
+        let end = stop_point.max(start_time).saturating_add(release_time);
+        Some(end.saturating_sub(self.last_global_time.unwrap_or_default()))
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.envelope_level_at(Some(global_time))
+            .map(|level| level * self.peak_volume * self.modifiers.output_gain)
     }
     fn box_clone(&self) -> Box<dyn Pom<SampleBank>> {
         Box::new(self.clone())
     }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn to_serialized(&self) -> Option<SerializedSynth> {
+        Some(SerializedSynth::Operator(Box::new(self.clone())))
+    }
 }
 
 /// A combinator
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "std", derive(Binary))]
 pub enum CombinatorType {
     Modulate,
     Sum,
+    /// Multiplies the outputs of all synths together (ring modulation).
+    /// A synth that is off counts as 0, silencing the whole product.
+    Multiply,
+    /// Takes the lowest output of all synths, for hard waveshaping. A synth that is off counts as 0.
+    Min,
+    /// Takes the highest output of all synths, for hard waveshaping. A synth that is off counts as 0.
+    Max,
+    /// Averages the outputs of all synths, which won't clip if none of them do. A synth that is off counts as 0.
+    Average,
+    /// Sums the outputs of all synths, each multiplied by its weight in [`Combinator::weights`].
+    WeightedSum,
 }
 pub struct Combinator<Data> {
     pub synths: Vec<Box<dyn Pom<Data>>>,
     pub ty: CombinatorType,
+    /// The weight of each synth for [`CombinatorType::WeightedSum`].
+    /// Synths without a corresponding weight have a weight of 1.
+    pub weights: Vec<f64>,
+    /// Whether a [`CombinatorType::Sum`] is divided by the number of synths currently producing output,
+    /// which keeps it within [-1, 1] if all of them are.
+    pub normalize: bool,
 }
-impl<Data: 'static> Pom<Data> for Combinator<Data> {
-    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
-        match self.ty {
-            CombinatorType::Modulate => {
-                let mut carry = Some(phase_offset);
-                for op in &mut self.synths {
-                    carry = op.sample(data, global_time, carry.unwrap_or_default());
-                }
-                carry
-            }
-            CombinatorType::Sum => Some(
-                self.synths
-                    .iter_mut()
-                    .map(|op| {
-                        op.sample(data, global_time, phase_offset)
+impl<Data> Combinator<Data> {
+    pub fn new(synths: Vec<Box<dyn Pom<Data>>>, ty: CombinatorType) -> Self {
+        Self {
+            synths,
+            ty,
+            weights: vec![],
+            normalize: false,
+        }
+    }
+    /// Creates a [`CombinatorType::Sum`] divided by the number of sounding synths.
+    pub fn normalized_sum(synths: Vec<Box<dyn Pom<Data>>>) -> Self {
+        Self {
+            normalize: true,
+            ..Self::new(synths, CombinatorType::Sum)
+        }
+    }
+    fn normalize_sum(&self, sum: f64, sounding: usize) -> f64 {
+        if self.normalize && sounding > 1 {
+            sum / sounding as f64
+        } else {
+            sum
+        }
+    }
+    /// Creates a [`CombinatorType::WeightedSum`].
+    pub fn weighted_sum(synths: Vec<Box<dyn Pom<Data>>>, weights: Vec<f64>) -> Self {
+        Self {
+            synths,
+            ty: CombinatorType::WeightedSum,
+            weights,
+            normalize: false,
+        }
+    }
+}
+impl Combinator<SampleBank> {
+    /// Creates a [`CombinatorType::Sum`] of `voices` copies of `template`, such as for a supersaw.
+    ///
+    /// The voices are detuned symmetrically, with the outermost voices `detune_cents` away from the template,
+    /// and panned symmetrically across `stereo_spread` (from 0 for none to 1 for hard left and right).
+    /// The voices are not attenuated, so the output gets louder with more voices.
+    /// If the template has a random start phase, each voice is given a different seed.
+    pub fn unison(
+        template: Operator,
+        voices: usize,
+        detune_cents: f64,
+        stereo_spread: f64,
+    ) -> Combinator<SampleBank> {
+        let synths = (0..voices)
+            .map(|voice| {
+                // from -1 for the first voice to 1 for the last
+                let position = if voices > 1 {
+                    voice as f64 * 2.0 / (voices - 1) as f64 - 1.0
+                } else {
+                    0.0
+                };
+                let mut operator = template.clone();
+                operator.modifiers.detune_cents += detune_cents * position;
+                operator.start_phase_seed = template.start_phase_seed.wrapping_add(voice as u64);
+                if stereo_spread == 0.0 {
+                    Box::new(operator) as Box<dyn Pom<SampleBank>>
+                } else {
+                    Box::new(Panned::new(Box::new(operator), stereo_spread * position))
+                }
+            })
+            .collect();
+        Combinator::new(synths, CombinatorType::Sum)
+    }
+}
+impl<Data: 'static> Pom<Data> for Combinator<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        match self.ty {
+            CombinatorType::Modulate => {
+                let mut carry = Some(phase_offset);
+                for op in &mut self.synths {
+                    let output = op.sample(data, global_time, carry.unwrap_or_default());
+                    // muted synths still run, but pass the phase offset through to the next synth
+                    if !op.is_muted() {
+                        carry = output;
+                    }
+                }
+                carry
+            }
+            CombinatorType::Sum => {
+                let (sum, sounding) = self
+                    .synths
+                    .iter_mut()
+                    .filter_map(|op| op.sample(data, global_time, phase_offset))
+                    .fold((0.0, 0), |(sum, sounding), sample| {
+                        (sum + sample, sounding + 1)
+                    });
+                Some(self.normalize_sum(sum, sounding))
+            }
+            CombinatorType::Average => {
+                let count = self.synths.len();
+                let sum: f64 = self
+                    .synths
+                    .iter_mut()
+                    .map(|op| {
+                        op.sample(data, global_time, phase_offset)
+                            .unwrap_or_default()
+                    })
+                    .sum();
+                Some(if count == 0 { 0.0 } else { sum / count as f64 })
+            }
+            CombinatorType::WeightedSum => Some(
+                self.synths
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(i, op)| {
+                        let weight = self.weights.get(i).copied().unwrap_or(1.0);
+                        op.sample(data, global_time, phase_offset)
                             .unwrap_or_default()
+                            * weight
                     })
                     .sum(),
             ),
+            CombinatorType::Multiply => Some(
+                self.synths
+                    .iter_mut()
+                    .map(|op| {
+                        op.sample(data, global_time, phase_offset)
+                            .unwrap_or_default()
+                    })
+                    .product(),
+            ),
+            CombinatorType::Min => self
+                .synths
+                .iter_mut()
+                .map(|op| {
+                    op.sample(data, global_time, phase_offset)
+                        .unwrap_or_default()
+                })
+                .reduce(f64::min),
+            CombinatorType::Max => self
+                .synths
+                .iter_mut()
+                .map(|op| {
+                    op.sample(data, global_time, phase_offset)
+                        .unwrap_or_default()
+                })
+                .reduce(f64::max),
         }
     }
 
@@ -390,116 +2567,3684 @@ impl<Data: 'static> Pom<Data> for Combinator<Data> {
             .iter_mut()
             .for_each(|op| op.play(frequency, volume));
     }
+    fn set_start(&mut self, when: Duration) {
+        self.synths.iter_mut().for_each(|op| op.set_start(when));
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.synths
+            .iter_mut()
+            .for_each(|op| op.set_frequency(frequency));
+    }
     fn cut(&mut self) {
         self.synths.iter_mut().for_each(|op| op.cut());
     }
     fn release(&mut self) {
         self.synths.iter_mut().for_each(|op| op.release());
     }
+    fn reset_phase(&mut self) {
+        self.synths.iter_mut().for_each(|op| op.reset_phase());
+    }
+    fn is_active(&self) -> bool {
+        self.synths.iter().any(|op| op.is_active())
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.synths
+            .iter()
+            .try_fold(Duration::ZERO, |max, op| Some(max.max(op.remaining()?)))
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.synths
+            .iter()
+            .filter_map(|op| op.current_volume(global_time))
+            .reduce(f64::max)
+    }
     fn box_clone(&self) -> Box<dyn Pom<Data>> {
         Box::new(Self {
             synths: self.synths.iter().map(|op| op.box_clone()).collect(),
+            weights: self.weights.clone(),
             ..*self
         })
     }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn as_stereo_mut(&mut self) -> Option<&mut dyn StereoPom<Data>> {
+        let has_stereo_synths = self
+            .synths
+            .iter_mut()
+            .any(|op| op.as_stereo_mut().is_some());
+        if self.ty == CombinatorType::Sum && has_stereo_synths {
+            Some(self)
+        } else {
+            None
+        }
+    }
+    fn to_serialized(&self) -> Option<SerializedSynth> {
+        Some(SerializedSynth::Combinator {
+            ty: self.ty,
+            weights: self.weights.clone(),
+            normalize: self.normalize,
+            synths: self
+                .synths
+                .iter()
+                .map(|op| op.to_serialized())
+                .collect::<Option<_>>()?,
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Binary)]
-pub enum StackInstruction {
-    /// Pushes a constant value.
-    Constant(f64),
-    /// Pushes the input (given as `phase_offset`).
-    InputPhaseOffset,
+/// Only [`CombinatorType::Sum`] combinators mix in stereo; mono synths are placed on both sides at full volume.
+/// When normalizing, both sides are divided by the number of sounding synths.
+/// Other combinator types produce their mono output on both sides.
+impl<Data: 'static> StereoPom<Data> for Combinator<Data> {
+    fn sample_stereo(
+        &mut self,
+        data: &Data,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<(f64, f64)> {
+        if self.ty != CombinatorType::Sum {
+            return self
+                .sample(data, global_time, phase_offset)
+                .map(|sample| (sample, sample));
+        }
+        let (left, right, sounding) = self
+            .synths
+            .iter_mut()
+            .filter_map(|op| op.sample_as_stereo(data, global_time, phase_offset))
+            .fold((0.0, 0.0, 0), |(left, right, sounding), (l, r)| {
+                (left + l, right + r, sounding + 1)
+            });
+        Some((
+            self.normalize_sum(left, sounding),
+            self.normalize_sum(right, sounding),
+        ))
+    }
+}
 
-    /// Pops a value,
-    /// then samples the given operator with that value as a phase offset,
-    /// pushing it back onto the stack.
-    Sample(u64),
-    /// Pops and computes the sum of the top two values of the stack.
-    Add,
-    /// Duplicates the top value of the stack.
-    Dupe,
+/// An event that changes the state of a synthesiser, corresponding to a [`Pom`] method.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum Event {
+    Play { frequency: f64, volume: f64 },
+    Release,
+    Cut,
+    SetFrequency(f64),
 }
-/// Combines operators together using a simple stack-based executor.
+
+/// Applies [`Event`]s to another synthesiser at scheduled global times, for sequencing.
 ///
-/// This allows you to freely modify operators and instructions without reconstruction,
-/// as you would need to do otherwise with more generic synths.
-#[derive(Debug, Clone, PartialEq, PartialOrd, Binary)]
-pub struct Stacker {
-    pub operators: Vec<Operator>,
-    pub instructions: Vec<StackInstruction>,
+/// Events are applied at the start of the first [`Pom::sample`] call whose global time has reached them.
+/// Played events start exactly at their scheduled time, even if it falls between samples.
+pub struct Scheduled<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    /// Events that have not been applied yet, sorted by time.
+    pub events: Vec<(Duration, Event)>,
 }
-impl Stacker {
-    pub fn chain(operators: Vec<Operator>) -> Self {
-        let mut instructions = vec![StackInstruction::InputPhaseOffset];
-        for i in (0..operators.len()).rev() {
-            instructions.push(StackInstruction::Sample(i as u64));
-        }
+impl<Data> Scheduled<Data> {
+    pub fn new(inner: Box<dyn Pom<Data>>) -> Self {
         Self {
-            operators,
-            instructions,
+            inner,
+            events: vec![],
         }
     }
-    pub fn add(operators: Vec<Operator>) -> Self {
-        let mut instructions = vec![];
-        if operators.is_empty() {
-            instructions.push(StackInstruction::InputPhaseOffset);
-        } else {
-            for i in 0..operators.len() {
-                instructions.push(StackInstruction::Constant(0.0));
-                instructions.push(StackInstruction::Sample(i as u64));
-                instructions.push(StackInstruction::Add);
+    /// Schedules `event` at `when`, in global time. Events at the same time are applied in the order they were scheduled.
+    pub fn schedule(&mut self, when: Duration, event: Event) {
+        let index = self.events.partition_point(|(time, _)| *time <= when);
+        self.events.insert(index, (when, event));
+    }
+    /// Removes every event that has not been applied yet.
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+    fn has_pending_play(&self) -> bool {
+        self.events
+            .iter()
+            .any(|(_, event)| matches!(event, Event::Play { .. }))
+    }
+}
+impl<Data: 'static> Pom<Data> for Scheduled<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let due = self
+            .events
+            .partition_point(|(time, _)| *time <= global_time);
+        for (time, event) in self.events.drain(..due) {
+            match event {
+                Event::Play { frequency, volume } => {
+                    self.inner.play(frequency, volume);
+                    self.inner.set_start(time);
+                }
+                Event::Release => self.inner.release(),
+                Event::Cut => self.inner.cut(),
+                Event::SetFrequency(frequency) => self.inner.set_frequency(frequency),
             }
         }
-        Self {
-            operators,
-            instructions,
+        self.inner.sample(data, global_time, phase_offset)
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active() || self.has_pending_play()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        if self.has_pending_play() {
+            None
+        } else {
+            self.inner.remaining()
         }
     }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            events: self.events.clone(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
-impl Pom<SampleBank> for Stacker {
-    fn sample(
-        &mut self,
-        data: &SampleBank,
-        global_time: Duration,
-        phase_offset: f64,
-    ) -> Option<f64> {
-        let mut stack = vec![];
-        for instruction in &self.instructions {
-            match instruction {
-                StackInstruction::Constant(constant) => stack.push(*constant),
-                StackInstruction::InputPhaseOffset => stack.push(phase_offset),
-                StackInstruction::Sample(op) => {
-                    let phase_offset = stack.pop().unwrap_or(0.0);
-                    let Some(op) = self.operators.get_mut(*op as usize) else {
-                        stack.push(0.0);
-                        break;
-                    };
-                    stack.push(op.sample(data, global_time, phase_offset).unwrap_or(0.0));
-                }
-                StackInstruction::Add => {
-                    let lhs = stack.pop().unwrap_or(0.0);
-                    let rhs = stack.pop().unwrap_or(0.0);
-                    stack.push(lhs + rhs);
-                }
-                StackInstruction::Dupe => stack.push(stack.last().copied().unwrap_or(0.0)),
-            }
+
+/// Hard-syncs one synthesiser to another: whenever the master's oscillator completes a period,
+/// the slave's phase is reset. Only the slave is heard.
+///
+/// The master must report its [`Pom::oscillator_period`], otherwise the slave is never reset.
+pub struct HardSync<Data> {
+    pub master: Box<dyn Pom<Data>>,
+    pub slave: Box<dyn Pom<Data>>,
+}
+impl<Data> HardSync<Data> {
+    pub fn new(master: Box<dyn Pom<Data>>, slave: Box<dyn Pom<Data>>) -> Self {
+        Self { master, slave }
+    }
+}
+impl<Data: 'static> Pom<Data> for HardSync<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let previous_period = self.master.oscillator_period();
+        self.master.sample(data, global_time, phase_offset);
+        if let (Some(previous), Some(current)) = (previous_period, self.master.oscillator_period())
+            && current.as_secs() > previous.as_secs()
+        {
+            // the master crossed a period boundary
+            self.slave.reset_phase();
         }
-        stack.pop()
+        self.slave.sample(data, global_time, phase_offset)
     }
 
     fn play(&mut self, frequency: f64, volume: f64) {
-        self.operators
-            .iter_mut()
-            .for_each(|op| op.play(frequency, volume));
+        self.master.play(frequency, volume);
+        self.slave.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.master.set_start(when);
+        self.slave.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.master.set_frequency(frequency);
+        self.slave.set_frequency(frequency);
     }
     fn cut(&mut self) {
-        self.operators.iter_mut().for_each(|op| op.cut());
+        self.master.cut();
+        self.slave.cut();
     }
     fn release(&mut self) {
-        self.operators.iter_mut().for_each(|op| op.release());
+        self.master.release();
+        self.slave.release();
     }
-    fn box_clone(&self) -> Box<dyn Pom<SampleBank>> {
-        Box::new(self.clone())
+    fn reset_phase(&mut self) {
+        self.master.reset_phase();
+        self.slave.reset_phase();
+    }
+    fn is_active(&self) -> bool {
+        self.slave.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.slave.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.slave.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            master: self.master.box_clone(),
+            slave: self.slave.box_clone(),
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Rhythmically gates another synthesiser, such as for trance gates: global time is divided into steps of `step`,
+/// and the carrier is muted during steps whose entry in `pattern` is false. The pattern repeats.
+///
+/// An empty pattern or a zero step leaves the carrier ungated. Notes are passed through to the carrier,
+/// which keeps running while muted.
+pub struct Gate<Data> {
+    pub carrier: Box<dyn Pom<Data>>,
+    pub pattern: Vec<bool>,
+    pub step: Duration,
+}
+impl<Data> Gate<Data> {
+    pub fn new(carrier: Box<dyn Pom<Data>>, pattern: Vec<bool>, step: Duration) -> Self {
+        Self {
+            carrier,
+            pattern,
+            step,
+        }
+    }
+    /// Whether the carrier is heard at `global_time`.
+    pub fn is_open(&self, global_time: Duration) -> bool {
+        if self.pattern.is_empty() || self.step.is_zero() {
+            return true;
+        }
+        let step = global_time.as_nanos() / self.step.as_nanos();
+        self.pattern[(step % self.pattern.len() as u128) as usize]
+    }
+}
+impl<Data: 'static> Pom<Data> for Gate<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let output = self.carrier.sample(data, global_time, phase_offset)?;
+        Some(if self.is_open(global_time) {
+            output
+        } else {
+            0.0
+        })
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.carrier.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.carrier.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.carrier.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.carrier.cut();
+    }
+    fn release(&mut self) {
+        self.carrier.release();
+    }
+    fn reset_phase(&mut self) {
+        self.carrier.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.carrier.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.carrier.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.carrier.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.carrier.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        let volume = self.carrier.current_volume(global_time)?;
+        Some(if self.is_open(global_time) {
+            volume
+        } else {
+            0.0
+        })
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            carrier: self.carrier.box_clone(),
+            pattern: self.pattern.clone(),
+            step: self.step,
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The amount of past outputs a [`Chorus`] remembers, which limits its longest delay
+/// (about 170ms at 48kHz).
+pub const CHORUS_BUFFER_LENGTH: usize = 8192;
+
+/// Thickens another synthesiser by mixing its output with a copy of itself,
+/// delayed by a time that a sine LFO sweeps between `delay - depth` and `delay + depth`.
+///
+/// Delays are converted to samples using the interval between the last two sampled times,
+/// so sampling should happen at a steady rate. Delays longer than the buffer are shortened.
+///
+/// Once `inner` has ended, the delayed signal keeps playing until everything in the buffer is inaudible.
+pub struct Chorus<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    /// The delay at the centre of the sweep.
+    pub delay: Duration,
+    /// How far the delay sweeps either side of `delay`.
+    pub depth: Duration,
+    /// The frequency of the LFO sweeping the delay, in hertz.
+    pub rate: f64,
+    /// How much of the delayed signal is heard, from 0 for only the dry signal to 1 for only the delayed signal.
+    pub mix: f64,
+    /// A ring buffer of past outputs of `inner`, [`CHORUS_BUFFER_LENGTH`] long by default.
+    pub buffer: Vec<f64>,
+    /// Where the latest output was written in `buffer`.
+    pub cursor: usize,
+    /// The phase of the LFO, within [0, 1).
+    pub lfo_phase: f64,
+    pub sample_interval: Duration,
+    pub last_global_time: Option<Duration>,
+    /// How many inaudible outputs have been written to `buffer` in a row.
+    /// Once the whole buffer is inaudible, so is the delayed signal.
+    silent_samples: usize,
+}
+impl<Data> Chorus<Data> {
+    pub fn new(
+        inner: Box<dyn Pom<Data>>,
+        delay: Duration,
+        depth: Duration,
+        rate: f64,
+        mix: f64,
+    ) -> Self {
+        Self {
+            inner,
+            delay,
+            depth,
+            rate,
+            mix,
+            buffer: vec![0.0; CHORUS_BUFFER_LENGTH],
+            cursor: 0,
+            lfo_phase: 0.0,
+            sample_interval: Duration::ZERO,
+            last_global_time: None,
+            silent_samples: CHORUS_BUFFER_LENGTH,
+        }
+    }
+    /// Whether everything in the buffer is inaudible, so the delayed signal has ended.
+    fn tail_ended(&self) -> bool {
+        self.silent_samples >= self.buffer.len()
+    }
+    /// How long `samples` samples last at the current sample interval.
+    fn samples_duration(&self, samples: usize) -> Duration {
+        self.sample_interval
+            .saturating_mul(samples.try_into().unwrap_or(u32::MAX))
+    }
+    /// Gets the output from `samples_ago` samples ago, linearly interpolating between samples.
+    fn tap(&self, samples_ago: f64) -> f64 {
+        let length = self.buffer.len();
+        if length < 2 {
+            return 0.0;
+        }
+        let samples_ago = samples_ago.clamp(0.0, (length - 2) as f64);
+        let whole = samples_ago.floor();
+        let fraction = samples_ago - whole;
+        let at = |ago: usize| self.buffer[(self.cursor + length - ago) % length];
+        let newer = at(whole as usize);
+        let older = at(whole as usize + 1);
+        newer + (older - newer) * fraction
+    }
+}
+impl<Data: 'static> Pom<Data> for Chorus<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let delta_time =
+            global_time.saturating_sub(*self.last_global_time.get_or_insert(global_time));
+        self.last_global_time = Some(global_time);
+        if !delta_time.is_zero() {
+            self.sample_interval = delta_time;
+        }
+        self.lfo_phase = (self.lfo_phase + self.rate * delta_time.as_secs_f64()).rem_euclid(1.0);
+
+        let output = self.inner.sample(data, global_time, phase_offset);
+        let dry = output.unwrap_or_default();
+        if !self.buffer.is_empty() {
+            self.cursor = (self.cursor + 1) % self.buffer.len();
+            self.buffer[self.cursor] = dry;
+        }
+        self.silent_samples = if dry.abs() < INAUDIBLE_LEVEL {
+            self.silent_samples.saturating_add(1)
+        } else {
+            0
+        };
+        if output.is_none() && self.tail_ended() {
+            return None;
+        }
+        if self.sample_interval.is_zero() {
+            return Some(dry);
+        }
+        let delay =
+            self.delay.as_secs_f64() + self.depth.as_secs_f64() * (self.lfo_phase * TAU).sin();
+        let wet = self.tap(delay / self.sample_interval.as_secs_f64());
+        Some(dry + (wet - dry) * self.mix)
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+        self.buffer.fill(0.0);
+        self.silent_samples = self.buffer.len();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active() || !self.tail_ended()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        let remaining = self.inner.remaining()?;
+        Some(if self.inner.is_active() {
+            // everything up to the end of `inner` is delayed by up to the length of the buffer
+            remaining.saturating_add(self.samples_duration(self.buffer.len()))
+        } else {
+            self.samples_duration(self.buffer.len().saturating_sub(self.silent_samples))
+        })
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            buffer: self.buffer.clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The most allpass stages a [`Phaser`] can have.
+pub const MAX_PHASER_STAGES: usize = 64;
+
+/// The state of one first-order allpass stage of a [`Phaser`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct AllpassState {
+    pub last_input: f64,
+    pub last_output: f64,
+}
+impl AllpassState {
+    /// Filters one sample with the allpass coefficient `coefficient`, which sets where the stage shifts the phase by 90°.
+    pub fn process(&mut self, coefficient: f64, input: f64) -> f64 {
+        let output = coefficient * input + self.last_input - coefficient * self.last_output;
+        self.last_input = input;
+        self.last_output = output;
+        output
+    }
+}
+
+/// Sweeps notches through the spectrum of another synthesiser by mixing its output with a copy
+/// passed through a cascade of allpass filters, whose corner frequency a sine LFO sweeps.
+///
+/// Every two stages add a notch where the delayed copy cancels the dry signal, which is deepest when `mix` is 0.5.
+/// The corner frequency is computed using the interval between the last two sampled times,
+/// so sampling should happen at a steady rate.
+pub struct Phaser<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    /// The corner frequency at the centre of the sweep, in hertz.
+    pub frequency: f64,
+    /// How far the corner frequency sweeps either side of `frequency`, in octaves.
+    pub depth: f64,
+    /// The frequency of the LFO sweeping the corner frequency, in hertz.
+    pub rate: f64,
+    /// How much of the filtered signal is fed back into the cascade, from -1 to 1, which sharpens the notches.
+    pub feedback: f64,
+    /// How much of the filtered signal is heard, from 0 for only the dry signal to 1 for only the filtered signal.
+    pub mix: f64,
+    /// The state of each allpass stage, at most [`MAX_PHASER_STAGES`] long.
+    pub stages: Vec<AllpassState>,
+    /// The phase of the LFO, within [0, 1).
+    pub lfo_phase: f64,
+    /// The last output of the allpass cascade, used for feedback.
+    pub last_wet: f64,
+    pub sample_interval: Duration,
+    pub last_global_time: Option<Duration>,
+}
+impl<Data> Phaser<Data> {
+    /// `stages` is limited to [`MAX_PHASER_STAGES`].
+    pub fn new(
+        inner: Box<dyn Pom<Data>>,
+        stages: usize,
+        frequency: f64,
+        depth: f64,
+        rate: f64,
+        feedback: f64,
+        mix: f64,
+    ) -> Self {
+        Self {
+            inner,
+            frequency,
+            depth,
+            rate,
+            feedback,
+            mix,
+            stages: vec![AllpassState::default(); stages.min(MAX_PHASER_STAGES)],
+            lfo_phase: 0.0,
+            last_wet: 0.0,
+            sample_interval: Duration::ZERO,
+            last_global_time: None,
+        }
+    }
+    /// The allpass coefficient for the current position of the LFO.
+    fn coefficient(&self) -> f64 {
+        let corner = self.frequency * 2f64.powf(self.depth * (self.lfo_phase * TAU).sin());
+        // limited to just below the Nyquist frequency, where the coefficient would reach 1
+        let warped = (PI * corner * self.sample_interval.as_secs_f64()).min(PI / 2.0 - 1e-3);
+        let tan = warped.tan();
+        let coefficient = (tan - 1.0) / (tan + 1.0);
+        if coefficient.is_finite() {
+            coefficient
+        } else {
+            0.0
+        }
+    }
+    fn clear(&mut self) {
+        self.stages.fill(AllpassState::default());
+        self.last_wet = 0.0;
+    }
+}
+impl<Data: 'static> Pom<Data> for Phaser<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let delta_time =
+            global_time.saturating_sub(*self.last_global_time.get_or_insert(global_time));
+        self.last_global_time = Some(global_time);
+        if !delta_time.is_zero() {
+            self.sample_interval = delta_time;
+        }
+        self.lfo_phase = (self.lfo_phase + self.rate * delta_time.as_secs_f64()).rem_euclid(1.0);
+
+        let dry = self.inner.sample(data, global_time, phase_offset)?;
+        if self.sample_interval.is_zero() {
+            return Some(dry);
+        }
+        let coefficient = self.coefficient();
+        let feedback = if self.feedback.is_nan() {
+            0.0
+        } else {
+            self.feedback.clamp(-0.99, 0.99)
+        };
+        let wet = self
+            .stages
+            .iter_mut()
+            .fold(dry + self.last_wet * feedback, |signal, stage| {
+                stage.process(coefficient, signal)
+            });
+        self.last_wet = if wet.is_finite() { wet } else { 0.0 };
+        Some(dry + (self.last_wet - dry) * self.mix)
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+        self.clear();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.inner.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            stages: self.stages.clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The amount of taps per unit of oversampling in the decimation filter of an [`Oversampled`] synthesiser.
+pub const OVERSAMPLING_TAPS_PER_FACTOR: usize = 32;
+
+/// Reduces aliasing from nonlinear waveforms and feedback by sampling another synthesiser `factor` times
+/// per sample, then low-pass filtering and decimating the result.
+///
+/// The decimation filter is a Blackman-windowed sinc with [`OVERSAMPLING_TAPS_PER_FACTOR`] taps per unit of
+/// `factor`, cutting off just below the Nyquist frequency of the output. Its state persists between samples,
+/// so when oversampling, the output is delayed by half the filter's length, which is 16 samples.
+/// The filter's contents keep playing after `inner` ends, and [`Oversampled::is_active`] and
+/// [`Oversampled::remaining`] include them, but cutting ends the output immediately.
+///
+/// Like [`Chorus`], the extra sample times are spread over the interval between the last two sampled times,
+/// so sampling should happen at a steady rate.
+pub struct Oversampled<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    factor: u32,
+    coefficients: Vec<f64>,
+    /// A ring buffer of the latest oversampled outputs of `inner`, as long as `coefficients`.
+    history: Vec<f64>,
+    /// Where the latest output was written in `history`.
+    cursor: usize,
+    /// How many oversampled outputs in a row `inner` has been off for.
+    silent_samples: usize,
+    /// The time between the last two sampled times.
+    sample_interval: Duration,
+    pub last_global_time: Option<Duration>,
+}
+impl<Data> Oversampled<Data> {
+    /// A `factor` of 0 is treated as 1, which passes `inner` through unchanged.
+    pub fn new(inner: Box<dyn Pom<Data>>, factor: u32) -> Self {
+        let mut oversampled = Self {
+            inner,
+            factor: 1,
+            coefficients: vec![],
+            history: vec![],
+            cursor: 0,
+            silent_samples: 0,
+            sample_interval: Duration::ZERO,
+            last_global_time: None,
+        };
+        oversampled.set_factor(factor);
+        oversampled
+    }
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+    /// Changes the amount of oversampling, clearing the decimation filter's state if it changes.
+    pub fn set_factor(&mut self, factor: u32) {
+        let factor = factor.max(1);
+        if factor == self.factor && !self.coefficients.is_empty() {
+            return;
+        }
+        self.factor = factor;
+        self.coefficients = if factor == 1 {
+            vec![1.0]
+        } else {
+            Self::decimation_filter(factor)
+        };
+        self.history = vec![0.0; self.coefficients.len()];
+        self.cursor = 0;
+        self.silent_samples = self.history.len();
+    }
+    /// Whether the filter has been flushed of the output of `inner`, so the delayed signal has ended.
+    fn tail_ended(&self) -> bool {
+        self.silent_samples >= self.history.len()
+    }
+    /// How long `samples` oversampled outputs last at the current sample interval, rounded up to whole samples.
+    fn samples_duration(&self, samples: usize) -> Duration {
+        let samples = samples.div_ceil(self.factor as usize);
+        self.sample_interval
+            .saturating_mul(samples.try_into().unwrap_or(u32::MAX))
+    }
+    /// A windowed-sinc low-pass filter for decimating by `factor`, normalised to unity gain.
+    fn decimation_filter(factor: u32) -> Vec<f64> {
+        let length = OVERSAMPLING_TAPS_PER_FACTOR * factor as usize + 1;
+        let middle = (length - 1) as f64 / 2.0;
+        // just below the output's Nyquist frequency, in cycles per oversampled sample
+        let cutoff = 0.45 / factor as f64;
+        let coefficients: Vec<f64> = (0..length)
+            .map(|n| {
+                let x = n as f64 - middle;
+                let sinc = if x == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (TAU * cutoff * x).sin() / (PI * x)
+                };
+                let window_phase = TAU * n as f64 / (length - 1) as f64;
+                let window = 0.42 - 0.5 * window_phase.cos() + 0.08 * (2.0 * window_phase).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: f64 = coefficients.iter().sum();
+        coefficients.into_iter().map(|c| c / sum).collect()
+    }
+}
+impl<Data: 'static> Pom<Data> for Oversampled<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let last_global_time = *self.last_global_time.get_or_insert(global_time);
+        self.last_global_time = Some(global_time);
+        let delta_time = global_time.saturating_sub(last_global_time);
+        if !delta_time.is_zero() {
+            self.sample_interval = delta_time;
+        }
+
+        let length = self.history.len();
+        for step in 1..=self.factor {
+            let time = if step == self.factor {
+                global_time
+            } else {
+                last_global_time.saturating_add(time::duration_saturating_mul_f64(
+                    delta_time,
+                    step as f64 / self.factor as f64,
+                ))
+            };
+            let output = self.inner.sample(data, time, phase_offset);
+            self.silent_samples = match output {
+                Some(_) => 0,
+                None => self.silent_samples.saturating_add(1),
+            };
+            self.cursor = (self.cursor + 1) % length;
+            self.history[self.cursor] = output.unwrap_or_default();
+        }
+        // the note has ended once the filter has been flushed of its output
+        if self.tail_ended() {
+            return None;
+        }
+        Some(
+            self.coefficients
+                .iter()
+                .enumerate()
+                .map(|(ago, coefficient)| {
+                    coefficient * self.history[(self.cursor + length - ago) % length]
+                })
+                .sum(),
+        )
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+        self.history.fill(0.0);
+        self.silent_samples = self.history.len();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active() || !self.tail_ended()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        let remaining = self.inner.remaining()?;
+        Some(if self.inner.is_active() {
+            // everything up to the end of `inner` is delayed by up to the length of the filter
+            remaining.saturating_add(self.samples_duration(self.history.len()))
+        } else {
+            self.samples_duration(self.history.len().saturating_sub(self.silent_samples))
+        })
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            coefficients: self.coefficients.clone(),
+            history: self.history.clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A synthesiser that can place its output in a stereo field.
+pub trait StereoPom<Data>: Pom<Data> {
+    /// Samples the synthesiser as a `(left, right)` pair. `None` has the same meaning as in [`Pom::sample`].
+    fn sample_stereo(
+        &mut self,
+        data: &Data,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<(f64, f64)>;
+}
+
+/// The `(left, right)` gains of the equal-power panning law for `pan`, from -1 (hard left) to 1 (hard right).
+/// The centre is 3dB down on both sides, and hard pans are at full volume on one side.
+fn equal_power_gains(pan: f64) -> (f64, f64) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+    (angle.cos(), angle.sin())
+}
+
+/// Pans another synthesiser using the equal-power panning law, so the center is 3dB down on both sides.
+///
+/// Sampling in mono produces the signal before panning.
+pub struct Panned<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    /// The pan position, from -1 (hard left) to 1 (hard right).
+    pub pan: f64,
+}
+impl<Data> Panned<Data> {
+    pub fn new(inner: Box<dyn Pom<Data>>, pan: f64) -> Self {
+        Self { inner, pan }
+    }
+    /// The `(left, right)` gains for the current pan.
+    pub fn gains(&self) -> (f64, f64) {
+        equal_power_gains(self.pan)
+    }
+}
+impl<Data: 'static> Pom<Data> for Panned<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        self.inner.sample(data, global_time, phase_offset)
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.inner.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn as_stereo_mut(&mut self) -> Option<&mut dyn StereoPom<Data>> {
+        Some(self)
+    }
+}
+impl<Data: 'static> StereoPom<Data> for Panned<Data> {
+    fn sample_stereo(
+        &mut self,
+        data: &Data,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<(f64, f64)> {
+        let (left, right) = self.gains();
+        self.inner
+            .sample(data, global_time, phase_offset)
+            .map(|sample| (sample * left, sample * right))
+    }
+}
+
+/// Places another synthesiser in the stereo field with `pan` and `width` controls.
+///
+/// Mono synthesisers are treated as centred (see [`Pom::sample_as_stereo`]), so `width` only affects stereo ones,
+/// scaling the difference between their sides. Panning uses the same equal-power law as [`Panned`], so the centre
+/// is 3dB down on both sides, and a mono signal keeps the same total power (`left² + right²`) wherever it is panned.
+///
+/// Sampling in mono produces the signal before panning.
+pub struct Stereo<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    /// The pan position, from -1 (hard left) to 1 (hard right).
+    pub pan: f64,
+    /// The stereo width, from 0 (mono) through 1 (unchanged) and beyond (wider).
+    pub width: f64,
+}
+impl<Data> Stereo<Data> {
+    pub fn new(inner: Box<dyn Pom<Data>>, pan: f64, width: f64) -> Self {
+        Self { inner, pan, width }
+    }
+    /// The `(left, right)` gains for the current pan. See [`Panned::gains`].
+    pub fn gains(&self) -> (f64, f64) {
+        equal_power_gains(self.pan)
+    }
+}
+impl<Data: 'static> Pom<Data> for Stereo<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        self.inner.sample(data, global_time, phase_offset)
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.inner.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn as_stereo_mut(&mut self) -> Option<&mut dyn StereoPom<Data>> {
+        Some(self)
+    }
+}
+impl<Data: 'static> StereoPom<Data> for Stereo<Data> {
+    fn sample_stereo(
+        &mut self,
+        data: &Data,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<(f64, f64)> {
+        let (left, right) = self
+            .inner
+            .sample_as_stereo(data, global_time, phase_offset)?;
+        let mid = (left + right) / 2.0;
+        let side = (left - right) / 2.0 * self.width;
+        let (left_gain, right_gain) = self.gains();
+        Some(((mid + side) * left_gain, (mid - side) * right_gain))
+    }
+}
+
+/// A parameter of a synthesiser that a [`Modulated`] synthesiser can modulate.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum ModulationTarget {
+    /// Vibrato. `depth` is the peak deviation in semitones.
+    Frequency,
+    /// Tremolo. `depth` is the fraction of the volume removed at the LFO's lowest point, within [0, 1].
+    Volume,
+}
+/// Modulates the frequency or volume of another synthesiser with a low-frequency oscillator (LFO).
+///
+/// The LFO is evaluated at the global time, so it runs freely rather than restarting with each note.
+pub struct Modulated<Data> {
+    pub inner: Box<dyn Pom<Data>>,
+    pub target: ModulationTarget,
+    pub lfo: Waveform,
+    /// The frequency of the LFO, in hertz.
+    pub rate: f64,
+    pub depth: f64,
+    /// The frequency last given to the synthesiser, before modulation.
+    pub base_frequency: f64,
+}
+impl<Data> Modulated<Data> {
+    pub fn new(
+        inner: Box<dyn Pom<Data>>,
+        target: ModulationTarget,
+        lfo: Waveform,
+        rate: f64,
+        depth: f64,
+    ) -> Self {
+        Self {
+            inner,
+            target,
+            lfo,
+            rate,
+            depth,
+            base_frequency: 0.0,
+        }
+    }
+}
+impl<Data: Borrow<SampleBank> + 'static> Pom<Data> for Modulated<Data> {
+    fn sample(&mut self, data: &Data, global_time: Duration, phase_offset: f64) -> Option<f64> {
+        let lfo = self.lfo.sample(
+            data.borrow(),
+            time::duration_saturating_mul_f64(global_time, self.rate),
+            0.0,
+        );
+        match self.target {
+            ModulationTarget::Frequency => {
+                self.inner
+                    .set_frequency(self.base_frequency * 2f64.powf(self.depth * lfo / 12.0));
+                self.inner.sample(data, global_time, phase_offset)
+            }
+            ModulationTarget::Volume => {
+                let gain = 1.0 - self.depth * (1.0 - lfo) / 2.0;
+                self.inner
+                    .sample(data, global_time, phase_offset)
+                    .map(|sample| sample * gain)
+            }
+        }
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.base_frequency = frequency;
+        self.inner.play(frequency, volume);
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.inner.set_start(when);
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.base_frequency = frequency;
+        self.inner.set_frequency(frequency);
+    }
+    fn cut(&mut self) {
+        self.inner.cut();
+    }
+    fn release(&mut self) {
+        self.inner.release();
+    }
+    fn reset_phase(&mut self) {
+        self.inner.reset_phase();
+    }
+    fn oscillator_period(&self) -> Option<Period> {
+        self.inner.oscillator_period()
+    }
+    fn is_muted(&self) -> bool {
+        self.inner.is_muted()
+    }
+    fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.inner.remaining()
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.inner.current_volume(global_time)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<Data>> {
+        Box::new(Self {
+            inner: self.inner.box_clone(),
+            lfo: self.lfo.clone(),
+            ..*self
+        })
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum StackInstruction {
+    /// Pushes a constant value.
+    Constant(f64),
+    /// Pushes the input (given as `phase_offset`).
+    InputPhaseOffset,
+
+    /// Pops a value,
+    /// then samples the given operator with that value as a phase offset,
+    /// pushing it back onto the stack.
+    ///
+    /// Each operator is only sampled once per sample of the [`Stacker`]; sampling it again pushes the
+    /// same output as the first time, ignoring the new phase offset, so its phase only advances once.
+    Sample(u64),
+    /// Pops and computes the sum of the top two values of the stack.
+    Add,
+    /// Pops the top two values of the stack,
+    /// then pushes the second-from-top value minus the top value.
+    Sub,
+    /// Pops and computes the product of the top two values of the stack.
+    Mul,
+    /// Pops a value, then pushes its negation.
+    Neg,
+    /// Duplicates the top value of the stack.
+    Dupe,
+    /// Exchanges the top two values of the stack.
+    Swap,
+    /// Rotates the top three values of the stack, bringing the third-from-top value to the top:
+    /// `a b c` becomes `b c a`.
+    Rot,
+    /// Pops a selector, then two values, pushing the second-from-top value back if the selector
+    /// is greater than `threshold`, or the top value otherwise: `a b selector` becomes `a` or `b`.
+    ///
+    /// A NaN selector selects the top value. This allows splits and layers, such as by velocity,
+    /// to be made within the program.
+    ///
+    /// ```
+    /// # use pommel::{Operator, Pom, SampleBank, StackInstruction, Stacker, Waveform};
+    /// # use std::time::Duration;
+    /// // plays the hard layer for velocities above 0.5, and the soft layer otherwise
+    /// let layered = |velocity| Stacker {
+    ///     operators: vec![
+    ///         Operator::builder().waveform(Waveform::Constant(0.25)).build(),
+    ///         Operator::builder().waveform(Waveform::Constant(1.0)).build(),
+    ///     ],
+    ///     instructions: vec![
+    ///         StackInstruction::Constant(0.0),
+    ///         StackInstruction::Sample(1),
+    ///         StackInstruction::Constant(0.0),
+    ///         StackInstruction::Sample(0),
+    ///         StackInstruction::Constant(velocity),
+    ///         StackInstruction::SelectIf { threshold: 0.5 },
+    ///     ],
+    /// };
+    /// let time = Duration::from_millis(100);
+    /// for (velocity, expected) in [(0.3, 0.25), (0.9, 1.0)] {
+    ///     let mut stacker = layered(velocity);
+    ///     let mut compiled = stacker.compile().unwrap();
+    ///     stacker.play(440.0, 1.0);
+    ///     compiled.play(440.0, 1.0);
+    ///     assert_eq!(stacker.sample(&SampleBank::new(), time, 0.0), Some(expected));
+    ///     assert_eq!(compiled.sample(&SampleBank::new(), time, 0.0), Some(expected));
+    /// }
+    /// ```
+    SelectIf { threshold: f64 },
+}
+impl StackInstruction {
+    /// The amount of values this instruction pops from the stack, and the amount it then pushes.
+    pub fn stack_effect(&self) -> (usize, usize) {
+        match self {
+            StackInstruction::Constant(_) | StackInstruction::InputPhaseOffset => (0, 1),
+            StackInstruction::Sample(_) | StackInstruction::Neg => (1, 1),
+            StackInstruction::Add | StackInstruction::Sub | StackInstruction::Mul => (2, 1),
+            StackInstruction::Dupe => (1, 2),
+            StackInstruction::Swap => (2, 2),
+            StackInstruction::Rot => (3, 3),
+            StackInstruction::SelectIf { .. } => (3, 1),
+        }
+    }
+}
+
+/// A problem found when validating a [`Stacker`] program.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StackError {
+    /// The program has no instructions, so it would never produce a value.
+    EmptyProgram,
+    /// The instruction at index `instruction` samples an operator that does not exist.
+    OperatorOutOfRange { instruction: usize, operator: u64 },
+    /// The instruction at index `instruction` pops more values than are on the stack.
+    StackUnderflow { instruction: usize },
+}
+impl core::fmt::Display for StackError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StackError::EmptyProgram => write!(f, "the program is empty"),
+            StackError::OperatorOutOfRange {
+                instruction,
+                operator,
+            } => write!(
+                f,
+                "instruction {instruction} samples operator {operator}, which does not exist"
+            ),
+            StackError::StackUnderflow { instruction } => {
+                write!(f, "instruction {instruction} underflows the stack")
+            }
+        }
+    }
+}
+impl core::error::Error for StackError {}
+/// Combines operators together using a simple stack-based executor.
+///
+/// This allows you to freely modify operators and instructions without reconstruction,
+/// as you would need to do otherwise with more generic synths.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub struct Stacker {
+    pub operators: Vec<Operator>,
+    pub instructions: Vec<StackInstruction>,
+}
+impl Stacker {
+    pub fn chain(operators: Vec<Operator>) -> Self {
+        let mut instructions = vec![StackInstruction::InputPhaseOffset];
+        for i in (0..operators.len()).rev() {
+            instructions.push(StackInstruction::Sample(i as u64));
+        }
+        Self {
+            operators,
+            instructions,
+        }
+    }
+    pub fn add(operators: Vec<Operator>) -> Self {
+        let mut instructions = vec![];
+        if operators.is_empty() {
+            instructions.push(StackInstruction::InputPhaseOffset);
+        } else {
+            for i in 0..operators.len() {
+                instructions.push(StackInstruction::Constant(0.0));
+                instructions.push(StackInstruction::Sample(i as u64));
+                if i > 0 {
+                    instructions.push(StackInstruction::Add);
+                }
+            }
+        }
+        Self {
+            operators,
+            instructions,
+        }
+    }
+    /// Statically checks the program for problems, returning the first one found.
+    ///
+    /// A valid program only samples operators that exist and never pops from an empty stack.
+    /// Invalid programs can still be sampled, but missing operators and values are replaced with 0.
+    pub fn validate(&self) -> Result<(), StackError> {
+        if self.instructions.is_empty() {
+            return Err(StackError::EmptyProgram);
+        }
+        let mut depth = 0usize;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if let StackInstruction::Sample(operator) = instruction
+                && *operator >= self.operators.len() as u64
+            {
+                return Err(StackError::OperatorOutOfRange {
+                    instruction: index,
+                    operator: *operator,
+                });
+            }
+            let (pops, pushes) = instruction.stack_effect();
+            depth = depth
+                .checked_sub(pops)
+                .ok_or(StackError::StackUnderflow { instruction: index })?
+                + pushes;
+        }
+        Ok(())
+    }
+    /// Validates the program, then compiles it into a form that is faster to sample.
+    pub fn compile(&self) -> Result<CompiledStacker, StackError> {
+        self.validate()?;
+        let mut sampled = vec![false; self.operators.len()];
+        let mut depth = 0usize;
+        let mut max_depth = 0usize;
+        let instructions = self
+            .instructions
+            .iter()
+            .map(|instruction| {
+                let (pops, pushes) = instruction.stack_effect();
+                depth = depth - pops + pushes;
+                max_depth = max_depth.max(depth);
+                match *instruction {
+                    StackInstruction::Constant(constant) => CompiledInstruction::Constant(constant),
+                    StackInstruction::InputPhaseOffset => CompiledInstruction::InputPhaseOffset,
+                    StackInstruction::Sample(operator) => {
+                        let operator = operator as usize;
+                        if core::mem::replace(&mut sampled[operator], true) {
+                            CompiledInstruction::Reuse(operator)
+                        } else {
+                            CompiledInstruction::Sample(operator)
+                        }
+                    }
+                    StackInstruction::Add => CompiledInstruction::Add,
+                    StackInstruction::Sub => CompiledInstruction::Sub,
+                    StackInstruction::Mul => CompiledInstruction::Mul,
+                    StackInstruction::Neg => CompiledInstruction::Neg,
+                    StackInstruction::Dupe => CompiledInstruction::Dupe,
+                    StackInstruction::Swap => CompiledInstruction::Swap,
+                    StackInstruction::Rot => CompiledInstruction::Rot,
+                    StackInstruction::SelectIf { threshold } => {
+                        CompiledInstruction::SelectIf(threshold)
+                    }
+                }
+            })
+            .collect();
+        Ok(CompiledStacker {
+            operators: self.operators.clone(),
+            source: self.instructions.clone(),
+            instructions,
+            stack: vec![0.0; max_depth],
+            outputs: vec![0.0; self.operators.len()],
+        })
+    }
+}
+impl Pom<SampleBank> for Stacker {
+    fn sample(
+        &mut self,
+        data: &SampleBank,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<f64> {
+        let mut stack = vec![];
+        // outputs of operators that have already been sampled this tick
+        let mut outputs = vec![None; self.operators.len()];
+        for instruction in &self.instructions {
+            match instruction {
+                StackInstruction::Constant(constant) => stack.push(*constant),
+                StackInstruction::InputPhaseOffset => stack.push(phase_offset),
+                StackInstruction::Sample(index) => {
+                    let phase_offset = stack.pop().unwrap_or(0.0);
+                    let Some(op) = self.operators.get_mut(*index as usize) else {
+                        stack.push(0.0);
+                        break;
+                    };
+                    let output = *outputs[*index as usize].get_or_insert_with(|| {
+                        let output = op.sample(data, global_time, phase_offset).unwrap_or(0.0);
+                        if op.muted { phase_offset } else { output }
+                    });
+                    stack.push(output);
+                }
+                StackInstruction::Add => {
+                    let lhs = stack.pop().unwrap_or(0.0);
+                    let rhs = stack.pop().unwrap_or(0.0);
+                    stack.push(lhs + rhs);
+                }
+                StackInstruction::Sub => {
+                    let rhs = stack.pop().unwrap_or(0.0);
+                    let lhs = stack.pop().unwrap_or(0.0);
+                    stack.push(lhs - rhs);
+                }
+                StackInstruction::Mul => {
+                    let lhs = stack.pop().unwrap_or(0.0);
+                    let rhs = stack.pop().unwrap_or(0.0);
+                    stack.push(lhs * rhs);
+                }
+                StackInstruction::Neg => {
+                    let value = stack.pop().unwrap_or(0.0);
+                    stack.push(-value);
+                }
+                StackInstruction::Dupe => stack.push(stack.last().copied().unwrap_or(0.0)),
+                StackInstruction::Swap => {
+                    let top = stack.pop().unwrap_or(0.0);
+                    let second = stack.pop().unwrap_or(0.0);
+                    stack.push(top);
+                    stack.push(second);
+                }
+                StackInstruction::Rot => {
+                    let c = stack.pop().unwrap_or(0.0);
+                    let b = stack.pop().unwrap_or(0.0);
+                    let a = stack.pop().unwrap_or(0.0);
+                    stack.push(b);
+                    stack.push(c);
+                    stack.push(a);
+                }
+                StackInstruction::SelectIf { threshold } => {
+                    let selector = stack.pop().unwrap_or(0.0);
+                    let b = stack.pop().unwrap_or(0.0);
+                    let a = stack.pop().unwrap_or(0.0);
+                    stack.push(if selector > *threshold { a } else { b });
+                }
+            }
+        }
+        stack.pop()
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.operators
+            .iter_mut()
+            .for_each(|op| op.play(frequency, volume));
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.operators.iter_mut().for_each(|op| op.set_start(when));
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.operators
+            .iter_mut()
+            .for_each(|op| op.set_frequency(frequency));
+    }
+    fn cut(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.cut());
+    }
+    fn release(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.release());
+    }
+    fn reset_phase(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.reset_phase());
+    }
+    fn is_active(&self) -> bool {
+        self.operators.iter().any(|op| op.is_active())
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.operators
+            .iter()
+            .try_fold(Duration::ZERO, |max, op| Some(max.max(op.remaining()?)))
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.operators
+            .iter()
+            .filter_map(|op| op.current_volume(global_time))
+            .reduce(f64::max)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<SampleBank>> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn to_serialized(&self) -> Option<SerializedSynth> {
+        Some(SerializedSynth::Stacker(self.clone()))
+    }
+}
+
+/// A [`StackInstruction`] whose operands have been checked by [`Stacker::compile`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum CompiledInstruction {
+    Constant(f64),
+    InputPhaseOffset,
+    /// The first sample of an operator in the program.
+    Sample(usize),
+    /// A later sample of an operator in the program, which pushes its first output again.
+    Reuse(usize),
+    Add,
+    Sub,
+    Mul,
+    Neg,
+    Dupe,
+    Swap,
+    Rot,
+    SelectIf(f64),
+}
+
+/// A [`Stacker`] whose program has been validated and compiled by [`Stacker::compile`].
+///
+/// The program is known not to underflow, and needs no allocation to sample.
+/// Keep the [`Stacker`] around for editing, and compile it again after changes.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct CompiledStacker {
+    /// Private so that its length can't change, as the compiled program indexes into it.
+    operators: Vec<Operator>,
+    /// The instructions the program was compiled from.
+    source: Vec<StackInstruction>,
+    instructions: Vec<CompiledInstruction>,
+    stack: Vec<f64>,
+    /// The output of each operator this tick.
+    outputs: Vec<f64>,
+}
+impl CompiledStacker {
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+    /// The operators can be modified in place, but not added or removed.
+    pub fn operators_mut(&mut self) -> &mut [Operator] {
+        &mut self.operators
+    }
+    /// Converts the program back into an editable [`Stacker`].
+    pub fn decompile(&self) -> Stacker {
+        Stacker {
+            operators: self.operators.clone(),
+            instructions: self.source.clone(),
+        }
+    }
+}
+impl Pom<SampleBank> for CompiledStacker {
+    fn sample(
+        &mut self,
+        data: &SampleBank,
+        global_time: Duration,
+        phase_offset: f64,
+    ) -> Option<f64> {
+        let stack = &mut self.stack;
+        let mut depth = 0;
+        for instruction in &self.instructions {
+            match *instruction {
+                CompiledInstruction::Constant(constant) => {
+                    stack[depth] = constant;
+                    depth += 1;
+                }
+                CompiledInstruction::InputPhaseOffset => {
+                    stack[depth] = phase_offset;
+                    depth += 1;
+                }
+                CompiledInstruction::Sample(operator) => {
+                    let op = &mut self.operators[operator];
+                    let output = op
+                        .sample(data, global_time, stack[depth - 1])
+                        .unwrap_or(0.0);
+                    let output = if op.muted { stack[depth - 1] } else { output };
+                    self.outputs[operator] = output;
+                    stack[depth - 1] = output;
+                }
+                CompiledInstruction::Reuse(operator) => stack[depth - 1] = self.outputs[operator],
+                CompiledInstruction::Add => {
+                    depth -= 1;
+                    stack[depth - 1] += stack[depth];
+                }
+                CompiledInstruction::Sub => {
+                    depth -= 1;
+                    stack[depth - 1] -= stack[depth];
+                }
+                CompiledInstruction::Mul => {
+                    depth -= 1;
+                    stack[depth - 1] *= stack[depth];
+                }
+                CompiledInstruction::Neg => stack[depth - 1] = -stack[depth - 1],
+                CompiledInstruction::Dupe => {
+                    stack[depth] = stack[depth - 1];
+                    depth += 1;
+                }
+                CompiledInstruction::Swap => stack.swap(depth - 1, depth - 2),
+                CompiledInstruction::Rot => stack[depth - 3..depth].rotate_left(1),
+                CompiledInstruction::SelectIf(threshold) => {
+                    depth -= 2;
+                    stack[depth - 1] = if stack[depth + 1] > threshold {
+                        stack[depth - 1]
+                    } else {
+                        stack[depth]
+                    };
+                }
+            }
+        }
+        Some(stack[depth - 1])
+    }
+
+    fn play(&mut self, frequency: f64, volume: f64) {
+        self.operators
+            .iter_mut()
+            .for_each(|op| op.play(frequency, volume));
+    }
+    fn set_start(&mut self, when: Duration) {
+        self.operators.iter_mut().for_each(|op| op.set_start(when));
+    }
+    fn set_frequency(&mut self, frequency: f64) {
+        self.operators
+            .iter_mut()
+            .for_each(|op| op.set_frequency(frequency));
+    }
+    fn cut(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.cut());
+    }
+    fn release(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.release());
+    }
+    fn reset_phase(&mut self) {
+        self.operators.iter_mut().for_each(|op| op.reset_phase());
+    }
+    fn is_active(&self) -> bool {
+        self.operators.iter().any(|op| op.is_active())
+    }
+    fn remaining(&self) -> Option<Duration> {
+        self.operators
+            .iter()
+            .try_fold(Duration::ZERO, |max, op| Some(max.max(op.remaining()?)))
+    }
+    fn current_volume(&self, global_time: Duration) -> Option<f64> {
+        self.operators
+            .iter()
+            .filter_map(|op| op.current_volume(global_time))
+            .reduce(f64::max)
+    }
+    fn box_clone(&self) -> Box<dyn Pom<SampleBank>> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn to_serialized(&self) -> Option<SerializedSynth> {
+        Some(SerializedSynth::Stacker(self.decompile()))
+    }
+}
+
+/// A synthesiser in a form that can be encoded with Decent,
+/// recording which concrete type it is so that it can be reconstructed.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "std", derive(Binary))]
+pub enum SerializedSynth {
+    Operator(Box<Operator>),
+    Stacker(Stacker),
+    Combinator {
+        ty: CombinatorType,
+        weights: Vec<f64>,
+        normalize: bool,
+        synths: Vec<SerializedSynth>,
+    },
+}
+impl SerializedSynth {
+    /// Reconstructs the synthesiser.
+    pub fn into_pom(self) -> Box<dyn Pom<SampleBank>> {
+        match self {
+            SerializedSynth::Operator(operator) => operator,
+            SerializedSynth::Stacker(stacker) => Box::new(stacker),
+            SerializedSynth::Combinator {
+                ty,
+                weights,
+                normalize,
+                synths,
+            } => Box::new(Combinator {
+                synths: synths.into_iter().map(SerializedSynth::into_pom).collect(),
+                ty,
+                weights,
+                normalize,
+            }),
+        }
+    }
+    /// Encodes the synthesiser into bytes.
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.encode(&mut bytes)?;
+        Ok(bytes)
+    }
+    /// Decodes a synthesiser from bytes produced by [`SerializedSynth::to_bytes`].
+    #[cfg(feature = "std")]
+    pub fn from_bytes(mut bytes: &[u8]) -> std::io::Result<Self> {
+        Self::decode(&mut bytes)
+    }
+}
+
+/// An owned synthesiser together with the sample bank it plays from,
+/// offering the same lifecycle and buffer filling as the C API without any unsafe code.
+pub struct Synth {
+    pub synth: Box<dyn Pom<SampleBank>>,
+    pub bank: Arc<SampleBank>,
+}
+impl Synth {
+    pub fn new(synth: impl Pom<SampleBank> + 'static, bank: Arc<SampleBank>) -> Self {
+        Self::from_boxed(Box::new(synth), bank)
+    }
+    pub fn from_boxed(synth: Box<dyn Pom<SampleBank>>, bank: Arc<SampleBank>) -> Self {
+        Self { synth, bank }
+    }
+    /// Creates a synthesiser with an empty sample bank, for synthesisers that don't use PCM samples.
+    pub fn without_samples(synth: impl Pom<SampleBank> + 'static) -> Self {
+        Self::new(synth, Arc::default())
+    }
+    pub fn play(&mut self, frequency: f64, volume: f64) {
+        self.synth.play(frequency, volume);
+    }
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.synth.set_frequency(frequency);
+    }
+    pub fn release(&mut self) {
+        self.synth.release();
+    }
+    pub fn cut(&mut self) {
+        self.synth.cut();
+    }
+    pub fn is_active(&self) -> bool {
+        self.synth.is_active()
+    }
+    /// Samples the synthesiser once, stepping it to `global_time`. Silence is 0.
+    pub fn sample(&mut self, global_time: Duration, phase_offset: f64) -> f64 {
+        self.synth
+            .sample(&self.bank, global_time, phase_offset)
+            .unwrap_or_default()
+    }
+    /// Fills `buffer` with samples `sample_interval` apart, starting at `global_time`.
+    ///
+    /// Like `pom_fill`, sample `i` is taken at exactly [`time::advance`]`(global_time, sample_interval, i)`,
+    /// so filling consecutive blocks produces the same output as filling one large buffer.
+    pub fn fill(
+        &mut self,
+        buffer: &mut [f64],
+        global_time: Duration,
+        sample_interval: Duration,
+        phase_offset: f64,
+    ) {
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = self.sample(
+                time::advance(global_time, sample_interval, i as u64),
+                phase_offset,
+            );
+        }
+    }
+    /// Like [`Synth::fill`], but for 32-bit float buffers.
+    pub fn fill_f32(
+        &mut self,
+        buffer: &mut [f32],
+        global_time: Duration,
+        sample_interval: Duration,
+        phase_offset: f64,
+    ) {
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = self.sample(
+                time::advance(global_time, sample_interval, i as u64),
+                phase_offset,
+            ) as f32;
+        }
+    }
+    /// Renders a single note offline. See [`Pom::render`].
+    pub fn render(
+        &mut self,
+        frequency: f64,
+        volume: f64,
+        sample_rate: u32,
+        note_length: Duration,
+        tail: Duration,
+    ) -> Vec<f32> {
+        self.synth.render(
+            &self.bank,
+            frequency,
+            volume,
+            sample_rate,
+            note_length,
+            tail,
+        )
+    }
+}
+impl Clone for Synth {
+    /// Clones the synthesiser, sharing the sample bank.
+    fn clone(&self) -> Self {
+        Self::from_boxed(self.synth.box_clone(), self.bank.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::println;
+
+    /// The time of sample `index` at `sample_rate`, computed exactly.
+    fn time_at(index: usize, sample_rate: u32) -> Duration {
+        Duration::from_nanos(index as u64 * time::NANOS_PER_SEC as u64 / sample_rate as u64)
+    }
+    /// Samples `synth` `length` times at `sample_rate`, starting from a global time of zero.
+    fn render(synth: &mut dyn Pom<SampleBank>, sample_rate: u32, length: usize) -> Vec<f64> {
+        let bank = SampleBank::default();
+        (0..length)
+            .map(|i| {
+                synth
+                    .sample(&bank, time_at(i, sample_rate), 0.0)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+    /// The amplitude of the component of `samples` at `frequency`, from a single bin of a DFT.
+    fn amplitude_at(samples: &[f64], frequency: f64, sample_rate: u32) -> f64 {
+        let (re, im) = samples
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, x)| {
+                let angle = TAU * frequency * i as f64 / sample_rate as f64;
+                (re + x * angle.cos(), im - x * angle.sin())
+            });
+        2.0 * re.hypot(im) / samples.len() as f64
+    }
+    fn sine(frequency: f64) -> Operator {
+        let mut operator = Operator::builder().waveform(Waveform::Sine).build();
+        operator.play(frequency, 1.0);
+        operator
+    }
+
+    #[test]
+    fn envelope_plateaus_at_sustain_level() {
+        let envelope = Envelope::builder()
+            .attack(Duration::from_millis(10))
+            .halving_rate(20.0)
+            .sustain(0.4)
+            .release(Duration::from_millis(100))
+            .build();
+        // the decay has halved 40 times after 2 seconds, so the level should have settled
+        for millis in [2000, 5000, 60000] {
+            let volume = envelope
+                .sample_volume(Duration::from_millis(millis), None)
+                .unwrap();
+            assert!((volume - 0.4).abs() < 1e-9, "{volume} at {millis}ms");
+        }
+        let stop_point = Some(Duration::from_secs(60));
+        let releasing = envelope
+            .sample_volume(Duration::from_millis(60050), stop_point)
+            .unwrap();
+        assert!((releasing - 0.2).abs() < 1e-9);
+        assert_eq!(
+            envelope.sample_volume(Duration::from_millis(60101), stop_point),
+            None
+        );
+    }
+
+    #[test]
+    fn multiply_produces_sum_and_difference_frequencies() {
+        let mut ring = Combinator::new(
+            vec![Box::new(sine(1000.0)), Box::new(sine(300.0))],
+            CombinatorType::Multiply,
+        );
+        // 0.1s, so every frequency involved lands exactly on a bin
+        let output = render(&mut ring, 48000, 4800);
+        for frequency in [700.0, 1300.0] {
+            assert!((amplitude_at(&output, frequency, 48000) - 0.5).abs() < 1e-3);
+        }
+        for frequency in [300.0, 1000.0] {
+            assert!(amplitude_at(&output, frequency, 48000) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn band_limiting_removes_aliasing() {
+        // the aliases of a 5kHz saw at 48kHz land on the other multiples of 1kHz
+        let aliasing = |waveform: Waveform| {
+            let mut operator = Operator::builder().waveform(waveform).build();
+            operator.play(5000.0, 1.0);
+            let output = render(&mut operator, 48000, 4800);
+            (1..24)
+                .filter(|khz| khz % 5 != 0)
+                .map(|khz| amplitude_at(&output, khz as f64 * 1000.0, 48000).powi(2))
+                .sum::<f64>()
+        };
+        let naive = aliasing(Waveform::Sawtooth);
+        let band_limited = aliasing(Waveform::band_limited(Waveform::Sawtooth, 5000.0, 48000.0));
+        assert!(naive > 1e-3, "{naive}");
+        assert!(band_limited < naive * 1e-6, "{band_limited} vs {naive}");
+    }
+
+    #[test]
+    fn voice_manager_steals_the_oldest_voice() {
+        let template = Operator::builder()
+            .waveform(Waveform::Constant(1.0))
+            .build();
+        let mut voices = VoiceManager::new(Box::new(template), 2);
+        let bank = SampleBank::default();
+        let first = voices.note_on(440.0, 1.0);
+        voices.sample(&bank, Duration::from_millis(1), 0.0);
+        let second = voices.note_on(550.0, 1.0);
+        assert_eq!(voices.sample(&bank, Duration::from_millis(2), 0.0), 2.0);
+
+        let third = voices.note_on(660.0, 1.0);
+        let held: Vec<_> = voices.voices.iter().map(|voice| voice.id).collect();
+        assert!(!held.contains(&first));
+        assert!(held.contains(&second) && held.contains(&third));
+        assert_eq!(voices.active_voices(), 2);
+        assert_eq!(voices.sample(&bank, Duration::from_millis(3), 0.0), 2.0);
+    }
+
+    #[test]
+    fn feedback_turns_a_sine_towards_a_sawtooth() {
+        // the relative amplitude of each harmonic, which is 1/n for a sawtooth
+        let harmonics = |feedback| {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Sine)
+                .feedback(feedback)
+                .build();
+            operator.play(100.0, 1.0);
+            let output = render(&mut operator, 48000, 4800);
+            let fundamental = amplitude_at(&output, 100.0, 48000);
+            (2..=5)
+                .map(|n| {
+                    (
+                        n,
+                        amplitude_at(&output, 100.0 * n as f64, 48000) / fundamental,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        for (_, relative) in harmonics(0.0) {
+            assert!(relative < 1e-9);
+        }
+        for (n, relative) in harmonics(0.2) {
+            let sawtooth = 1.0 / n as f64;
+            assert!(
+                relative > sawtooth / 2.0 && relative < sawtooth * 2.0,
+                "harmonic {n} is {relative}"
+            );
+        }
+    }
+
+    #[test]
+    fn vibrato_wobbles_the_frequency_periodically() {
+        let mut vibrato = Modulated::new(
+            Box::new(Operator::builder().waveform(Waveform::Sine).build()),
+            ModulationTarget::Frequency,
+            Waveform::Sine,
+            5.0,
+            1.0,
+        );
+        vibrato.play(1000.0, 1.0);
+        let bank = SampleBank::default();
+        // the instantaneous frequency, from how far the oscillator moves each sample
+        let mut previous = Period::ZERO;
+        let frequencies: Vec<f64> = (0..48000)
+            .map(|i| {
+                vibrato.sample(&bank, time_at(i, 48000), 0.0);
+                let period = vibrato.oscillator_period().unwrap();
+                let frequency = (period - previous).as_secs_f64() * 48000.0;
+                previous = period;
+                frequency
+            })
+            .collect();
+        let frequencies = &frequencies[1..];
+        let (low, high) = frequencies
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(low, high), &f| {
+                (low.min(f), high.max(f))
+            });
+        assert!(
+            (high - 1000.0 * 2f64.powf(1.0 / 12.0)).abs() < 0.1,
+            "{high}"
+        );
+        assert!((low - 1000.0 * 2f64.powf(-1.0 / 12.0)).abs() < 0.1, "{low}");
+        // one LFO cycle later, the frequency is the same again
+        for i in (0..38400).step_by(997) {
+            assert!((frequencies[i] - frequencies[i + 9600]).abs() < 0.01);
+        }
+    }
+
+    /// The largest difference between consecutive samples.
+    fn largest_step(samples: &[f64]) -> f64 {
+        samples
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f64::max)
+    }
+    fn retriggerable_sine() -> Operator {
+        Operator::builder()
+            .waveform(Waveform::Sine)
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::from_millis(10))
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn retriggering_does_not_jump() {
+        let bank = SampleBank::default();
+        let mut operator = retriggerable_sine();
+        operator.play(440.0, 1.0);
+        // retrigger at a peak of the waveform, where restarting its phase would jump furthest
+        let mut output: Vec<f64> = (0..1227)
+            .map(|i| operator.sample(&bank, time_at(i, 48000), 0.0).unwrap())
+            .collect();
+        operator.play(440.0, 1.0);
+        output
+            .extend((1227..2400).map(|i| operator.sample(&bank, time_at(i, 48000), 0.0).unwrap()));
+        // a 440Hz sine moves by at most about 0.058 per sample at 48kHz
+        let step = largest_step(&output);
+        assert!(step < 0.06, "{step}");
+    }
+
+    #[test]
+    fn scheduled_retriggering_does_not_jump() {
+        let bank = SampleBank::default();
+        let mut scheduled = Scheduled::new(Box::new(retriggerable_sine()));
+        scheduled.schedule(
+            Duration::ZERO,
+            Event::Play {
+                frequency: 440.0,
+                volume: 1.0,
+            },
+        );
+        // between two samples
+        let retrigger = time_at(1227, 48000) + Duration::from_nanos(10000);
+        scheduled.schedule(
+            retrigger,
+            Event::Play {
+                frequency: 440.0,
+                volume: 1.0,
+            },
+        );
+        let output: Vec<f64> = (0..2400)
+            .map(|i| scheduled.sample(&bank, time_at(i, 48000), 0.0).unwrap())
+            .collect();
+        let step = largest_step(&output);
+        assert!(step < 0.06, "{step}");
+    }
+
+    #[test]
+    fn min_and_max_clamp_to_the_other_synth() {
+        let inverted_sine = || {
+            let mut operator = sine(100.0);
+            operator.modifiers.constant_phase_offset = 0.5;
+            Box::new(operator) as Box<dyn Pom<SampleBank>>
+        };
+        let sines = |ty| Combinator::new(vec![Box::new(sine(100.0)), inverted_sine()], ty);
+        let reference = render(&mut sine(100.0), 48000, 960);
+        let max = render(&mut sines(CombinatorType::Max), 48000, 960);
+        let min = render(&mut sines(CombinatorType::Min), 48000, 960);
+        for ((reference, max), min) in reference.iter().zip(&max).zip(&min) {
+            assert!((max - reference.abs()).abs() < 1e-9);
+            assert!((min + reference.abs()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn panning_is_equal_power_and_3db_down_in_the_centre() {
+        let constant = || {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Constant(1.0))
+                .build();
+            operator.play(440.0, 1.0);
+            Box::new(operator)
+        };
+        let bank = SampleBank::default();
+        for pan in [-1.0, -0.5, 0.0, 0.3, 1.0] {
+            let mut panned = Panned::new(constant(), pan);
+            let (left, right) = panned.sample_stereo(&bank, Duration::ZERO, 0.0).unwrap();
+            assert!((left * left + right * right - 1.0).abs() < 1e-12);
+        }
+        let (left, right) = Panned::new(constant(), 0.0)
+            .sample_stereo(&bank, Duration::ZERO, 0.0)
+            .unwrap();
+        assert!((left - right).abs() < 1e-12);
+        assert!((20.0 * left.log10() + 3.0103).abs() < 1e-3);
+        let hard_left = Panned::new(constant(), -1.0)
+            .sample_stereo(&bank, Duration::ZERO, 0.0)
+            .unwrap();
+        assert!((hard_left.0 - 1.0).abs() < 1e-12 && hard_left.1.abs() < 1e-12);
+    }
+
+    #[test]
+    fn hard_sync_resets_the_slave_with_each_master_cycle() {
+        let mut sync = HardSync::new(
+            Box::new(Operator::builder().waveform(Waveform::Sine).build()),
+            Box::new(Operator::builder().waveform(Waveform::Sawtooth).build()),
+        );
+        sync.play(100.0, 1.0);
+        let bank = SampleBank::default();
+        let mut resets = 0;
+        for i in 0..24000 {
+            // sweep the slave from 150Hz to 450Hz
+            let slave_frequency = 150.0 + 300.0 * i as f64 / 24000.0;
+            sync.slave.set_frequency(slave_frequency);
+            let master_before = sync.master.oscillator_period().unwrap();
+            let slave_before = sync.slave.oscillator_period().unwrap();
+            sync.sample(&bank, time_at(i, 48000), 0.0);
+            let master_wrapped =
+                sync.master.oscillator_period().unwrap().as_secs() > master_before.as_secs();
+            let slave = sync.slave.oscillator_period().unwrap();
+            assert_eq!(slave < slave_before, master_wrapped, "sample {i}");
+            if master_wrapped {
+                resets += 1;
+                // the slave restarted, then ran for one sample
+                assert!((slave.as_secs_f64() - slave_frequency / 48000.0).abs() < 1e-6);
+            }
+        }
+        // 100Hz for half a second
+        assert_eq!(resets, 49);
+    }
+
+    #[test]
+    fn ramp_wavetable_reproduces_a_sawtooth() {
+        let ramp: Vec<f64> = (0..1000).map(|i| i as f64 / 500.0 - 1.0).collect();
+        let mut bank = SampleBank::new();
+        bank.samples
+            .insert(0, Sample::new(ramp, 48000.0, 1000.0, 0.0, 0.0));
+        let mut wavetable = Operator::builder().waveform(Waveform::Wavetable(0)).build();
+        let mut sawtooth = Operator::builder().waveform(Waveform::Sawtooth).build();
+        wavetable.play(110.0, 1.0);
+        sawtooth.play(110.0, 1.0);
+        for i in 0..4800 {
+            let time = time_at(i, 48000);
+            let expected = sawtooth.sample(&bank, time, 0.0).unwrap();
+            let actual = wavetable.sample(&bank, time, 0.0).unwrap();
+            // the last entry of the table interpolates back towards the first, where the saw jumps instead
+            if expected < 0.998 {
+                assert!(
+                    (actual - expected).abs() < 1e-9,
+                    "{actual} != {expected} at {i}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn phase_distortion_brightens_with_amount() {
+        let bank = SampleBank::default();
+        // one period over 1000 points, so harmonic `n` is a frequency of `n` at a sample rate of 1000
+        let overtones = |amount: f64| {
+            let waveform = Waveform::PhaseDistort {
+                base: Box::new(Waveform::Sine),
+                amount,
+                pivot: 0.1,
+            };
+            let period: Vec<f64> = (0..1000)
+                .map(|i| waveform.sample(&bank, Period::from_secs_f64(i as f64 / 1000.0), 0.0))
+                .collect();
+            (2..=20)
+                .map(|harmonic| amplitude_at(&period, harmonic as f64, 1000))
+                .sum::<f64>()
+        };
+        assert!(overtones(0.0) < 1e-9);
+        let mut previous = 0.0;
+        for amount in [0.25, 0.5, 0.75, 1.0] {
+            let brightness = overtones(amount);
+            assert!(brightness > previous, "{brightness} at {amount}");
+            previous = brightness;
+        }
+    }
+
+    #[test]
+    fn unison_spreads_voices_symmetrically() {
+        let template = Operator::builder()
+            .waveform(Waveform::Sawtooth)
+            .detune_cents(5.0)
+            .build();
+        let unison = Combinator::unison(template.clone(), 5, 20.0, 0.0);
+        assert_eq!(unison.synths.len(), 5);
+        let detunes: Vec<f64> = unison
+            .synths
+            .iter()
+            .map(|synth| {
+                let operator = synth.as_any().downcast_ref::<Operator>().unwrap();
+                operator.modifiers.detune_cents - 5.0
+            })
+            .collect();
+        assert_eq!(detunes, [-20.0, -10.0, 0.0, 10.0, 20.0]);
+
+        let spread = Combinator::unison(template, 4, 20.0, 0.5);
+        assert_eq!(spread.synths.len(), 4);
+        let pans: Vec<f64> = spread
+            .synths
+            .iter()
+            .map(|synth| {
+                synth
+                    .as_any()
+                    .downcast_ref::<Panned<SampleBank>>()
+                    .unwrap()
+                    .pan
+            })
+            .collect();
+        for (left, right) in pans.iter().zip(pans.iter().rev()) {
+            assert!((left + right).abs() < 1e-12);
+        }
+        assert!((pans[0] + 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn key_tracking_follows_settings_changed_mid_note() {
+        let mut operator = Operator::builder()
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::from_millis(100))
+                    .build(),
+            )
+            .build();
+        operator.modifiers.key_track = 1.0;
+        operator.play(880.0, 1.0);
+        operator.sample(&SampleBank::default(), Duration::ZERO, 0.0);
+        // an octave above the reference halves the attack
+        let level =
+            |operator: &Operator| operator.current_volume(Duration::from_millis(25)).unwrap();
+        assert!((level(&operator) - 0.5).abs() < 1e-9);
+        operator.modifiers.key_track_reference = 220.0;
+        assert!((level(&operator) - 1.0).abs() < 1e-9);
+        operator.envelope = Envelope::builder()
+            .attack(Duration::from_millis(200))
+            .build()
+            .into();
+        assert!((level(&operator) - 0.5).abs() < 1e-9);
+        operator.modifiers.key_track = 0.0;
+        assert!((level(&operator) - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn low_pass_filter_attenuates_high_harmonics() {
+        let spectrum = |filter: Option<Filter>| {
+            let mut saw = Operator::builder().waveform(Waveform::Sawtooth).build();
+            saw.filter = filter;
+            saw.play(100.0, 1.0);
+            // skip the filter settling in
+            let output = render(&mut saw, 48000, 9600).split_off(4800);
+            [100.0, 2000.0, 5000.0].map(|frequency| amplitude_at(&output, frequency, 48000))
+        };
+        let unfiltered = spectrum(None);
+        let filtered = spectrum(Some(Filter {
+            cutoff_hz: 500.0,
+            ..Filter::default()
+        }));
+        // a flat response at the fundamental, falling 12dB per octave above the cutoff
+        assert!((filtered[0] / unfiltered[0] - 1.0).abs() < 0.05);
+        assert!(filtered[1] < unfiltered[1] / 10.0);
+        assert!(filtered[2] < unfiltered[2] / 50.0);
+    }
+
+    #[test]
+    fn zero_attack_and_release_are_instant() {
+        let envelope = Envelope::builder()
+            .attack(Duration::ZERO)
+            .sustain(1.0)
+            .release(Duration::ZERO)
+            .build();
+        let stop_point = Some(Duration::from_millis(10));
+        for (millis, expected) in [(0, Some(1.0)), (5, Some(1.0)), (10, None), (11, None)] {
+            let volume = envelope.sample_volume(Duration::from_millis(millis), stop_point);
+            assert!(volume.is_none_or(f64::is_finite));
+            assert_eq!(volume, expected, "at {millis}ms");
+        }
+    }
+
+    #[test]
+    fn decay_ends_instead_of_producing_denormals() {
+        let mut operator = Operator::builder()
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::ZERO)
+                    .halving_rate(1000.0)
+                    .sustain(0.0)
+                    .build(),
+            )
+            .build();
+        operator.play(440.0, 1.0);
+        let bank = SampleBank::default();
+        // the decay falls below the inaudible level after about 30 halvings, or 30ms
+        let end = (0..4800)
+            .position(|i| match operator.sample(&bank, time_at(i, 48000), 0.0) {
+                Some(value) => {
+                    assert!(!value.is_subnormal(), "{value} at {i}");
+                    false
+                }
+                None => true,
+            })
+            .expect("the note never ended");
+        assert!(end < 1500, "ended at {end}");
+        assert!(!operator.is_active());
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored`"]
+    fn compiled_stacker_is_faster_than_interpreted() {
+        let stacker = Stacker {
+            operators: vec![sine(440.0), sine(660.0), sine(880.0)],
+            instructions: vec![
+                StackInstruction::InputPhaseOffset,
+                StackInstruction::Sample(0),
+                StackInstruction::Dupe,
+                StackInstruction::Sample(1),
+                StackInstruction::Add,
+                StackInstruction::Constant(0.5),
+                StackInstruction::Mul,
+                StackInstruction::Sample(2),
+                StackInstruction::Dupe,
+                StackInstruction::Mul,
+            ],
+        };
+        let mut compiled_stacker = stacker.compile().unwrap();
+        let mut interpreted_stacker = stacker;
+        let time = |synth: &mut dyn Pom<SampleBank>| {
+            let start = std::time::Instant::now();
+            std::hint::black_box(render(synth, 48000, 48000));
+            start.elapsed()
+        };
+        // the fastest of several interleaved runs, to reduce noise from the rest of the system
+        let (mut interpreted, mut compiled) = (Duration::MAX, Duration::MAX);
+        for _ in 0..10 {
+            interpreted = interpreted.min(time(&mut interpreted_stacker));
+            compiled = compiled.min(time(&mut compiled_stacker));
+        }
+        println!("1 second at 48kHz: {interpreted:?} interpreted, {compiled:?} compiled");
+        assert!(compiled < interpreted);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored`"]
+    fn dense_sample_lookup_is_faster_than_hashing() {
+        let data: Vec<f64> = (0..4800).map(|i| (i as f64 / 4800.0 * TAU).sin()).collect();
+        let sample = Sample::new(data, 48000.0, 4800.0, 0.0, 0.1);
+        // the same instrument's worth of samples, hashed on every lookup as before and indexed directly
+        let (mut mapped, mut dense) = (SampleBank::new(), SampleBank::new());
+        for id in 0..128 {
+            mapped.samples.insert(id, sample.clone());
+            dense.insert(id, sample.clone());
+        }
+        // the PCM fill path of an operator, without its envelope and modifiers
+        let time = |bank: &SampleBank| {
+            let start = std::time::Instant::now();
+            for i in 0..48000 {
+                let period = Period::from_secs_f64(i as f64 * 440.0 / 48000.0);
+                std::hint::black_box(Waveform::PCM(64).sample(bank, period, 0.0));
+            }
+            start.elapsed()
+        };
+        let (mut hashed, mut indexed) = (Duration::MAX, Duration::MAX);
+        for _ in 0..10 {
+            hashed = hashed.min(time(&mapped));
+            indexed = indexed.min(time(&dense));
+        }
+        println!("1 second of PCM at 48kHz: {hashed:?} hashed, {indexed:?} indexed");
+        assert!(indexed < hashed);
+    }
+
+    #[test]
+    fn sample_bank_keeps_small_ids_dense() {
+        let sample = |level: f64| Sample::new(vec![level], 48000.0, 1.0, 0.0, 0.0);
+        let mut bank = SampleBank::new();
+        assert_eq!(bank.insert(3, sample(1.0)), None);
+        assert_eq!(bank.insert(DENSE_SAMPLE_IDS, sample(2.0)), None);
+        assert!(bank.dense[3].is_some());
+        assert!(bank.samples.contains_key(&DENSE_SAMPLE_IDS));
+        assert_eq!(bank.get(3), Some(&sample(1.0)));
+        assert_eq!(bank.get(DENSE_SAMPLE_IDS), Some(&sample(2.0)));
+
+        // a sample added to the map directly is replaced, not shadowed, by inserting the same ID
+        bank.samples.insert(5, sample(3.0));
+        assert_eq!(bank.get(5), Some(&sample(3.0)));
+        assert_eq!(bank.insert(5, sample(4.0)), Some(sample(3.0)));
+        assert!(!bank.samples.contains_key(&5));
+        assert_eq!(bank.get(5), Some(&sample(4.0)));
+
+        let mut ids: Vec<_> = bank.ids().collect();
+        ids.sort();
+        assert_eq!(ids, [3, 5, DENSE_SAMPLE_IDS]);
+        assert_eq!(bank.remove(3), Some(sample(1.0)));
+        assert_eq!(bank.remove(3), None);
+        assert_eq!(bank.get(3), None);
+
+        // Waveform::PCM finds samples wherever they're stored
+        let pcm = |id| Waveform::PCM(id).sample(&bank, Period::ZERO, 0.0);
+        assert_eq!(pcm(5), 4.0);
+        assert_eq!(pcm(DENSE_SAMPLE_IDS), 2.0);
+        assert_eq!(pcm(3), 0.0);
+    }
+
+    #[test]
+    fn midi_notes_convert_to_frequencies() {
+        assert_eq!(pitch::midi_to_frequency(69), 440.0);
+        assert!((pitch::midi_to_frequency(0) - 8.1758).abs() < 1e-4);
+        assert_eq!(pitch::midi_to_frequency_tuned(69, 432.0), 432.0);
+        for note in [0, 60, 69, 127] {
+            let frequency = pitch::midi_to_frequency(note);
+            assert!((pitch::frequency_to_midi(frequency) - note as f64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn scheduled_event_applies_when_its_time_is_reached() {
+        let constant = Operator::builder()
+            .waveform(Waveform::Constant(1.0))
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::ZERO)
+                    .sustain(1.0)
+                    .build(),
+            )
+            .build();
+        let mut scheduled = Scheduled::new(Box::new(constant));
+        let bank = SampleBank::default();
+        scheduled.schedule(
+            Duration::from_secs(1),
+            Event::Play {
+                frequency: 440.0,
+                volume: 1.0,
+            },
+        );
+        for i in 47900..48000 {
+            let output = scheduled.sample(&bank, time_at(i, 48000), 0.0);
+            assert_eq!(output.unwrap_or_default(), 0.0, "at {i}");
+            assert_eq!(scheduled.events.len(), 1);
+        }
+        scheduled.sample(&bank, time_at(48000, 48000), 0.0);
+        assert!(scheduled.events.is_empty());
+        assert!(scheduled.is_active());
+        assert_eq!(
+            scheduled.sample(&bank, time_at(48001, 48000), 0.0),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn saturation_compresses_monotonically() {
+        let bank = SampleBank::default();
+        let saturate = |value: f64| {
+            Waveform::Saturate {
+                base: Box::new(Waveform::Constant(value)),
+                drive: 2.0,
+            }
+            .sample(&bank, Period::ZERO, 0.0)
+        };
+        let mut previous = f64::NEG_INFINITY;
+        for i in -400..=400 {
+            let input = i as f64 / 100.0;
+            let output = saturate(input);
+            assert!(output > previous, "{output} at {input}");
+            assert!(output.abs() <= input.abs().max(1.0));
+            if input.abs() > 1.0 {
+                assert!(output.abs() < input.abs());
+                assert!((output.abs() - 1.0).abs() < 0.1);
+            }
+            previous = output;
+        }
+        // a tiny drive is almost linear
+        let linear = Waveform::Saturate {
+            base: Box::new(Waveform::Constant(0.5)),
+            drive: 1e-6,
+        };
+        assert!((linear.sample(&bank, Period::ZERO, 0.0) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reversed_ramp_reads_descending() {
+        let ramp: Vec<f64> = (0..100).map(|i| i as f64 / 100.0).collect();
+        let mut sample = Sample::new(ramp, 48000.0, 100.0, 0.0, 0.0);
+        sample.reverse = true;
+        sample.loop_point = sample.duration();
+        // the middle of the `index`th PCM sample, so rounding can't land on its neighbour
+        let period_at = |index: u64| Period::from_micros(index * 10000 + 5000);
+        for i in 0..100 {
+            assert_eq!(sample.get(period_at(i), 0.0), (99 - i) as f64 / 100.0);
+        }
+        // looping the second half of the reversed data, which is the first half of the original
+        sample.loop_point = Period::from_millis(500);
+        sample.loop_duration = Period::from_millis(500);
+        assert!(sample.loop_fits());
+        for i in 100..300 {
+            let looped = 50 + (i - 50) % 50;
+            assert_eq!(sample.get(period_at(i), 0.0), (99 - looped) as f64 / 100.0);
+        }
+    }
+
+    #[test]
+    fn crossfading_smooths_the_loop_seam() {
+        // 13.4 cycles fit in the loop, so its ends are far apart
+        let sine: Vec<f64> = (0..1000).map(|i| (TAU * i as f64 / 37.3).sin()).collect();
+        let seam_step = |crossfade: Period| {
+            let mut sample = Sample::new(sine.clone(), 48000.0, 100.0, 0.0, 0.0);
+            sample.loop_point = Period::from_secs(2);
+            sample.loop_duration = Period::from_secs(5);
+            sample.crossfade = crossfade;
+            // reads a PCM sample at a time, across the end of the loop at the 700th
+            let samples: Vec<f64> = (600..800)
+                .map(|i| sample.get(Period::from_micros(i * 10000 + 5000), 0.0))
+                .collect();
+            largest_step(&samples)
+        };
+        let hard = seam_step(Period::ZERO);
+        let crossfaded = seam_step(Period::from_millis(500));
+        assert!(hard > 1.0, "{hard}");
+        // no larger than the steps of the sine itself, which are up to 2π / 37.3
+        assert!(crossfaded < 0.2, "{crossfaded}");
+    }
+
+    #[test]
+    fn output_gain_halves_the_output_at_every_velocity() {
+        let output = |velocity: f64, output_gain: f64| {
+            let mut operator = Operator::builder().waveform(Waveform::Sine).build();
+            operator.modifiers.output_gain = output_gain;
+            operator.play(440.0, velocity);
+            render(&mut operator, 48000, 480)
+        };
+        for velocity in [0.1, 0.5, 1.0] {
+            let full = output(velocity, 1.0);
+            let halved = output(velocity, 0.5);
+            for (full, halved) in full.iter().zip(&halved) {
+                assert!((full * 0.5 - halved).abs() < 1e-12);
+            }
+            assert!(full.iter().any(|&x| x.abs() > velocity / 2.0));
+        }
+    }
+
+    #[test]
+    fn glide_approaches_the_target_exponentially() {
+        let mut operator = sine(440.0);
+        operator.glide_time = Duration::from_millis(10);
+        let bank = SampleBank::default();
+        operator.sample(&bank, Duration::ZERO, 0.0);
+        operator.set_frequency(880.0);
+        let mut previous = 440.0;
+        for i in 1..=4800 {
+            operator.sample(&bank, time_at(i, 48000), 0.0);
+            assert!(operator.frequency > previous && operator.frequency < 880.0);
+            previous = operator.frequency;
+            // the gap shrinks by a factor of e every glide time
+            if i % 480 == 0 {
+                let expected = 880.0 - 440.0 * (-(i as f64) / 480.0).exp();
+                assert!((operator.frequency - expected).abs() < 1e-6, "at {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn mix_blends_smoothly_from_a_to_b() {
+        let bank = SampleBank::default();
+        let period = Period::from_millis(100);
+        let (a, b) = (Waveform::Sawtooth, Waveform::Sine);
+        let (a_value, b_value) = (a.sample(&bank, period, 0.0), b.sample(&bank, period, 0.0));
+        let mix = |blend: f64| {
+            Waveform::Mix {
+                a: Box::new(a.clone()),
+                b: Box::new(b.clone()),
+                blend,
+            }
+            .sample(&bank, period, 0.0)
+        };
+        assert_eq!(mix(0.0), a_value);
+        assert_eq!(mix(1.0), b_value);
+        // each step of the sweep moves the same distance from a towards b
+        let sweep: Vec<f64> = (0..=100).map(|i| mix(i as f64 / 100.0)).collect();
+        for pair in sweep.windows(2) {
+            assert!((pair[1] - pair[0] - (b_value - a_value) / 100.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn fm_with_a_large_index_has_bessel_sidebands() {
+        // a phase offset of up to 1.5 periods, or an index of 3π radians
+        let mut modulator = Operator::builder().waveform(Waveform::Sine).build();
+        modulator.play(100.0, 1.5);
+        let index = 1.5 * TAU;
+        // the carrier is high enough that no significant sideband folds back past 0Hz
+        let mut fm = Combinator::new(
+            vec![Box::new(modulator), Box::new(sine(3000.0))],
+            CombinatorType::Modulate,
+        );
+        let output = render(&mut fm, 48000, 4800);
+        // the Bessel function of the first kind, from its integral form
+        let bessel = |n: i32| {
+            let steps = 10000;
+            (0..steps)
+                .map(|i| {
+                    let tau = (i as f64 + 0.5) * PI / steps as f64;
+                    (n as f64 * tau - index * tau.sin()).cos()
+                })
+                .sum::<f64>()
+                / steps as f64
+        };
+        for n in -8..=8 {
+            let sideband = amplitude_at(&output, 3000.0 + 100.0 * n as f64, 48000);
+            assert!(
+                (sideband - bessel(n).abs()).abs() < 0.01,
+                "{sideband} at {n}"
+            );
+        }
+    }
+
+    #[test]
+    fn pulse_is_high_for_the_duty_cycle() {
+        let bank = SampleBank::default();
+        for duty_cycle in [0.25, 0.75] {
+            let pulse = Waveform::Pulse { duty_cycle };
+            let values: Vec<f64> = (0..1000)
+                .map(|i| pulse.sample(&bank, Period::from_micros(i * 1000 + 500), 0.0))
+                .collect();
+            let high = values.iter().take_while(|&&value| value == 1.0).count();
+            assert_eq!(high, (duty_cycle * 1000.0) as usize);
+            assert!(values[high..].iter().all(|&value| value == -1.0));
+        }
+    }
+
+    #[test]
+    fn antialiased_triangle_reduces_aliasing() {
+        let render_at = |waveform: Waveform, frequency: f64| {
+            let mut operator = Operator::builder().waveform(waveform).build();
+            operator.play(frequency, 1.0);
+            render(&mut operator, 48000, 4800)
+        };
+        // at 3kHz, the aliases of the odd harmonics fold back onto the odd harmonics below them,
+        // so aliasing shows up as error in their amplitudes
+        let error = |waveform: Waveform| {
+            let output = render_at(waveform, 3000.0);
+            [1, 3, 5, 7].map(|n| {
+                let ideal = 8.0 / (PI * PI * (n * n) as f64);
+                (amplitude_at(&output, n as f64 * 3000.0, 48000) - ideal).abs() / ideal
+            })
+        };
+        let (naive, antialiased) = (
+            error(Waveform::Triangle),
+            error(Waveform::AntialiasedTriangle),
+        );
+        assert!(antialiased[0] < 0.02);
+        for n in 1..4 {
+            assert!(antialiased[n] < naive[n], "{antialiased:?} vs {naive:?}");
+        }
+        assert!(naive[3] > 0.9 && antialiased[3] < 0.35);
+
+        // at 3.1kHz, the aliases land between the harmonics, on the other multiples of 100Hz
+        let aliasing = |waveform: Waveform| {
+            let output = render_at(waveform, 3100.0);
+            (1..240)
+                .filter(|hundreds| hundreds % 31 != 0)
+                .map(|hundreds| amplitude_at(&output, hundreds as f64 * 100.0, 48000).powi(2))
+                .sum::<f64>()
+        };
+        let (naive, antialiased) = (
+            aliasing(Waveform::Triangle),
+            aliasing(Waveform::AntialiasedTriangle),
+        );
+        assert!(antialiased < naive / 10.0, "{antialiased} vs {naive}");
+    }
+
+    #[test]
+    fn wrap_duration_wraps_into_range() {
+        let max = Duration::from_millis(1500);
+        let wrap = |duration| time::wrap_duration(duration, max);
+        assert_eq!(wrap(Duration::from_millis(700)), Duration::from_millis(700));
+        assert_eq!(wrap(max), Duration::ZERO);
+        assert_eq!(wrap(Duration::from_millis(1501)), Duration::from_millis(1));
+        assert_eq!(
+            wrap(Duration::from_secs(1_500_000_000) + Duration::from_nanos(7)),
+            Duration::from_nanos(7)
+        );
+        assert_eq!(wrap(Duration::MAX), Duration::from_nanos(999_999_999));
+        assert_eq!(time::wrap_duration(max, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn duration_saturating_mul_f64_saturates() {
+        let second = Duration::from_secs(1);
+        let mul = time::duration_saturating_mul_f64;
+        assert_eq!(mul(second, 2.5), Duration::from_millis(2500));
+        assert_eq!(mul(second, 0.0), Duration::ZERO);
+        assert_eq!(mul(second, -0.0), Duration::ZERO);
+        assert_eq!(mul(second, -1.0), Duration::ZERO);
+        assert_eq!(mul(second, f64::NEG_INFINITY), Duration::ZERO);
+        assert_eq!(mul(second, f64::NAN), Duration::ZERO);
+        assert_eq!(mul(Duration::ZERO, f64::INFINITY), Duration::ZERO);
+        assert_eq!(mul(second, f64::INFINITY), Duration::MAX);
+        assert_eq!(mul(second, 1e300), Duration::MAX);
+        assert_eq!(mul(Duration::MAX, 2.0), Duration::MAX);
+    }
+
+    #[test]
+    fn thin_and_cut_handle_out_of_range_percents() {
+        let bank = SampleBank::default();
+        let periods = || (0..100).map(|i| Period::from_millis(i * 10 + 5));
+        for percent in [0.0, -0.5, f64::NAN, f64::NEG_INFINITY] {
+            let thin = Waveform::Thin {
+                base: Box::new(Waveform::Sawtooth),
+                waveform_active_percent: percent,
+            };
+            let cut = Waveform::Cut {
+                base: Box::new(Waveform::Sawtooth),
+                waveform_active_percent: percent,
+            };
+            for period in periods() {
+                assert_eq!(thin.sample(&bank, period, 0.0), 0.0);
+                assert_eq!(cut.sample(&bank, period, 0.0), 0.0);
+            }
+        }
+        // above 1, thinning stretches the saw so it never reaches the end, and cutting leaves it whole
+        let thin = Waveform::Thin {
+            base: Box::new(Waveform::Sawtooth),
+            waveform_active_percent: 2.0,
+        };
+        let cut = Waveform::Cut {
+            base: Box::new(Waveform::Sawtooth),
+            waveform_active_percent: 1.5,
+        };
+        for period in periods() {
+            let phase = period.as_secs_f64();
+            let thinned = thin.sample(&bank, period, 0.0);
+            assert!((thinned - (phase / 2.0 * 2.0 - 1.0)).abs() < 1e-9);
+            assert!(thinned < 0.0);
+            let saw = Waveform::Sawtooth.sample(&bank, period, 0.0);
+            assert_eq!(cut.sample(&bank, period, 0.0), saw);
+        }
+    }
+
+    #[test]
+    fn gate_zeroes_off_steps() {
+        let mut carrier = Operator::builder()
+            .waveform(Waveform::Constant(1.0))
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::ZERO)
+                    .sustain(1.0)
+                    .build(),
+            )
+            .build();
+        carrier.play(440.0, 1.0);
+        let pattern = vec![true, false, true, true];
+        let mut gate = Gate::new(
+            Box::new(carrier),
+            pattern.clone(),
+            Duration::from_millis(10),
+        );
+        // 8 steps of 10ms, or two repeats of the pattern
+        let output = render(&mut gate, 48000, 3840);
+        for (i, value) in output.iter().enumerate() {
+            let expected = if pattern[i / 480 % 4] { 1.0 } else { 0.0 };
+            assert_eq!(*value, expected, "at {i}");
+        }
+    }
+
+    #[test]
+    fn offset_negates_and_shifts() {
+        let bank = SampleBank::default();
+        let offset = |gain, bias| Waveform::Offset {
+            base: Box::new(Waveform::Sine),
+            gain,
+            bias,
+        };
+        let (negated, constant) = (offset(-1.0, 0.0), offset(0.0, 0.5));
+        for i in 0..100 {
+            let period = Period::from_millis(i * 10 + 3);
+            let sine = Waveform::Sine.sample(&bank, period, 0.0);
+            assert_eq!(negated.sample(&bank, period, 0.0), -sine);
+            assert_eq!(constant.sample(&bank, period, 0.0), 0.5);
+        }
+    }
+
+    #[test]
+    fn breakpoint_envelope_interpolates_and_releases_mid_segment() {
+        let envelope = BreakpointEnvelope::new(
+            vec![
+                Breakpoint {
+                    time: Duration::from_millis(30),
+                    level: 0.2,
+                },
+                Breakpoint {
+                    time: Duration::from_millis(10),
+                    level: 1.0,
+                },
+                Breakpoint {
+                    time: Duration::from_millis(50),
+                    level: 0.6,
+                },
+            ],
+            Duration::from_millis(20),
+        );
+        let level = |millis, stop_point: Option<u64>| {
+            envelope
+                .sample_volume(
+                    Duration::from_millis(millis),
+                    stop_point.map(Duration::from_millis),
+                )
+                .unwrap()
+        };
+        for (millis, expected) in [
+            (5, 0.5),
+            (10, 1.0),
+            (20, 0.6),
+            (40, 0.4),
+            (50, 0.6),
+            (90, 0.6),
+        ] {
+            assert!(
+                (level(millis, None) - expected).abs() < 1e-9,
+                "at {millis}ms"
+            );
+        }
+        // released halfway between the first two points, at a level of 0.6
+        assert!((level(20, Some(20)) - 0.6).abs() < 1e-9);
+        assert!((level(30, Some(20)) - 0.3).abs() < 1e-9);
+        assert!((level(35, Some(20)) - 0.15).abs() < 1e-9);
+        assert_eq!(
+            envelope.sample_volume(Duration::from_millis(41), Some(Duration::from_millis(20))),
+            None
+        );
+    }
+
+    #[test]
+    fn different_seeds_start_out_of_phase() {
+        let start_phase = |seed| {
+            let mut operator = Operator::builder().waveform(Waveform::Sine).build();
+            operator.random_start_phase = true;
+            operator.start_phase_seed = seed;
+            operator.play(440.0, 1.0);
+            operator.sample(&SampleBank::default(), Duration::ZERO, 0.0);
+            operator.phase()
+        };
+        assert!((start_phase(1) - start_phase(2)).abs() > 1e-3);
+        assert_eq!(start_phase(1), start_phase(1));
+    }
+
+    #[test]
+    fn chorus_comb_filters_and_keeps_its_tail() {
+        // a fixed 1ms delay mixed in equally cancels 500Hz and its odd multiples, and reinforces 1kHz
+        let response = |frequency: f64| {
+            let mut chorus = Chorus::new(
+                Box::new(sine(frequency)),
+                Duration::from_millis(1),
+                Duration::ZERO,
+                0.0,
+                0.5,
+            );
+            let output = render(&mut chorus, 48000, 9600).split_off(4800);
+            amplitude_at(&output, frequency, 48000)
+        };
+        for (frequency, expected) in [
+            (250.0, std::f64::consts::FRAC_1_SQRT_2),
+            (500.0, 0.0),
+            (1000.0, 1.0),
+        ] {
+            assert!(
+                (response(frequency) - expected).abs() < 1e-3,
+                "at {frequency}Hz"
+            );
+        }
+
+        let mut operator = Operator::builder()
+            .waveform(Waveform::Constant(1.0))
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::ZERO)
+                    .sustain(1.0)
+                    .release(Duration::ZERO)
+                    .build(),
+            )
+            .build();
+        operator.play(440.0, 1.0);
+        let mut chorus = Chorus::new(
+            Box::new(operator),
+            Duration::from_millis(1),
+            Duration::ZERO,
+            0.0,
+            0.5,
+        );
+        let bank = SampleBank::default();
+        for i in 0..480 {
+            chorus.sample(&bank, time_at(i, 48000), 0.0);
+        }
+        chorus.release();
+        // the dry signal has ended, but the last millisecond of it is still coming out of the delay
+        for i in 480..528 {
+            assert_eq!(
+                chorus.sample(&bank, time_at(i, 48000), 0.0),
+                Some(0.5),
+                "at {i}"
+            );
+        }
+        assert!(chorus.is_active());
+        let end = (528..20000)
+            .position(|i| chorus.sample(&bank, time_at(i, 48000), 0.0).is_none())
+            .expect("the tail never ended")
+            + 528;
+        // the tail ends once the whole buffer has gone silent
+        assert_eq!(end, 480 + CHORUS_BUFFER_LENGTH - 1);
+        assert!(!chorus.is_active());
+    }
+
+    #[test]
+    fn stereo_pans_with_equal_power() {
+        use std::f64::consts::FRAC_1_SQRT_2;
+        let constant = || {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Constant(1.0))
+                .build();
+            operator.play(440.0, 1.0);
+            Box::new(operator)
+        };
+        let bank = SampleBank::default();
+        // mono synths are centred on both sides at full volume
+        assert_eq!(
+            constant().sample_as_stereo(&bank, Duration::ZERO, 0.0),
+            Some((1.0, 1.0))
+        );
+        for pan in [-1.0, -0.5, 0.0, 0.3, 1.0] {
+            // the same law as `Panned`, so a full-scale source never goes past full scale
+            assert_eq!(
+                Stereo::new(constant(), pan, 1.0).gains(),
+                Panned::new(constant(), pan).gains()
+            );
+            for width in [0.0, 1.0, 2.0] {
+                let mut stereo = Stereo::new(constant(), pan, width);
+                let (left, right) = stereo.sample_stereo(&bank, Duration::ZERO, 0.0).unwrap();
+                assert!((left * left + right * right - 1.0).abs() < 1e-12);
+                assert!(left <= 1.0 && right <= 1.0);
+            }
+        }
+        let centre = Stereo::new(constant(), 0.0, 1.0).gains();
+        assert!((centre.0 - FRAC_1_SQRT_2).abs() < 1e-12);
+        assert!((centre.1 - FRAC_1_SQRT_2).abs() < 1e-12);
+        let hard_right = Stereo::new(constant(), 1.0, 1.0).gains();
+        assert!(hard_right.0.abs() < 1e-12 && (hard_right.1 - 1.0).abs() < 1e-12);
+
+        // narrowing a hard-left signal to mono moves half of it to the right
+        let hard_left = || Box::new(Panned::new(constant(), -1.0));
+        let mut narrowed = Stereo::new(hard_left(), 0.0, 0.0);
+        let (left, right) = narrowed.sample_stereo(&bank, Duration::ZERO, 0.0).unwrap();
+        assert!((left - 0.5 * FRAC_1_SQRT_2).abs() < 1e-12);
+        assert!((right - 0.5 * FRAC_1_SQRT_2).abs() < 1e-12);
+        let mut unchanged = Stereo::new(hard_left(), -1.0, 1.0);
+        let (left, right) = unchanged.sample_stereo(&bank, Duration::ZERO, 0.0).unwrap();
+        assert!((left - 1.0).abs() < 1e-12 && right.abs() < 1e-12);
+    }
+
+    #[test]
+    fn velocity_curve_shapes_played_volume() {
+        let quadratic = OperatorModifiers {
+            velocity_curve: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(quadratic.effective_volume(0.0), 0.0);
+        assert!((quadratic.effective_volume(0.5) - 0.25).abs() < 1e-12);
+        assert!((quadratic.effective_volume(-0.5) + 0.25).abs() < 1e-12);
+        assert!((quadratic.effective_volume(1.0) - 1.0).abs() < 1e-12);
+
+        // zero-initialised modifiers from C must not play silent notes at full volume
+        for curve in [0.0, -1.0, f64::NAN] {
+            let modifiers = OperatorModifiers {
+                velocity_curve: curve,
+                ..Default::default()
+            };
+            assert_eq!(modifiers.effective_volume(0.0), 0.0);
+            assert_eq!(modifiers.effective_volume(0.5), 0.5);
+            assert_eq!(modifiers.effective_volume(-0.5), -0.5);
+        }
+    }
+
+    #[test]
+    fn nyquist_mute_silences_frequencies_above_nyquist() {
+        let mut muted = sine(30000.0);
+        muted.nyquist_mute = true;
+        let output = render(&mut muted, 44100, 4410);
+        // the sample rate is only known from the second sample onwards
+        assert!(output[1..].iter().all(|&x| x == 0.0));
+
+        // without the mute, the same note aliases down to 14.1kHz
+        let mut aliasing = sine(30000.0);
+        let output = render(&mut aliasing, 44100, 4410);
+        assert!(amplitude_at(&output, 14100.0, 44100) > 0.9);
+
+        // notes below nyquist are untouched
+        let mut audible = sine(1000.0);
+        audible.nyquist_mute = true;
+        let output = render(&mut audible, 44100, 4410);
+        assert!((amplitude_at(&output, 1000.0, 44100) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn looping_envelope_is_periodic_while_held() {
+        let envelope = Envelope::builder()
+            .attack(Duration::from_millis(10))
+            .decay_curve(DecayCurve::Linear {
+                time: Duration::from_millis(40),
+            })
+            .sustain(0.2)
+            .release(Duration::from_millis(20))
+            .looping(true)
+            .build();
+        let cycle = Duration::from_millis(50);
+        assert_eq!(envelope.loop_duration(), Some(cycle));
+        assert_eq!(envelope.sample_volume(Duration::ZERO, None), Some(0.0));
+        // later cycles rise from the sustain level, and repeat exactly
+        assert_eq!(envelope.sample_volume(cycle, None), Some(0.2));
+        for ms in (50..100).step_by(3) {
+            let time = Duration::from_millis(ms);
+            let volume = envelope.sample_volume(time, None).unwrap();
+            for later in 1..5 {
+                let repeated = envelope.sample_volume(time + cycle * later, None).unwrap();
+                assert!((volume - repeated).abs() < 1e-9);
+            }
+        }
+
+        // once released, the current cycle fades out without restarting
+        let stop_point = Some(Duration::from_millis(160));
+        let peak = envelope
+            .sample_volume(Duration::from_millis(160), stop_point)
+            .unwrap();
+        let mut previous = peak;
+        for ms in 161..180 {
+            let volume = envelope
+                .sample_volume(Duration::from_millis(ms), stop_point)
+                .unwrap();
+            assert!(volume <= previous);
+            previous = volume;
+        }
+        assert_eq!(
+            envelope.sample_volume(Duration::from_millis(181), stop_point),
+            None
+        );
+    }
+
+    #[test]
+    fn boxed_synths_downcast_to_their_concrete_type() {
+        let mut synth: Box<dyn Pom<SampleBank>> = Box::new(sine(440.0));
+        assert!(synth.as_any().downcast_ref::<Stacker>().is_none());
+        assert!(
+            synth
+                .as_any()
+                .downcast_ref::<Scheduled<SampleBank>>()
+                .is_none()
+        );
+        let operator = synth.as_any_mut().downcast_mut::<Operator>().unwrap();
+        operator.waveform = Waveform::Constant(0.5);
+        // changes made through the downcast reach the boxed synth
+        assert_eq!(
+            synth.sample(&SampleBank::default(), Duration::ZERO, 0.0),
+            Some(0.5)
+        );
+
+        let mut scheduled: Box<dyn Pom<SampleBank>> = Box::new(Scheduled::new(synth));
+        assert!(scheduled.as_any().downcast_ref::<Operator>().is_none());
+        let scheduled = scheduled
+            .as_any_mut()
+            .downcast_mut::<Scheduled<SampleBank>>()
+            .unwrap();
+        scheduled.schedule(Duration::from_secs(1), Event::Release);
+        assert_eq!(scheduled.events.len(), 1);
+        assert!(
+            scheduled
+                .inner
+                .as_any()
+                .downcast_ref::<Operator>()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn muted_operators_drop_out_of_sums_and_modulation() {
+        let constant = |level: f64, muted: bool| {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Constant(level))
+                .build();
+            operator.muted = muted;
+            operator.play(440.0, 1.0);
+            Box::new(operator) as Box<dyn Pom<SampleBank>>
+        };
+        let reference = render(&mut sine(440.0), 48000, 480);
+
+        let mut sum = Combinator::new(
+            vec![Box::new(sine(440.0)), constant(0.25, true)],
+            CombinatorType::Sum,
+        );
+        let output = render(&mut sum, 48000, 480);
+        assert!(
+            output
+                .iter()
+                .zip(&reference)
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        );
+
+        // a muted modulator leaves the carrier unmodulated
+        let mut modulated = Combinator::new(
+            vec![constant(0.25, true), Box::new(sine(440.0))],
+            CombinatorType::Modulate,
+        );
+        let output = render(&mut modulated, 48000, 480);
+        assert!(
+            output
+                .iter()
+                .zip(&reference)
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        );
+
+        // a muted carrier outputs the modulation itself
+        let mut carrier = sine(440.0);
+        carrier.muted = true;
+        let mut modulated = Combinator::new(
+            vec![constant(0.25, false), Box::new(carrier)],
+            CombinatorType::Modulate,
+        );
+        let output = render(&mut modulated, 48000, 480);
+        let modulation = render(constant(0.25, false).as_mut(), 48000, 480);
+        assert_eq!(output, modulation);
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        // a saturated sine only has odd harmonics, which fall off quickly enough to be fully captured at 4x
+        let saturated = || {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Saturate {
+                    base: Box::new(Waveform::Sine),
+                    drive: 4.0,
+                })
+                .build();
+            operator.play(5000.0, 1.0);
+            operator
+        };
+        // the 7th harmonic, at 35kHz, aliases down to 13kHz at 48kHz
+        let mut plain = saturated();
+        let output = render(&mut plain, 48000, 4848);
+        let plain_alias = amplitude_at(&output[48..], 13000.0, 48000);
+        let plain_fundamental = amplitude_at(&output[48..], 5000.0, 48000);
+
+        let mut oversampled = Oversampled::new(Box::new(saturated()), 4);
+        let output = render(&mut oversampled, 48000, 4848);
+        let oversampled_alias = amplitude_at(&output[48..], 13000.0, 48000);
+        let oversampled_fundamental = amplitude_at(&output[48..], 5000.0, 48000);
+
+        assert!(plain_alias > 0.05);
+        assert!(oversampled_alias < plain_alias / 1000.0);
+        assert!((oversampled_fundamental - plain_fundamental).abs() < 0.05);
+    }
+
+    #[test]
+    fn oversampling_keeps_its_filter_tail_until_cut() {
+        let operator = Operator::builder()
+            .waveform(Waveform::Constant(1.0))
+            .envelope(
+                Envelope::builder()
+                    .attack(Duration::ZERO)
+                    .sustain(1.0)
+                    .release(Duration::ZERO)
+                    .build(),
+            )
+            .build();
+        let mut oversampled = Oversampled::new(Box::new(operator), 4);
+        // there's no tail before anything has played
+        assert!(!oversampled.is_active());
+        oversampled.play(440.0, 1.0);
+        let bank = SampleBank::default();
+        for i in 0..480 {
+            oversampled.sample(&bank, time_at(i, 48000), 0.0);
+        }
+        oversampled.release();
+        // the filter holds 129 oversampled outputs, which take 33 samples to flush at 4x
+        assert!(
+            oversampled
+                .sample(&bank, time_at(480, 48000), 0.0)
+                .is_some()
+        );
+        assert!(oversampled.is_active());
+        let remaining = oversampled.remaining().unwrap();
+        assert!(remaining > Duration::ZERO && remaining <= time_at(33, 48000));
+        let end = (481..1000)
+            .position(|i| oversampled.sample(&bank, time_at(i, 48000), 0.0).is_none())
+            .expect("the tail never ended")
+            + 481;
+        assert_eq!(end, 480 + 32);
+        assert!(!oversampled.is_active());
+        assert_eq!(oversampled.remaining(), Some(Duration::ZERO));
+
+        // cutting ends the note immediately, tail and all
+        oversampled.play(440.0, 1.0);
+        for i in 1000..1480 {
+            oversampled.sample(&bank, time_at(i, 48000), 0.0);
+        }
+        oversampled.cut();
+        assert!(!oversampled.is_active());
+        assert_eq!(oversampled.remaining(), Some(Duration::ZERO));
+        assert_eq!(oversampled.sample(&bank, time_at(1480, 48000), 0.0), None);
+    }
+
+    #[test]
+    fn phaser_sweeps_notches_through_the_spectrum() {
+        // the amplitude of a 1kHz tone in 10ms windows, which falls as a notch passes over it
+        let window_amplitudes = |frequency: f64, depth: f64| {
+            let mut phaser =
+                Phaser::new(Box::new(sine(1000.0)), 2, frequency, depth, 1.0, 0.0, 0.5);
+            let output = render(&mut phaser, 48000, 48000);
+            output[480..]
+                .chunks(480)
+                .map(|window| amplitude_at(window, 1000.0, 48000))
+                .collect::<Vec<_>>()
+        };
+        // two stages notch out their corner frequency
+        assert!(window_amplitudes(1000.0, 0.0).iter().all(|&x| x < 1e-6));
+        let away = window_amplitudes(4000.0, 0.0);
+        assert!(away.iter().all(|&x| (x - away[0]).abs() < 1e-6 && x > 0.5));
+
+        // sweeping two octaves either side of the tone passes the notch over it
+        let swept = window_amplitudes(1000.0, 2.0);
+        let quietest = swept.iter().copied().fold(f64::INFINITY, f64::min);
+        let loudest = swept.iter().copied().fold(0.0, f64::max);
+        assert!(quietest < 0.1);
+        assert!(loudest > 0.8);
+    }
+
+    #[test]
+    fn sample_bank_merges_by_conflict_policy() {
+        let sample = |level: f64| Sample::new(vec![level], 48000.0, 1.0, 0.0, 0.0);
+        let bank = |entries: &[(SampleID, f64)]| SampleBank {
+            samples: entries
+                .iter()
+                .map(|&(id, level)| (id, sample(level)))
+                .collect(),
+            ..Default::default()
+        };
+        let destination = bank(&[(1, 1.0), (2, 1.0), (3, 1.0)]);
+        let source = bank(&[(3, 2.0), (4, 2.0), (2, 2.0)]);
+
+        let mut overwritten = destination.clone();
+        assert_eq!(
+            overwritten.merge(source.clone(), ConflictPolicy::Overwrite),
+            Ok(())
+        );
+        assert_eq!(overwritten, bank(&[(1, 1.0), (2, 2.0), (3, 2.0), (4, 2.0)]));
+
+        let mut skipped = destination.clone();
+        assert_eq!(skipped.merge(source.clone(), ConflictPolicy::Skip), Ok(()));
+        assert_eq!(skipped, bank(&[(1, 1.0), (2, 1.0), (3, 1.0), (4, 2.0)]));
+
+        // nothing is merged, and the lowest conflicting ID is reported
+        let mut rejected = destination.clone();
+        assert_eq!(
+            rejected.merge(source, ConflictPolicy::Error),
+            Err(SampleConflict { id: 2 })
+        );
+        assert_eq!(rejected, destination);
+        assert_eq!(
+            rejected.merge(bank(&[(5, 2.0)]), ConflictPolicy::Error),
+            Ok(())
+        );
+        assert_eq!(rejected.ids().count(), 4);
+    }
+
+    #[test]
+    fn phase_offset_smoothing_ramps_a_step_change() {
+        let saw = || {
+            let mut operator = Operator::builder().waveform(Waveform::Sawtooth).build();
+            operator.play(0.0, 1.0);
+            operator
+        };
+        let mut smoothed = saw();
+        smoothed.phase_offset_smoothing = Duration::from_millis(1);
+        // an unsmoothed operator fed the expected ramp directly
+        let mut reference = saw();
+        let bank = SampleBank::default();
+        let mut outputs = vec![];
+        for i in 0..10 {
+            let time = time_at(i, 48000);
+            let output = smoothed.sample(&bank, time, 0.0).unwrap();
+            assert_eq!(Some(output), reference.sample(&bank, time, 0.0));
+            outputs.push(output);
+        }
+        for i in 10..490 {
+            let time = time_at(i, 48000);
+            let elapsed = time.saturating_sub(time_at(9, 48000)).as_secs_f64();
+            let expected = 0.25 * (1.0 - (-elapsed / 0.001).exp());
+            let output = smoothed.sample(&bank, time, 0.25).unwrap();
+            let ramped = reference.sample(&bank, time, expected).unwrap();
+            assert!((output - ramped).abs() < 1e-9);
+            outputs.push(output);
+        }
+        // the step is spread over several samples, and settles after ten time constants
+        assert!(largest_step(&outputs) < 0.05);
+        let settled = reference.sample(&bank, time_at(490, 48000), 0.25).unwrap();
+        assert!((outputs[outputs.len() - 1] - settled).abs() < 1e-4);
+        assert!((settled - outputs[0]).abs() > 0.4);
+    }
+
+    #[test]
+    fn non_finite_notes_play_finite_output() {
+        let mut operator = Operator::builder()
+            .waveform(Waveform::Sine)
+            .modifiers(OperatorModifiers {
+                feedback: 0.5,
+                ..Default::default()
+            })
+            .filter(Filter {
+                cutoff_hz: 5000.0,
+                ..Filter::default()
+            })
+            .build();
+        let bank = SampleBank::default();
+        let mut index = 0;
+        let mut play = |operator: &mut Operator, length: usize| {
+            let output: Vec<f64> = (index..index + length)
+                .map(|i| {
+                    operator
+                        .sample(&bank, time_at(i, 48000), 0.0)
+                        .unwrap_or_default()
+                })
+                .collect();
+            index += length;
+            output
+        };
+        for (frequency, volume) in [
+            (f64::NAN, 1.0),
+            (440.0, f64::NAN),
+            (f64::INFINITY, 1.0),
+            (440.0, f64::NEG_INFINITY),
+            (f64::NEG_INFINITY, f64::INFINITY),
+        ] {
+            operator.play(frequency, volume);
+            assert!(play(&mut operator, 480).iter().all(|x| x.is_finite()));
+        }
+        operator.play(440.0, 1.0);
+        operator.set_frequency(f64::NAN);
+        assert!(play(&mut operator, 480).iter().all(|x| x.is_finite()));
+
+        // the state isn't poisoned, so a following valid note still sounds
+        operator.play(1000.0, 1.0);
+        let output = play(&mut operator, 4800);
+        assert!(output.iter().all(|x| x.is_finite()));
+        assert!(amplitude_at(&output, 1000.0, 48000) > 0.3);
+    }
+
+    #[test]
+    fn fractional_starts_attenuate_their_first_sample() {
+        // the energy of a constant note started `fraction` samples after the 10th sample at 1kHz
+        let onset_energy = |fraction: f64| {
+            let mut operator = Operator::builder()
+                .waveform(Waveform::Constant(1.0))
+                .envelope(Envelope::builder().sustain(1.0).build())
+                .build();
+            operator.play(440.0, 1.0);
+            operator
+                .set_start(Duration::from_millis(10) + Duration::from_secs_f64(fraction / 1000.0));
+            let output = render(&mut operator, 1000, 20);
+            (output[10], output.iter().map(|x| x * x).sum::<f64>())
+        };
+        // an aligned start plays its first sample at full level
+        let (first, aligned) = onset_energy(0.0);
+        assert_eq!(first, 1.0);
+        assert!((aligned - 10.0).abs() < 1e-9);
+
+        // a later start is heard from the next sample, attenuated by the part of the interval it covers
+        let mut previous = aligned;
+        for (fraction, expected) in [(0.25, 8.5625), (0.5, 8.25), (0.75, 8.0625)] {
+            let (first, energy) = onset_energy(fraction);
+            assert_eq!(first, 0.0);
+            assert!((energy - expected).abs() < 1e-9);
+            assert!(energy < previous);
+            previous = energy;
+        }
+        let (first, next_aligned) = onset_energy(1.0);
+        assert_eq!(first, 0.0);
+        assert!((next_aligned - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clamp_flattens_the_tops_of_waves() {
+        let bank = SampleBank::default();
+        let clamped = |min: f64, max: f64| Waveform::Clamp {
+            base: Box::new(Waveform::Sine),
+            min,
+            max,
+        };
+        let phases = (0..1000).map(|i| (i as f64 + 0.5) / 1000.0);
+        let clamp = clamped(-0.5, 0.5);
+        let output: Vec<f64> = phases
+            .clone()
+            .map(|phase| clamp.sample(&bank, Period::ZERO, phase))
+            .collect();
+        assert!(output.iter().all(|x| x.abs() <= 0.5));
+        // a sine is above half its peak for two thirds of each period
+        let flat = output.iter().filter(|x| x.abs() == 0.5).count();
+        assert!((flat as f64 / 1000.0 - 2.0 / 3.0).abs() < 0.01);
+        // and passes through unchanged below the bounds
+        for (phase, x) in phases.clone().zip(&output) {
+            let sine = Waveform::Sine.sample(&bank, Period::ZERO, phase);
+            if sine.abs() < 0.5 {
+                assert_eq!(*x, sine);
+            }
+        }
+
+        // reversed bounds are swapped, and NaN bounds are ignored
+        for phase in phases {
+            let sample = |waveform: Waveform| waveform.sample(&bank, Period::ZERO, phase);
+            assert_eq!(sample(clamped(0.5, -0.5)), sample(clamped(-0.5, 0.5)));
+            assert_eq!(
+                sample(clamped(f64::NAN, 0.5)),
+                sample(Waveform::Sine).min(0.5)
+            );
+            assert_eq!(
+                sample(clamped(-0.5, f64::NAN)),
+                sample(Waveform::Sine).max(-0.5)
+            );
+        }
+    }
+
+    #[test]
+    fn uses_absolute_time_searches_nested_waveforms() {
+        let wrappers: [fn(Waveform) -> Waveform; 10] = [
+            |base| Waveform::Thin {
+                base: Box::new(base),
+                waveform_active_percent: 0.5,
+            },
+            |base| Waveform::Cut {
+                base: Box::new(base),
+                waveform_active_percent: 0.5,
+            },
+            |base| Waveform::Absolute(Box::new(base)),
+            |base| Waveform::PhaseDistort {
+                base: Box::new(base),
+                amount: 0.5,
+                pivot: 0.5,
+            },
+            |base| Waveform::BandLimited {
+                base: Box::new(base),
+                max_harmonic: 8,
+            },
+            |base| Waveform::Saturate {
+                base: Box::new(base),
+                drive: 2.0,
+            },
+            |base| Waveform::Offset {
+                base: Box::new(base),
+                gain: -1.0,
+                bias: 0.0,
+            },
+            |base| Waveform::Clamp {
+                base: Box::new(base),
+                min: -0.5,
+                max: 0.5,
+            },
+            |base| Waveform::Mix {
+                a: Box::new(Waveform::Sine),
+                b: Box::new(base),
+                blend: 0.5,
+            },
+            |base| Waveform::Mix {
+                a: Box::new(base),
+                b: Box::new(Waveform::Triangle),
+                blend: 0.5,
+            },
+        ];
+        let leaves = [
+            (Waveform::PCM(0), true),
+            (
+                Waveform::Noise {
+                    seed: 1,
+                    pink: true,
+                },
+                true,
+            ),
+            (Waveform::Wavetable(0), false),
+            (Waveform::Sawtooth, false),
+        ];
+        for (leaf, absolute) in leaves {
+            assert_eq!(leaf.uses_absolute_time(), absolute);
+            for outer in wrappers {
+                for inner in wrappers {
+                    let nested = outer(inner(leaf.clone()));
+                    assert_eq!(nested.uses_absolute_time(), absolute, "{nested:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn select_if_picks_velocity_layers() {
+        let bank = SampleBank::default();
+        let time = Duration::from_millis(100);
+        // the hard layer is pushed first, then the soft layer, then the velocity
+        let layered = |velocity: f64| Stacker {
+            operators: vec![
+                Operator::builder()
+                    .waveform(Waveform::Constant(0.25))
+                    .build(),
+                Operator::builder()
+                    .waveform(Waveform::Constant(1.0))
+                    .build(),
+            ],
+            instructions: vec![
+                StackInstruction::Constant(0.0),
+                StackInstruction::Sample(1),
+                StackInstruction::Constant(0.0),
+                StackInstruction::Sample(0),
+                StackInstruction::Constant(velocity),
+                StackInstruction::SelectIf { threshold: 0.5 },
+            ],
+        };
+        // velocities at the threshold, and NaN velocities, select the soft layer
+        for (velocity, expected) in [
+            (0.0, 0.25),
+            (0.5, 0.25),
+            (0.51, 1.0),
+            (1.0, 1.0),
+            (f64::NAN, 0.25),
+        ] {
+            let mut stacker = layered(velocity);
+            let mut compiled = stacker.compile().unwrap();
+            stacker.play(440.0, 1.0);
+            compiled.play(440.0, 1.0);
+            assert_eq!(stacker.sample(&bank, time, 0.0), Some(expected));
+            assert_eq!(compiled.sample(&bank, time, 0.0), Some(expected));
+        }
+
+        // missing values are read as 0, so a lone selector above the threshold picks 0
+        let mut underflowing = Stacker {
+            operators: vec![],
+            instructions: vec![
+                StackInstruction::Constant(0.75),
+                StackInstruction::Constant(1.0),
+                StackInstruction::SelectIf { threshold: 0.5 },
+            ],
+        };
+        underflowing.play(440.0, 1.0);
+        assert_eq!(underflowing.sample(&bank, time, 0.0), Some(0.0));
+    }
+
+    #[test]
+    fn attack_curves_start_silent_and_reach_full_volume() {
+        let attack = |curve: Curve| {
+            Envelope::builder()
+                .attack(Duration::from_millis(10))
+                .attack_curve(curve)
+                .sustain(1.0)
+                .build()
+        };
+        let level = |curve: Curve, ms: f64| {
+            attack(curve)
+                .sample_volume(Duration::from_secs_f64(ms / 1000.0), None)
+                .unwrap()
+        };
+        for curve in [
+            Curve::Linear,
+            Curve::Smoothstep,
+            Curve::Exponential { exponent: 2.0 },
+            Curve::Exponential { exponent: 0.5 },
+        ] {
+            assert_eq!(level(curve, 0.0), 0.0);
+            assert!((level(curve, 10.0) - 1.0).abs() < 1e-12);
+        }
+        assert!((level(Curve::Exponential { exponent: 2.0 }, 5.0) - 0.25).abs() < 1e-12);
+
+        // zero, negative and NaN exponents are linear, instead of clicking in or starting at infinity
+        for exponent in [0.0, -1.0, f64::NAN] {
+            let curve = Curve::Exponential { exponent };
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(level(curve, 0.0), 0.0);
+            assert!((level(curve, 5.0) - 0.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn start_offset_skips_into_the_data_but_not_the_loop() {
+        let ramp: Vec<f64> = (0..100).map(|i| i as f64 / 100.0).collect();
+        // the middle of the `index`th PCM sample, so rounding can't land on its neighbour
+        let period_at = |index: u64| Period::from_micros(index * 10000 + 5000);
+        // where reading `index` samples into the data lands, looping [50, 80)
+        let looped = |index: u64| {
+            if index < 80 {
+                index
+            } else {
+                50 + (index - 50) % 30
+            }
+        };
+
+        let mut sample = Sample::new(ramp, 48000.0, 100.0, 0.0, 0.0);
+        sample.start_offset = Period::from_millis(200);
+        sample.loop_point = sample.duration();
+        for i in 0..80 {
+            assert_eq!(sample.get(period_at(i), 0.0), (20 + i) as f64 / 100.0);
+        }
+
+        // the loop point is measured from the start of the data, not from the offset
+        sample.loop_point = Period::from_millis(500);
+        sample.loop_duration = Period::from_millis(300);
+        assert!(sample.loop_fits());
+        assert_eq!(sample.get(period_at(0), 0.0), 0.2);
+        for i in 0..200 {
+            assert_eq!(sample.get(period_at(i), 0.0), looped(20 + i) as f64 / 100.0);
+        }
+
+        // an offset inside the loop starts partway through it, then wraps back to the loop point
+        sample.start_offset = Period::from_millis(600);
+        assert_eq!(sample.get(period_at(0), 0.0), 0.6);
+        assert_eq!(sample.get(period_at(20), 0.0), 0.5);
+        for i in 0..200 {
+            assert_eq!(sample.get(period_at(i), 0.0), looped(60 + i) as f64 / 100.0);
+        }
+
+        // reversed, the offset and the loop region are both measured from the end of the data
+        sample.reverse = true;
+        sample.start_offset = Period::from_millis(200);
+        assert_eq!(sample.get(period_at(0), 0.0), 0.79);
+        for i in 0..200 {
+            assert_eq!(
+                sample.get(period_at(i), 0.0),
+                (99 - looped(20 + i)) as f64 / 100.0
+            );
+        }
     }
 }